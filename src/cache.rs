@@ -0,0 +1,133 @@
+use std::cell::Cell;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::log::verbose;
+use crate::style::{bold, style_stderr};
+
+/// Files that persist between runs, like usage history.
+///
+/// The directory is only created once something is written to it.
+/// If a write fails, any further writes are skipped for the rest of the run.
+#[derive(Debug, Clone, Default)]
+pub struct Cache {
+    dir: Option<PathBuf>,
+    failed: Cell<bool>,
+}
+
+impl Cache {
+    /// Create a cache in `dir`, or a cache that is never read or written if `dir` is `None`.
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir,
+            failed: Cell::new(false),
+        }
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// Read the cache file `name`, returning `None` if it doesn't exist or can't be read.
+    pub fn read(&self, name: &str) -> Option<String> {
+        let path = self.dir.as_ref()?.join(name);
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => Some(contents),
+            Err(err) => {
+                if err.kind() != ErrorKind::NotFound {
+                    verbose!("unable to read cache file `{}`: {err}", path.display());
+                }
+                None
+            }
+        }
+    }
+
+    /// Replace the contents of the cache file `name`, creating the cache directory if needed.
+    ///
+    /// Only the first failure is returned as an error; after that, writes are silently skipped.
+    /// This lets callers warn about a broken cache once, then carry on without it.
+    ///
+    /// ```
+    /// use dmm::cache::Cache;
+    ///
+    /// // Nothing can be created inside a file, even with the permissions to do so.
+    /// let file = std::env::temp_dir().join(format!("dmm-cache-write-doc-{}", std::process::id()));
+    /// std::fs::write(&file, "").unwrap();
+    /// let cache = Cache::new(Some(file.join("dmm")));
+    ///
+    /// assert!(cache.write("usage.toml", "").is_err());
+    /// assert!(cache.write("usage.toml", "").is_ok());
+    /// assert!(cache.write("labels.toml", "").is_ok());
+    /// assert!(cache.read("usage.toml").is_none());
+    /// std::fs::remove_file(file).unwrap();
+    /// ```
+    pub fn write(&self, name: &str, contents: &str) -> anyhow::Result<()> {
+        let Some(dir) = &self.dir else {
+            return Ok(());
+        };
+        if self.failed.get() {
+            return Ok(());
+        }
+
+        let result = write_atomic(dir, name, contents);
+        if result.is_err() {
+            self.failed.set(true);
+        }
+        result
+            .context("unable to save state between runs; it won't be saved until dmm is run again")
+    }
+
+    /// Delete the cache file `name`, returning whether it existed.
+    ///
+    /// ```
+    /// use dmm::cache::Cache;
+    ///
+    /// let dir = std::env::temp_dir().join(format!("dmm-cache-remove-doc-{}", std::process::id()));
+    /// let cache = Cache::new(Some(dir.clone()));
+    /// cache.write("usage.toml", "").unwrap();
+    /// cache.write("labels.toml", "").unwrap();
+    ///
+    /// assert!(cache.remove("labels.toml").unwrap());
+    /// assert!(!cache.remove("labels.toml").unwrap());
+    /// assert!(cache.read("labels.toml").is_none());
+    /// assert!(cache.read("usage.toml").is_some());
+    /// std::fs::remove_dir_all(dir).unwrap();
+    /// ```
+    pub fn remove(&self, name: &str) -> anyhow::Result<bool> {
+        let Some(dir) = &self.dir else {
+            return Ok(false);
+        };
+
+        let path = dir.join(name);
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err).context(format!(
+                "unable to remove cache file `{}`",
+                style_stderr!(bold(), "{}", path.display())
+            )),
+        }
+    }
+}
+
+/// Write to a temporary file, then move it over `dir/name`,
+/// so a failed write never leaves a truncated cache file behind.
+fn write_atomic(dir: &Path, name: &str, contents: &str) -> anyhow::Result<()> {
+    fs::create_dir_all(dir).context(format!(
+        "unable to create cache directory `{}`",
+        style_stderr!(bold(), "{}", dir.display())
+    ))?;
+
+    let path = dir.join(name);
+    let temp = dir.join(format!(".{name}.tmp"));
+    fs::write(&temp, contents)
+        .and_then(|()| fs::rename(&temp, &path))
+        .context(format!(
+            "unable to write cache file `{}`",
+            style_stderr!(bold(), "{}", path.display())
+        ))
+}
@@ -0,0 +1,79 @@
+use std::ffi::{CStr, CString};
+
+/// Orders names by the collation rules of the user's locale, from `LC_ALL`, `LC_COLLATE` or `LANG`.
+///
+/// Unlike comparing bytes, collation places accented letters next to their plain forms,
+/// so `Über` sorts between `tar` and `vim` rather than after `zsh`.
+#[derive(Debug)]
+pub struct Collator(());
+
+impl Collator {
+    /// Set up collation for the user's locale.
+    ///
+    /// Returns `None` if the locale is `C` or `POSIX`, which compare bytes rather than letters,
+    /// or if it isn't installed, in which case there's no collation data to use.
+    ///
+    /// # Safety
+    ///
+    /// This sets the `LC_COLLATE` category of the process's locale, which isn't thread safe:
+    /// no other thread may be using or changing the locale while it's called,
+    /// such as by formatting numbers in C code or calling `setlocale` itself.
+    pub unsafe fn new() -> Option<Self> {
+        // SAFETY: The locale name is a valid C string, and `setlocale` returns either null
+        // or a valid C string, which is copied before the locale can change again.
+        // The caller ensures no other threads are using the locale.
+        let locale = unsafe {
+            let locale = libc::setlocale(libc::LC_COLLATE, c"".as_ptr());
+            if locale.is_null() {
+                return None;
+            }
+            CStr::from_ptr(locale).to_string_lossy().into_owned()
+        };
+
+        let language = locale.split(['.', '@']).next().unwrap_or_default();
+        if language == "C" || language == "POSIX" {
+            None
+        } else {
+            Some(Self(()))
+        }
+    }
+
+    /// Transform `name` into a key whose byte order is the collation order of names.
+    ///
+    /// ```
+    /// use dmm::collate::Collator;
+    ///
+    /// // Collation order differs between locales; in Swedish, `Å` sorts after `z`.
+    /// // So the locale is pinned, and the test is skipped if it isn't installed.
+    /// std::env::set_var("LC_ALL", "en_US.UTF-8");
+    /// // SAFETY: Nothing else in this test uses the locale.
+    /// if let Some(collator) = unsafe { Collator::new() } {
+    ///     let mut names = ["zsh", "Über", "vim", "Ångström", "apt", "tar", "ёлка", "дом"];
+    ///     names.sort_by_cached_key(|name| collator.sort_key(name));
+    ///     assert_eq!(names[..6], ["Ångström", "apt", "tar", "Über", "vim", "zsh"]);
+    ///     assert_eq!(names[6..], ["дом", "ёлка"]);
+    /// }
+    /// ```
+    pub fn sort_key(&self, name: &str) -> Vec<u8> {
+        // Names can't contain a nul in a C string; anything after one is compared by bytes.
+        let (name, rest) = name.split_once('\0').unwrap_or((name, ""));
+        let name = CString::new(name).expect("unreachable");
+
+        let mut key = Vec::<u8>::new();
+        loop {
+            // SAFETY: `name` is a valid C string, and `strxfrm` writes at most `key.len()`
+            // bytes into `key`, including the terminating nul.
+            let len = unsafe { libc::strxfrm(key.as_mut_ptr().cast(), name.as_ptr(), key.len()) };
+            if len < key.len() {
+                key.truncate(len);
+                break;
+            }
+            key.resize(len + 1, 0);
+        }
+
+        // Anything after a nul is compared by bytes, after the part before it.
+        key.push(0);
+        key.extend_from_slice(rest.as_bytes());
+        key
+    }
+}
@@ -1,18 +1,28 @@
 use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::ffi::OsString;
 use std::fmt::{Display, Write};
 use std::io::{ErrorKind, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+use std::time::Duration;
 use std::{env, fmt, fs, io, panic, process};
 
-use ahash::HashSet;
+use ahash::{HashMap, HashSet};
 use anyhow::{anyhow, Context};
-use clap::{command, crate_description, Arg, ArgMatches};
+use clap::{command, crate_description, Arg, ArgAction, ArgMatches, ColorChoice};
 use directories::{BaseDirs, ProjectDirs};
 use is_terminal::IsTerminal;
 use toml::{map::Map, Value};
 
+use crate::cache::Cache;
+use crate::dotenv;
+use crate::fold::nfc;
 use crate::imstr::ImStr;
-use crate::style::{bold, style_stderr, style_stdout};
+use crate::log::{self, verbose};
+use crate::style::{bold, env_color_override, style_stderr, style_stdout};
+use crate::tag::contains_tag_chars;
 
 const SHORT_EXAMPLE: &str = r#"    # A short example config; see `--help` for more info.
     [menu]
@@ -28,39 +38,80 @@ const SHORT_EXAMPLE: &str = r#"    # A short example config; see `--help` for mo
     dmenu.prompt = "example:"
 "#;
 const LONG_EXAMPLE: &str = include_str!("../EXAMPLE.toml");
+/// File names checked for the home config, in order; only the first that exists is read.
+const HOME_CONFIG_NAMES: [&str; 3] = ["config.toml", "dmm.toml", ".dmmrc"];
 
 pub fn get() -> anyhow::Result<Config> {
-    let dirs = ProjectDirs::from("", "", "dmm")
-        .context("no valid home directory could be detected")
-        .context("could not access config or cache directories")?;
-    let base_dirs = BaseDirs::new().expect("unreachable");
-    let args = parse_args(&dirs);
-
-    let config = if let Some(path) = args.get_one::<String>("PATTERN") {
-        fs::read_to_string(path).context(format!(
-            "unable to read config file `{}`",
-            style_stderr!(bold(), "{path}")
-        ))?
+    let dirs = ProjectDirs::from("", "", "dmm");
+    let base_dirs = BaseDirs::new();
+    let args = parse_args(dirs.as_ref());
+    log::set_verbose(args.get_flag("verbose"));
+    log::set_json(
+        args.get_one::<String>("error-format")
+            .is_some_and(|format| format == "json"),
+    );
+
+    if args.get_flag("home-config") {
+        let dirs = dirs.as_ref().ok_or_else(no_home_error)?;
+        println!("{}", dirs.config_dir().display());
+        process::exit(0);
+    }
+
+    if args.get_flag("init") {
+        let dirs = dirs.as_ref().ok_or_else(no_home_error)?;
+        let path = dirs.config_dir().join("config.toml");
+        init_config(&path, args.get_flag("force"))?;
+        println!("{}", path.display());
+        process::exit(0);
+    }
+
+    if args.get_flag("edit") {
+        let path = match args.get_one::<String>("PATTERN") {
+            Some(path) => PathBuf::from(path),
+            None => home_config_path(dirs.as_ref().ok_or_else(no_home_error)?.config_dir()),
+        };
+        edit_config(&path, args, dirs, base_dirs)?;
+        process::exit(0);
+    }
+
+    let config = if let Some(paths) = args.get_many::<String>("PATTERN") {
+        let mut merged = Value::Table(Map::new());
+        for path in paths {
+            verbose!("reading pattern `{path}`");
+            let config = fs::read_to_string(path).context(format!(
+                "unable to read config file `{}`",
+                style_stderr!(bold(), "{path}")
+            ))?;
+            let config = parse_toml(&config).context(format!(
+                "found incorrect formatting in pattern `{}`",
+                style_stderr!(bold(), "{path}")
+            ))?;
+            merge_values(&mut merged, config);
+        }
+        merged
+    } else if args.get_flag("export-history")
+        || args.contains_id("import-history")
+        || args.contains_id("clear-cache")
+        || args.get_flag("paths")
+    {
+        // Only the home config is needed to find the cache, and stdin may hold the import.
+        Value::Table(Map::new())
     } else {
-        let mut buf = String::new();
-        io::stdin()
-            .read_to_string(&mut buf)
-            .context("unable to read piped input")?;
-        buf
+        verbose!("reading pattern from stdin");
+        parse_toml(&read_stdin()?).context("found incorrect formatting in target config")?
     };
-    let config = config
-        .parse::<Value>()
-        .context("found incorrect formatting in target config")?;
 
-    let home_config = read_home_config(dirs.config_dir())?;
-    let home_config = home_config.map(|config| {
-        config.parse::<Value>().context(format!(
+    let home_config = match &dirs {
+        Some(dirs) => read_home_config(dirs.config_dir())?,
+        None => {
+            verbose!("no home directory could be detected, so no home config is read");
+            None
+        }
+    };
+    let home_config = home_config.map(|(config, path)| {
+        parse_toml(&config).context(format!(
             "found incorrect formatting in home config `{}`",
-            style_stderr!(
-                bold(),
-                "{}",
-                dirs.config_dir().join("config.toml").display()
-            )
+            style_stderr!(bold(), "{}", path.display())
         ))
     });
     let home_config = if let Some(home_config) = home_config {
@@ -69,16 +120,271 @@ pub fn get() -> anyhow::Result<Config> {
         None
     };
 
+    if args.get_flag("paths") {
+        let project_dirs = dirs.as_ref().ok_or_else(no_home_error)?;
+        let config_path = home_config_path(project_dirs.config_dir());
+        let cache_dir = try_get_config::<CacheDir>(&config, home_config.as_ref(), &config_path)?
+            .resolve(dirs.as_ref(), base_dirs.as_ref())?;
+        let paths = home_paths(project_dirs.config_dir(), cache_dir.as_deref());
+        println!("{}", serde_json::to_string_pretty(&paths)?);
+        process::exit(0);
+    }
+
     Config::try_new(&config, home_config.as_ref(), args, dirs, base_dirs)
 }
 
-fn read_home_config(dirs: &Path) -> anyhow::Result<Option<String>> {
-    let config_path = dirs.join("config.toml");
+/// Describe where dmm keeps its files, as output by `--paths`.
+///
+/// `config_file` is the home config that's read from `config_dir`,
+/// or the one `--init` would write if none exists yet.
+///
+/// ```
+/// use dmm::config::home_paths;
+///
+/// let config_dir = std::env::temp_dir().join("dmm-home-paths-doc");
+/// let paths = home_paths(&config_dir, None);
+/// let paths = serde_json::from_str::<serde_json::Value>(&paths.to_string()).unwrap();
+///
+/// assert_eq!(paths["config_file"], config_dir.join("config.toml").to_str().unwrap());
+/// assert_eq!(paths["exists"], false);
+/// assert!(paths["cache_dir"].is_null());
+/// ```
+pub fn home_paths(config_dir: &Path, cache_dir: Option<&Path>) -> serde_json::Value {
+    let config_file = home_config_path(config_dir);
+    serde_json::json!({
+        "config_dir": config_dir.to_string_lossy(),
+        "config_file": config_file.to_string_lossy(),
+        "exists": config_file.exists(),
+        "cache_dir": cache_dir.map(Path::to_string_lossy),
+    })
+}
+
+/// Write the short example config to `path`, creating any missing parent directories.
+fn init_config(path: &Path, force: bool) -> anyhow::Result<()> {
+    if path.exists() && !force {
+        return Err(anyhow!(
+            "the config file `{}` already exists; pass `{}` to overwrite it",
+            style_stderr!(bold(), "{}", path.display()),
+            style_stderr!(bold(), "--force"),
+        ));
+    }
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context(format!(
+            "unable to create directory `{}`",
+            style_stderr!(bold(), "{}", parent.display())
+        ))?;
+    }
+    fs::write(path, starter_config()).context(format!(
+        "unable to write config file `{}`",
+        style_stderr!(bold(), "{}", path.display())
+    ))
+}
+
+/// Open a config in the user's editor, creating it from a template if it doesn't exist.
+///
+/// Once the editor exits, the config is checked for problems.
+fn edit_config(
+    path: &Path,
+    args: ArgMatches,
+    dirs: Option<ProjectDirs>,
+    base_dirs: Option<BaseDirs>,
+) -> anyhow::Result<()> {
+    if !path.exists() {
+        init_config(path, false)?;
+    }
+
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    let mut editor_args = editor.split_whitespace();
+    let editor_name = editor_args.next().unwrap_or("vi");
+
+    let status = Command::new(editor_name)
+        .args(editor_args)
+        .arg(path)
+        .status()
+        .context(format!(
+            "failed to run editor `{}` (is it installed?)",
+            style_stderr!(bold(), "{editor_name}")
+        ))?;
+    if !status.success() {
+        return Err(anyhow!(
+            "editor `{}` exited unsuccessfully",
+            style_stderr!(bold(), "{editor_name}")
+        ));
+    }
+
+    let config = fs::read_to_string(path).context(format!(
+        "unable to read config file `{}`",
+        style_stderr!(bold(), "{}", path.display())
+    ))?;
+    let config = parse_toml(&config).context(format!(
+        "found incorrect formatting in edited config `{}`",
+        style_stderr!(bold(), "{}", path.display())
+    ))?;
+    Config::try_new(&config, None, args, dirs, base_dirs).context(format!(
+        "found a problem with edited config `{}`",
+        style_stderr!(bold(), "{}", path.display())
+    ))?;
+
+    Ok(())
+}
+
+/// Parse `source` as toml, showing the line containing any syntax error.
+fn parse_toml(source: &str) -> anyhow::Result<Value> {
+    source.parse::<Value>().map_err(|err| {
+        let Some((line, column)) = err.line_col() else {
+            return anyhow!(err);
+        };
+        let snippet = source.lines().nth(line).unwrap_or_default();
+
+        anyhow!(
+            "{err}\n    {} | {snippet}\n    {:width$} | {:column$}{}",
+            line + 1,
+            "",
+            "",
+            style_stderr!(bold(), "^"),
+            width = (line + 1).to_string().len(),
+        )
+    })
+}
+
+/// Expand a leading `~/` to the home directory, and `$VAR` or `${VAR}` to the value of `VAR`.
+///
+/// A `$` that isn't followed by a variable name is left as is.
+pub fn expand_path(path: &str, base_dirs: Option<&BaseDirs>) -> anyhow::Result<PathBuf> {
+    let (mut expanded, rest) = match path.strip_prefix("~/") {
+        Some(rest) => {
+            let base_dirs = base_dirs.ok_or_else(no_home_error)?;
+            (base_dirs.home_dir().join(""), rest)
+        }
+        None => (PathBuf::new(), path),
+    };
+
+    expanded.push(expand_vars(rest)?);
+    Ok(expanded)
+}
+
+/// Expand `$VAR` or `${VAR}` in `text` to the value of `VAR`.
+///
+/// A `$` that isn't followed by a variable name is left as is.
+pub fn expand_vars(text: &str) -> anyhow::Result<OsString> {
+    let mut buf = OsString::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c != '$' {
+            buf.push(c.encode_utf8(&mut [0; 4]));
+            continue;
+        }
+
+        let braced = chars.next_if(|&(_, c)| c == '{').is_some();
+        let start = chars.peek().map_or(text.len(), |&(i, _)| i);
+        let mut end = start;
+        while let Some((i, c)) = chars.next_if(|&(_, c)| c.is_ascii_alphanumeric() || c == '_') {
+            end = i + c.len_utf8();
+        }
+        if braced && chars.next_if(|&(_, c)| c == '}').is_none() {
+            return Err(anyhow!(
+                "`{}` is missing a closing `}}`",
+                style_stderr!(bold(), "{}", &text[i..])
+            ));
+        }
+
+        let name = &text[start..end];
+        if name.is_empty() {
+            let consumed = chars.peek().map_or(text.len(), |&(i, _)| i);
+            buf.push(&text[i..consumed]);
+            continue;
+        }
+        let value = env::var_os(name).ok_or_else(|| {
+            anyhow!(
+                "the environment variable `{}` isn't set",
+                style_stderr!(bold(), "{name}")
+            )
+        })?;
+        buf.push(value);
+    }
+
+    Ok(buf)
+}
+
+/// Merge `other` into `base`, with the values in `other` taking priority.
+///
+/// Tables are merged recursively, while any other value in `other` replaces the one in `base`.
+fn merge_values(base: &mut Value, other: Value) {
+    match (base, other) {
+        (Value::Table(base), Value::Table(other)) => {
+            for (key, value) in other {
+                match base.get_mut(&key) {
+                    Some(base) => merge_values(base, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, other) => *base = other,
+    }
+}
+
+/// Read a pattern piped through stdin.
+pub fn read_stdin() -> anyhow::Result<String> {
+    let mut buf = Vec::new();
+    io::stdin()
+        .lock()
+        .read_to_end(&mut buf)
+        .context("unable to read piped input")?;
+
+    String::from_utf8(buf).map_err(|err| {
+        anyhow!(
+            "piped config isn't valid utf-8; found an invalid byte at position {}",
+            style_stderr!(bold(), "{}", err.utf8_error().valid_up_to())
+        )
+    })
+}
+
+/// The short example config, without the indentation used to display it in `--help`.
+fn starter_config() -> String {
+    SHORT_EXAMPLE
+        .lines()
+        .map(|line| line.strip_prefix("    ").unwrap_or(line))
+        .fold(String::new(), |mut config, line| {
+            config.push_str(line);
+            config.push('\n');
+            config
+        })
+}
+
+/// The home config files that exist in `dirs`, in the order of [`HOME_CONFIG_NAMES`].
+fn home_config_candidates(dirs: &Path) -> Vec<PathBuf> {
+    HOME_CONFIG_NAMES
+        .iter()
+        .map(|name| dirs.join(name))
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// The path of the home config in `dirs`: the first that exists, or else `config.toml`.
+fn home_config_path(dirs: &Path) -> PathBuf {
+    home_config_candidates(dirs)
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| dirs.join(HOME_CONFIG_NAMES[0]))
+}
+
+/// Read the home config in `dirs`, returning its contents and path if it exists.
+fn read_home_config(dirs: &Path) -> anyhow::Result<Option<(String, PathBuf)>> {
+    let config_path = home_config_path(dirs);
     let result = fs::read_to_string(&config_path);
     match result {
-        Ok(config) => Ok(Some(config)),
+        Ok(config) => {
+            verbose!("reading home config `{}`", config_path.display());
+            Ok(Some((config, config_path)))
+        }
         Err(err) => {
             if err.kind() == ErrorKind::NotFound {
+                verbose!("no home config exists at `{}`", config_path.display());
                 Ok(None)
             } else {
                 Err(err).context(format!(
@@ -90,34 +396,309 @@ fn read_home_config(dirs: &Path) -> anyhow::Result<Option<String>> {
     }
 }
 
-fn parse_args(dirs: &ProjectDirs) -> ArgMatches {
-    let args = command!()
+/// Quote `arg` so a posix shell will interpret it as a single literal word.
+pub fn shell_quote(arg: &str) -> Cow<'_, str> {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "_-+=@%:,./".contains(c);
+
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        Cow::from(arg)
+    } else {
+        Cow::from(format!("'{}'", arg.replace('\'', r"'\''")))
+    }
+}
+
+/// The error for operations that need a home directory when none could be detected.
+fn no_home_error() -> anyhow::Error {
+    anyhow!("the home config and `~/` in paths can't be used without one")
+        .context("no home directory could be detected")
+}
+
+fn parse_args(dirs: Option<&ProjectDirs>) -> ArgMatches {
+    let args = cli(dirs);
+    let args = if io::stdin().is_terminal() {
+        args.arg_required_else_help(true)
+    } else {
+        args
+    };
+
+    args.get_matches()
+}
+
+/// dmm's command line interface, with the home config directory from `dirs` in its help.
+pub fn cli(dirs: Option<&ProjectDirs>) -> clap::Command {
+    let config_dir = dirs.map_or_else(
+        || String::from("<home config directory>"),
+        |dirs| dirs.config_dir().display().to_string(),
+    );
+
+    // Help and usage errors follow `NO_COLOR` and `FORCE_COLOR` like the rest of the output.
+    let color = match env_color_override() {
+        Some(true) => ColorChoice::Always,
+        Some(false) => ColorChoice::Never,
+        None => ColorChoice::Auto,
+    };
+    command!()
+        .color(color)
         .about(concat!(crate_description!(), ".\n"))
         .long_about(format!(
             concat!(
                 crate_description!(),
                 ".\n",
                 "The toml config may be piped in instead of specifying a file path.\n",
-                "A config may be written at `{}/config.toml`.\n",
+                "A config may be written at `{}/config.toml`, or `dmm.toml` or `.dmmrc`.\n",
                 "This will define default options that are overridden by the main pattern."
             ),
-            dirs.config_dir().display()
+            config_dir
         ))
         .arg(
             Arg::new("home-config")
                 .help("Output the directory that will be checked for config files")
-                .long("home-config-path"),
+                .long("home-config-path")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("paths")
+                .help("Output where dmm's config and cache are as json")
+                .long_help(
+                    "Output where dmm's config and cache are as a json object, then exit.\n\
+                     It has the `config_dir` checked for config files, the `config_file` that's read \
+                     or would be, whether that file `exists`, and the `cache_dir`, \
+                     which is null if the cache is disabled.",
+                )
+                .long("paths")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("init")
+                .help("Write a short example config to the home config file")
+                .long_help(
+                    "Write a short example config to the home config file, then output its path.\n\
+                     An existing home config is never overwritten unless `--force` is given.",
+                )
+                .long("init")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("force")
+                .help("Allow `--init` to overwrite an existing home config")
+                .long("force")
+                .requires("init")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("which")
+                .help("Output the command that would be run for the named entry")
+                .long_help(
+                    "Output the command that would be run for the named entry, and where it's from.\n\
+                     Exits unsuccessfully if there is no such entry.",
+                )
+                .long("which")
+                .value_name("NAME"),
+        )
+        .arg(
+            Arg::new("list")
+                .help("Output the entries the menu would show, with their indices")
+                .long_help(
+                    "Output the entries the menu would show, one per line, each after its index\n\
+                     and a tab, in the order the menu shows them. `--filter` applies.\n\
+                     The indices are the ones `--run-index` takes.",
+                )
+                .long("list")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["which", "run-index", "output", "no-run"]),
+        )
+        .arg(
+            Arg::new("run-index")
+                .help("Run the entry at INDEX in the menu, without opening a launcher")
+                .long_help(
+                    "Run the entry at INDEX in the menu, counting from 0, without opening a launcher.\n\
+                     The menu is ordered and filtered the same way as with `--list`,\n\
+                     which shows each entry's index. Entries that need confirmation still ask for it.",
+                )
+                .long("run-index")
+                .value_name("INDEX")
+                .value_parser(clap::value_parser!(usize))
+                .conflicts_with("which"),
+        )
+        .arg(
+            Arg::new("dump-config")
+                .help("Output the final config, after merging the pattern and home config")
+                .long("dump-config")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("export-history")
+                .help("Output the saved usage and custom command history as json")
+                .long_help(
+                    "Output the saved usage and custom command history as json.\n\
+                     The output can be read by `--import-history`, such as on another machine.\n\
+                     No pattern is needed, so stdin is ignored unless a pattern is given.",
+                )
+                .long("export-history")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("import-history"),
+        )
+        .arg(
+            Arg::new("import-history")
+                .help("Merge usage and history exported by `--export-history` into the cache")
+                .long_help(
+                    "Merge usage and history exported by `--export-history` into the cache.\n\
+                     Run counts are summed, and the latest time each entry was run is kept.\n\
+                     If FILE is `-`, the json is read from stdin.",
+                )
+                .long("import-history")
+                .value_name("FILE"),
+        )
+        .arg(
+            Arg::new("clear-cache")
+                .help("Delete WHAT from the cache, then output what was removed")
+                .long_help(
+                    "Delete WHAT from the cache, then output what was removed.\n\
+                     `frequency` is how often each entry was run, `history` the custom commands run,\n\
+                     `labels` the last output of label commands, and `path` the descriptions of \
+                     programs found in `config.path`. Without WHAT, or with `all`, everything is deleted.\n\
+                     WHAT must follow an equals sign, like `--clear-cache=history`.\n\
+                     No pattern is needed, so stdin is ignored unless a pattern is given.",
+                )
+                .long("clear-cache")
+                .value_name("WHAT")
+                .value_parser(["frequency", "history", "labels", "path", "all"])
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("all")
+                .conflicts_with_all(["export-history", "import-history"]),
+        )
+        .arg(
+            Arg::new("output")
+                .help("Output the selection in FORMAT instead of running it")
+                .long_help(
+                    "Output the selection in FORMAT instead of running it.\n\
+                     With `json`, each selection is an object with its `name`, its `command`,\n\
+                     which is a string for shell commands or an array for bare commands,\n\
+                     the `source` it came from, and whether it's a `custom` command.\n\
+                     Several selections are output as an array of objects.",
+                )
+                .long("output")
+                .value_name("FORMAT")
+                .value_parser(["json"])
+                .conflicts_with_all(["dump-config", "export-history", "import-history", "which"]),
+        )
+        .arg(
+            Arg::new("filter")
+                .help("Only show entries whose names fuzzy-match QUERY")
+                .long_help(
+                    "Only show entries whose names fuzzy-match QUERY, best matches first.\n\
+                     A name matches if it contains the characters of QUERY in order, ignoring case.\n\
+                     This keeps huge menus fast when the query is already known.",
+                )
+                .long("filter")
+                .value_name("QUERY")
+                .env("DMM_FILTER"),
+        )
+        .arg(
+            Arg::new("query")
+                .help("Prefill the launcher's search box with QUERY")
+                .long_help(
+                    "Prefill the launcher's search box with QUERY; overrides `config.dmenu.query`.\n\
+                     Only rofi and fuzzel can do this; dmenu ignores it with a warning.",
+                )
+                .long("query")
+                .value_name("QUERY"),
+        )
+        .arg(
+            Arg::new("no-run")
+                .help("Ask on the terminal before running each selected command")
+                .long_help(
+                    "Show the command each selection resolved to, and ask on the terminal\n\
+                     whether to run it. Only `y` or `yes` runs it.\n\
+                     Without a terminal to ask on, the commands are output and none are run.",
+                )
+                .long("no-run")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all([
+                    "dump-config",
+                    "export-history",
+                    "import-history",
+                    "which",
+                    "output",
+                ]),
+        )
+        .arg(
+            Arg::new("error-format")
+                .help("Output errors and warnings to stderr in FORMAT")
+                .long_help(
+                    "Output errors, warnings and diagnostics to stderr in FORMAT.\n\
+                     With `json`, each is a line holding an object with its `level`,\n\
+                     its `message`, and the `chain` of causes below it.\n\
+                     With `human`, the default, they're styled for reading.",
+                )
+                .long("error-format")
+                .value_name("FORMAT")
+                .value_parser(["human", "json"])
+                .default_value("human"),
+        )
+        .arg(
+            Arg::new("verbose")
+                .help("Output diagnostics about what dmm is doing to stderr")
+                .long_help(
+                    "Output diagnostics about what dmm is doing to stderr.\n\
+                     This includes which configs were read, which directories were searched,\n\
+                     how many entries came from each source, and every command that was run.",
+                )
+                .short('v')
+                .long("verbose")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("debug-menu")
+                .help("Output the exact dmenu command and the start of its input to stderr")
+                .long_help(
+                    "Output the exact dmenu command and the start of its input to stderr.\n\
+                     The command is quoted so it can be copied into a shell to reproduce it.\n\
+                     dmenu is still run as normal afterwards.",
+                )
+                .long("debug-menu")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("time")
+                .help("Output how long each part of the run took to stderr")
+                .long_help(
+                    "Output how long each part of the run took to stderr before exiting,\n\
+                     like building the entries and running the selected commands.\n\
+                     Time spent waiting in dmenu is reported separately from dmm's own overhead.",
+                )
+                .long("time")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("edit")
+                .help("Open the home config, or the pattern if given, in your editor")
+                .long_help(
+                    "Open the home config, or the pattern if given, in your editor.\n\
+                     The editor is chosen from `$VISUAL` or `$EDITOR`, defaulting to `vi`.\n\
+                     If the file doesn't exist, it is created with a short example pattern.\n\
+                     Once the editor exits, the config is checked for problems.",
+                )
+                .long("edit")
+                .action(ArgAction::SetTrue),
         )
         .arg({
             Arg::new("PATTERN")
-                .help("Path to a pattern file")
+                .help("Paths to pattern files")
                 .long_help(
-                    "Path to a pattern file.\n\
+                    "Paths to pattern files.\n\
                      Either this must be specified, or the pattern must be piped in.\n\
-                     If specified, anything piped through stdin is ignored.",
+                     If specified, anything piped through stdin is ignored.\n\
+                     Multiple patterns are merged, with later patterns overriding earlier ones.\n\
+                     With `--edit`, only the first pattern is opened.",
                 )
                 .index(1)
+                .num_args(1..)
+                .action(ArgAction::Append)
         })
+        .next_help_heading("Dmenu Options")
+        .args(Dmenu::args_from_cli())
         .after_help(format!(
             "{}\n{}",
             style_stdout!(bold().set_underline(true), "Example Pattern:"),
@@ -127,24 +708,10 @@ fn parse_args(dirs: &ProjectDirs) -> ArgMatches {
             "{}\n{}",
             style_stdout!(bold().set_underline(true), "Example Pattern:"),
             LONG_EXAMPLE
-        ));
-    let args = if io::stdin().is_terminal() {
-        args.arg_required_else_help(true)
-    } else {
-        args
-    };
-
-    let args = args.get_matches();
-
-    if args.contains_id("home-config") {
-        println!("{}", dirs.config_dir().display());
-        process::exit(0);
-    }
-
-    args
+        ))
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Run {
     Shell(ImStr),
     Bare(Vec<ImStr>),
@@ -154,6 +721,23 @@ impl Run {
     pub fn binary(run: ImStr) -> Self {
         Self::Bare(vec![run])
     }
+
+    /// The program a bare command runs, or `None` for a shell command.
+    pub fn program(&self) -> Option<&ImStr> {
+        match self {
+            Self::Bare(run) => run.first(),
+            Self::Shell(_) => None,
+        }
+    }
+}
+
+impl From<&Run> for Value {
+    fn from(run: &Run) -> Self {
+        match run {
+            Run::Shell(command) => Self::String(command.to_string()),
+            Run::Bare(command) => strings_to_value(command),
+        }
+    }
 }
 
 impl Display for Run {
@@ -174,72 +758,132 @@ impl Display for Run {
     }
 }
 
+/// Where a menu entry was defined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Pattern,
+    Home,
+    Path,
+    /// Typed into dmenu rather than selected from the menu.
+    Custom,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pattern => write!(f, "pattern"),
+            Self::Home => write!(f, "home config"),
+            Self::Path => write!(f, "path"),
+            Self::Custom => write!(f, "custom command"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Entry {
-    Full { name: ImStr, run: Run, group: i64 },
-    Name(ImStr),
+    Full {
+        name: ImStr,
+        run: Run,
+        group: i64,
+        probe: Option<Run>,
+        /// A command whose output is displayed in place of the name.
+        label: Option<Run>,
+        when: When,
+        options: Box<RunOptions>,
+        source: Source,
+    },
+    Name {
+        name: ImStr,
+        source: Source,
+    },
     Filter(ImStr),
 }
 
 impl Entry {
-    fn try_new(name: ImStr, entry: &Value) -> anyhow::Result<Self> {
+    fn try_new(
+        name: ImStr,
+        entry: &Value,
+        groups: &HashMap<ImStr, i64>,
+        source: Source,
+    ) -> anyhow::Result<Self> {
         match entry {
-            Value::Boolean(true) => Ok(Self::Name(name)),
+            Value::Boolean(true) => Ok(Self::Name { name, source }),
             Value::Boolean(false) => Ok(Self::Filter(name)),
-            Value::String(run) => Ok(Self::Full {
+            Value::String(_) | Value::Array(_) => Ok(Self::Full {
+                run: try_into_run(&format!("menu.{name}"))(entry)?,
                 name,
-                run: Run::Shell(ImStr::from(run)),
                 group: 0,
+                probe: None,
+                label: None,
+                when: When::default(),
+                options: Box::default(),
+                source,
             }),
-            Value::Array(run) => {
-                let run = run
-                    .iter()
-                    .map(try_into_array_string(&format!("menu.{name}")))
-                    .collect::<Result<Vec<ImStr>, _>>()?;
-
-                Ok(Self::Full {
-                    name,
-                    run: Run::Bare(run),
-                    group: 0,
-                })
-            }
             Value::Table(table) => {
                 let group = table
                     .get("group")
-                    .map(try_into_integer(&format!("menu.{name}.group")))
+                    .map(try_into_group(&format!("menu.{name}.group"), groups))
                     .transpose()?
                     .unwrap_or(0);
 
+                let probe = table
+                    .get("if")
+                    .map(try_into_run(&format!("menu.{name}.if")))
+                    .transpose()?;
+
+                let label = table
+                    .get("label")
+                    .map(try_into_run(&format!("menu.{name}.label")))
+                    .transpose()?;
+
+                let when = table
+                    .get("when")
+                    .map(|when| When::try_new(&format!("menu.{name}.when"), when))
+                    .transpose()?
+                    .unwrap_or_default();
+
+                let options = Box::new(RunOptions::try_new(&format!("menu.{name}"), table)?);
+
                 let missing_run_error = format!(
                     "`{}` must have a value if `{}` is a table",
                     style_stderr!(bold(), "menu.{name}.run"),
                     style_stderr!(bold(), "menu.{name}"),
                 );
 
+                // A boolean `run` stands in for the whole entry, so nothing else would be used.
+                if let Some(Value::Boolean(run)) = table.get("run") {
+                    let ignored = table
+                        .keys()
+                        .filter(|key| *key != "run")
+                        .map(|key| format!("`{}`", style_stderr!(bold(), "menu.{name}.{key}")))
+                        .collect::<Vec<String>>();
+                    if !ignored.is_empty() {
+                        return Err(anyhow!(
+                            "`{}` can't be combined with {}; set `{}` to the command instead",
+                            style_stderr!(bold(), "menu.{name}.run = {run}"),
+                            ignored.join(", "),
+                            style_stderr!(bold(), "menu.{name}.run"),
+                        ));
+                    }
+                }
+
                 table
                     .get("run")
                     .map(|value| match value {
-                        Value::Boolean(true) => Ok(Self::Name(name)),
+                        Value::Boolean(true) => Ok(Self::Name { name, source }),
                         Value::Boolean(false) => Ok(Self::Filter(name)),
-                        Value::String(run) => Ok(Self::Full {
+                        Value::String(_) | Value::Array(_) => Ok(Self::Full {
+                            run: try_into_run(&format!("menu.{name}.run"))(value)?,
                             name,
-                            run: Run::Shell(ImStr::from(run)),
                             group,
+                            probe,
+                            label,
+                            when,
+                            options,
+                            source,
                         }),
-                        Value::Array(run) => {
-                            let run = run
-                                .iter()
-                                .map(try_into_array_string(&format!("menu.{name}.run")))
-                                .collect::<Result<Vec<ImStr>, _>>()?;
-
-                            Ok(Self::Full {
-                                name,
-                                run: Run::Bare(run),
-                                group,
-                            })
-                        }
                         other => type_error(
-                            "menu.{name}.run",
+                            &format!("menu.{name}.run"),
                             &["string", "array", "boolean"],
                             other.type_str(),
                         ),
@@ -248,7 +892,7 @@ impl Entry {
                     .context(missing_run_error)
             }
             other => type_error(
-                "menu.{name}",
+                &format!("menu.{name}"),
                 &["string", "array", "boolean", "table"],
                 other.type_str(),
             ),
@@ -257,45 +901,701 @@ impl Entry {
 
     pub fn name(&self) -> ImStr {
         match self {
-            Self::Full { name, .. } | Self::Name(name) | Self::Filter(name) => name.clone(),
+            Self::Full { name, .. } | Self::Name { name, .. } | Self::Filter(name) => name.clone(),
         }
     }
-}
-
-#[derive(Debug, Clone)]
-pub enum Shell {
-    Disabled,
-    Enabled { shell: Vec<ImStr>, piped: bool },
-}
 
-impl Shell {
-    pub const fn is_enabled(&self) -> bool {
+    fn to_value(&self) -> Value {
         match self {
-            Self::Disabled => false,
-            Self::Enabled { .. } => true,
+            Self::Full {
+                run,
+                group,
+                probe,
+                label,
+                when,
+                options,
+                ..
+            } => {
+                let mut entry = Map::from_iter([
+                    (String::from("run"), Value::from(run)),
+                    (String::from("group"), Value::Integer(*group)),
+                ]);
+                entry.extend(options.to_map());
+                if let Some(probe) = probe {
+                    entry.insert(String::from("if"), Value::from(probe));
+                }
+                if let Some(label) = label {
+                    entry.insert(String::from("label"), Value::from(label));
+                }
+                if !when.env.is_empty() || when.host.is_some() {
+                    entry.insert(String::from("when"), when.to_value());
+                }
+                Value::Table(entry)
+            }
+            Self::Name { .. } => Value::Boolean(true),
+            Self::Filter(_) => Value::Boolean(false),
         }
     }
-}
 
-impl ConfigItem for Shell {
-    fn name() -> &'static str {
-        "shell"
-    }
-    fn merge(self, _: Self) -> Self {
-        self
+    pub const fn label(&self) -> Option<&Run> {
+        match self {
+            Self::Full { label, .. } => label.as_ref(),
+            Self::Name { .. } | Self::Filter(_) => None,
+        }
     }
-}
 
-impl Default for Shell {
-    fn default() -> Self {
-        Self::Enabled {
-            shell: vec![ImStr::new("sh"), ImStr::new("-c")],
-            piped: false,
+    pub const fn probe(&self) -> Option<&Run> {
+        match self {
+            Self::Full { probe, .. } => probe.as_ref(),
+            Self::Name { .. } | Self::Filter(_) => None,
         }
     }
-}
 
-impl TryFrom<&Value> for Shell {
+    pub fn when(&self) -> &When {
+        const ALWAYS: &When = &When {
+            env: Vec::new(),
+            host: None,
+        };
+
+        match self {
+            Self::Full { when, .. } => when,
+            Self::Name { .. } | Self::Filter(_) => ALWAYS,
+        }
+    }
+}
+
+/// Options that change how the command of an entry is run.
+#[derive(Debug, Default, Clone)]
+pub struct RunOptions {
+    /// How long to wait for the command before killing it; overrides `config.timeout`.
+    pub timeout: Option<Duration>,
+    /// Whether to wait for the command to finish; overrides `config.wait`.
+    /// If unset, a `timeout` of the entry's own means it's waited on.
+    pub wait: Option<bool>,
+    /// Ask for confirmation in a second menu before running the command.
+    pub confirm: Option<Confirm>,
+    /// The niceness to run the command with; clamped to the valid range when it's run.
+    pub nice: Option<i64>,
+    /// A file to append the command's output to; `~/` and `$VAR` are expanded when it's run.
+    pub log: Option<ImStr>,
+    /// Run the command with a pseudo-terminal as its stdio; overrides `config.pty`.
+    pub pty: Option<bool>,
+    /// Run the command in a shell even if `config.shell` is disabled.
+    pub allow_shell: Option<AllowShell>,
+    /// Whether running the entry is recorded in the history that sorts entries; tracked if unset.
+    pub track: Option<bool>,
+    /// Text written to the command's stdin; see [`RunOptions::expand_stdin`].
+    pub stdin: Option<ImStr>,
+}
+
+impl RunOptions {
+    fn try_new(name: &str, entry: &Map<String, Value>) -> anyhow::Result<Self> {
+        let timeout = entry
+            .get("timeout")
+            .map(try_into_duration(&format!("{name}.timeout")))
+            .transpose()?;
+
+        let wait = entry
+            .get("wait")
+            .map(try_into_boolean(&format!("{name}.wait")))
+            .transpose()?;
+
+        let confirm = entry
+            .get("confirm")
+            .map(|confirm| Confirm::try_new(&format!("{name}.confirm"), confirm))
+            .transpose()?
+            .flatten();
+
+        let nice = entry
+            .get("nice")
+            .map(try_into_integer(&format!("{name}.nice")))
+            .transpose()?;
+
+        let log = entry
+            .get("log")
+            .map(try_into_string(&format!("{name}.log")))
+            .transpose()?;
+
+        let pty = entry
+            .get("pty")
+            .map(try_into_boolean(&format!("{name}.pty")))
+            .transpose()?;
+
+        let allow_shell = entry
+            .get("allow-shell")
+            .map(|allow_shell| AllowShell::try_new(&format!("{name}.allow-shell"), allow_shell))
+            .transpose()?
+            .flatten();
+
+        let track = entry
+            .get("track")
+            .map(try_into_boolean(&format!("{name}.track")))
+            .transpose()?;
+
+        let stdin = entry
+            .get("stdin")
+            .map(try_into_string(&format!("{name}.stdin")))
+            .transpose()?;
+
+        Ok(Self {
+            timeout,
+            wait,
+            confirm,
+            nice,
+            log,
+            pty,
+            allow_shell,
+            track,
+            stdin,
+        })
+    }
+
+    /// The text to write to the stdin of the entry `name`, with `{name}` replaced by its name,
+    /// and `$VAR` or `${VAR}` by the value of `VAR`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if a variable isn't set, or its value isn't utf-8.
+    ///
+    /// ```
+    /// use dmm::config::RunOptions;
+    /// use dmm::imstr::ImStr;
+    ///
+    /// std::env::set_var("DMM_STDIN_DOC", "https://example.com");
+    /// let options = RunOptions {
+    ///     stdin: Some(ImStr::new("{name}: $DMM_STDIN_DOC")),
+    ///     ..RunOptions::default()
+    /// };
+    ///
+    /// assert_eq!(
+    ///     options.expand_stdin("qr").unwrap().as_deref(),
+    ///     Some("qr: https://example.com")
+    /// );
+    /// assert!(RunOptions::default().expand_stdin("qr").unwrap().is_none());
+    /// ```
+    pub fn expand_stdin(&self, name: &str) -> anyhow::Result<Option<String>> {
+        let Some(stdin) = &self.stdin else {
+            return Ok(None);
+        };
+        let expanded = expand_vars(&stdin.replace("{name}", name))?;
+        expanded.into_string().map(Some).map_err(|_| {
+            anyhow!(
+                "`{}` isn't utf-8 once its variables are expanded",
+                style_stderr!(bold(), "menu.{name}.stdin")
+            )
+        })
+    }
+
+    fn to_map(&self) -> Map<String, Value> {
+        let mut options = Map::new();
+        if let Some(timeout) = self.timeout {
+            options.insert(String::from("timeout"), Value::Float(timeout.as_secs_f64()));
+        }
+        if let Some(wait) = self.wait {
+            options.insert(String::from("wait"), Value::Boolean(wait));
+        }
+        if let Some(confirm) = &self.confirm {
+            options.insert(String::from("confirm"), confirm.to_value());
+        }
+        if let Some(nice) = self.nice {
+            options.insert(String::from("nice"), Value::Integer(nice));
+        }
+        if let Some(log) = &self.log {
+            options.insert(String::from("log"), Value::from(log.as_str()));
+        }
+        if let Some(pty) = self.pty {
+            options.insert(String::from("pty"), Value::Boolean(pty));
+        }
+        if let Some(allow_shell) = &self.allow_shell {
+            options.insert(String::from("allow-shell"), allow_shell.to_value());
+        }
+        if let Some(track) = self.track {
+            options.insert(String::from("track"), Value::Boolean(track));
+        }
+        if let Some(stdin) = &self.stdin {
+            options.insert(String::from("stdin"), Value::from(stdin.as_str()));
+        }
+        options
+    }
+}
+
+/// The shell an entry runs its command with, even if `config.shell` is disabled.
+///
+/// This only trusts the one entry: other shell commands are still refused.
+#[derive(Debug, Clone)]
+pub enum AllowShell {
+    /// `config.shell`, or `sh -c` if it's disabled.
+    Default,
+    /// A shell program and its arguments, like `["bash", "-c"]`.
+    Custom(Vec<ImStr>),
+}
+
+impl AllowShell {
+    /// Parse `true`, `false` or an array of strings; `false` is `None`.
+    fn try_new(name: &str, allow_shell: &Value) -> anyhow::Result<Option<Self>> {
+        match allow_shell {
+            Value::Boolean(allow_shell) => Ok(allow_shell.then_some(Self::Default)),
+            Value::Array(shell) => {
+                let shell = shell
+                    .iter()
+                    .map(try_into_array_string(name))
+                    .collect::<Result<Vec<ImStr>, _>>()?;
+                no_shell_program(name, &shell)?;
+                Ok(Some(Self::Custom(shell)))
+            }
+            other => type_error(name, &["boolean", "array"], other.type_str()),
+        }
+    }
+
+    /// The shell to run the entry with, given `config.shell`.
+    ///
+    /// ```
+    /// use dmm::config::{AllowShell, Shell};
+    /// use dmm::imstr::ImStr;
+    ///
+    /// let program = |shell: &Shell| match shell {
+    ///     Shell::Enabled { shell, .. } => shell.iter().map(ImStr::to_string).collect::<Vec<_>>(),
+    ///     Shell::Disabled => Vec::new(),
+    /// };
+    ///
+    /// assert_eq!(program(&AllowShell::Default.shell(&Shell::Disabled)), ["sh", "-c"]);
+    ///
+    /// let bash = AllowShell::Custom(vec![ImStr::from("bash"), ImStr::from("-c")]);
+    /// assert_eq!(program(&bash.shell(&Shell::Disabled)), ["bash", "-c"]);
+    /// ```
+    pub fn shell<'a>(&self, shell: &'a Shell) -> Cow<'a, Shell> {
+        match self {
+            Self::Default if shell.is_enabled() => Cow::Borrowed(shell),
+            Self::Default => Cow::Owned(Shell::default()),
+            Self::Custom(custom) => Cow::Owned(Shell::Enabled {
+                shell: custom.clone(),
+                piped: false,
+                login: false,
+                append_args: Vec::new(),
+                terminator: PipedTerminator::Eof,
+                forward_output: false,
+            }),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match self {
+            Self::Default => Value::Boolean(true),
+            Self::Custom(shell) => strings_to_value(shell),
+        }
+    }
+}
+
+/// A second menu that must be answered before an entry is run.
+#[derive(Debug, Clone)]
+pub struct Confirm {
+    /// The prompt of the menu; defaults to `Run <name>?`.
+    pub prompt: Option<ImStr>,
+    /// What must be typed into the menu to run the entry.
+    pub accept: ImStr,
+}
+
+impl Default for Confirm {
+    fn default() -> Self {
+        Self {
+            prompt: None,
+            accept: ImStr::new("yes"),
+        }
+    }
+}
+
+impl Confirm {
+    /// Parse `true`, `false` or a table with `prompt` and `accept`; `false` is `None`.
+    fn try_new(name: &str, confirm: &Value) -> anyhow::Result<Option<Self>> {
+        match confirm {
+            Value::Boolean(confirm) => Ok(confirm.then(Self::default)),
+            Value::Table(table) => {
+                let prompt = table
+                    .get("prompt")
+                    .map(try_into_string(&format!("{name}.prompt")))
+                    .transpose()?;
+                let accept = table
+                    .get("accept")
+                    .map(try_into_string(&format!("{name}.accept")))
+                    .transpose()?
+                    .unwrap_or_else(|| Self::default().accept);
+
+                if accept.trim().is_empty() {
+                    return Err(anyhow!(
+                        "`{}` can't be empty",
+                        style_stderr!(bold(), "{name}.accept")
+                    ));
+                }
+
+                Ok(Some(Self { prompt, accept }))
+            }
+            other => type_error(name, &["boolean", "table"], other.type_str()),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        if self.prompt.is_none() && self.accept == Self::default().accept {
+            return Value::Boolean(true);
+        }
+
+        let mut confirm = Map::from_iter([(
+            String::from("accept"),
+            Value::String(self.accept.to_string()),
+        )]);
+        if let Some(prompt) = &self.prompt {
+            confirm.insert(String::from("prompt"), Value::String(prompt.to_string()));
+        }
+        Value::Table(confirm)
+    }
+
+    /// The prompt to show when confirming the entry `name`.
+    ///
+    /// ```
+    /// use dmm::config::Confirm;
+    /// use dmm::imstr::ImStr;
+    ///
+    /// // `confirm = true`
+    /// assert_eq!(Confirm::default().prompt("shutdown"), "Run shutdown?");
+    ///
+    /// // `confirm = { prompt = "Really shut down?" }`
+    /// let custom = Confirm { prompt: Some(ImStr::new("Really shut down?")), ..Confirm::default() };
+    /// assert_eq!(custom.prompt("shutdown"), "Really shut down?");
+    /// ```
+    pub fn prompt(&self, name: &str) -> String {
+        self.prompt
+            .as_ref()
+            .map_or_else(|| format!("Run {name}?"), ImStr::to_string)
+    }
+
+    /// Whether `choice`, what was selected or typed into the menu, accepts running the entry.
+    ///
+    /// ```
+    /// use dmm::config::Confirm;
+    /// use dmm::imstr::ImStr;
+    ///
+    /// // `confirm = true`
+    /// let confirm = Confirm::default();
+    /// assert!(confirm.accepts("yes\n"));
+    /// assert!(!confirm.accepts("no"));
+    /// assert!(!confirm.accepts(""));
+    ///
+    /// // `confirm = { accept = "shutdown" }`
+    /// let confirm = Confirm { accept: ImStr::new("shutdown"), ..Confirm::default() };
+    /// assert!(confirm.accepts("shutdown"));
+    /// assert!(!confirm.accepts("yes"));
+    /// ```
+    pub fn accepts(&self, choice: &str) -> bool {
+        choice.trim() == self.accept.trim()
+    }
+}
+
+/// Cheap conditions that must all be met for an entry to be displayed.
+#[derive(Debug, Default, Clone)]
+pub struct When {
+    /// Environment variables that must be set.
+    pub env: Vec<ImStr>,
+    /// The hostname the machine must have.
+    pub host: Option<ImStr>,
+}
+
+impl When {
+    fn try_new(name: &str, when: &Value) -> anyhow::Result<Self> {
+        let when = try_into_table(name)(when)?;
+
+        let env_name = format!("{name}.env");
+        let env = when
+            .get("env")
+            .map(|env| match env {
+                Value::String(var) => Ok(vec![ImStr::from(var)]),
+                Value::Array(vars) => vars
+                    .iter()
+                    .map(try_into_array_string(&env_name))
+                    .collect::<Result<Vec<ImStr>, _>>(),
+                other => type_error(&env_name, &["string", "array"], other.type_str()),
+            })
+            .transpose()?
+            .unwrap_or_default();
+
+        let host = when
+            .get("host")
+            .map(try_into_string(&format!("{name}.host")))
+            .transpose()?;
+
+        Ok(Self { env, host })
+    }
+
+    fn to_value(&self) -> Value {
+        let mut when = Map::from_iter([(String::from("env"), strings_to_value(&self.env))]);
+        if let Some(host) = &self.host {
+            when.insert(String::from("host"), Value::String(host.to_string()));
+        }
+        Value::Table(when)
+    }
+
+    /// Check whether every condition is met.
+    ///
+    /// ```
+    /// use dmm::config::When;
+    /// use dmm::imstr::ImStr;
+    ///
+    /// std::env::set_var("DMM_WHEN_EXAMPLE", "1");
+    /// let set = When { env: vec![ImStr::new("DMM_WHEN_EXAMPLE")], host: None };
+    /// let unset = When { env: vec![ImStr::new("DMM_WHEN_UNSET")], host: None };
+    ///
+    /// assert!(set.is_met());
+    /// assert!(!unset.is_met());
+    /// assert!(When::default().is_met());
+    /// ```
+    pub fn is_met(&self) -> bool {
+        let env_is_set = self
+            .env
+            .iter()
+            .all(|var| env::var_os(var.as_str()).is_some());
+        let host_matches = || {
+            self.host
+                .as_ref()
+                .is_none_or(|host| hostname().as_deref() == Some(host.as_str()))
+        };
+
+        env_is_set && host_matches()
+    }
+}
+
+fn hostname() -> Option<String> {
+    // Hostnames are at most 255 bytes, so this always has room for the terminating nul.
+    let mut host = [0_u8; 256];
+    // SAFETY: `gethostname` writes at most `host.len()` bytes into `host`.
+    if unsafe { libc::gethostname(host.as_mut_ptr().cast(), host.len()) } == -1 {
+        return None;
+    }
+    let len = host
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(host.len());
+
+    Some(String::from_utf8_lossy(&host[..len]).into_owned()).filter(|host| !host.is_empty())
+}
+
+#[derive(Debug, Clone)]
+pub enum Shell {
+    Disabled,
+    Enabled {
+        shell: Vec<ImStr>,
+        piped: bool,
+        /// Run the shell as a login shell, by passing `-l` right after its name.
+        login: bool,
+        /// Arguments passed after the command, which the shell sees as `$0`, `$1`, and so on.
+        append_args: Vec<ImStr>,
+        /// What's written after the command to a piped shell's stdin, before it's closed.
+        terminator: PipedTerminator,
+        /// Let a piped shell write to dmm's stdout and stderr, rather than discarding its output.
+        forward_output: bool,
+    },
+}
+
+impl Shell {
+    pub const fn is_enabled(&self) -> bool {
+        match self {
+            Self::Disabled => false,
+            Self::Enabled { .. } => true,
+        }
+    }
+
+    /// The arguments to run shell command `run` with, starting with the shell program,
+    /// or `None` if the shell is disabled.
+    ///
+    /// A piped shell reads `run` from stdin, so it isn't one of the arguments.
+    ///
+    /// ```
+    /// use dmm::config::{PipedTerminator, Shell};
+    /// use dmm::imstr::ImStr;
+    ///
+    /// let shell = Shell::Enabled {
+    ///     shell: vec![ImStr::new("sh"), ImStr::new("-c")],
+    ///     piped: false,
+    ///     login: true,
+    ///     append_args: vec![ImStr::new("dmm"), ImStr::new("first")],
+    ///     terminator: PipedTerminator::Eof,
+    ///     forward_output: false,
+    /// };
+    /// assert_eq!(
+    ///     shell.argv("echo $0 $1").unwrap(),
+    ///     ["sh", "-l", "-c", "echo $0 $1", "dmm", "first"],
+    /// );
+    ///
+    /// let piped = Shell::Enabled {
+    ///     shell: vec![ImStr::new("sh"), ImStr::new("-s")],
+    ///     piped: true,
+    ///     login: false,
+    ///     append_args: vec![ImStr::new("first")],
+    ///     terminator: PipedTerminator::Eof,
+    ///     forward_output: false,
+    /// };
+    /// assert_eq!(piped.argv("echo $1").unwrap(), ["sh", "-s", "first"]);
+    ///
+    /// assert_eq!(Shell::Disabled.argv("true"), None);
+    /// ```
+    pub fn argv<'a>(&'a self, run: &'a str) -> Option<Vec<&'a str>> {
+        let Self::Enabled {
+            shell,
+            piped,
+            login,
+            append_args,
+            ..
+        } = self
+        else {
+            return None;
+        };
+        let (program, args) = shell.split_first()?;
+
+        let mut argv = vec![program.as_str()];
+        // `-l` rather than a `-` before argv[0], which wouldn't survive wrappers
+        // like `systemd-run`; sh, bash, zsh and fish all accept it.
+        if *login {
+            argv.push("-l");
+        }
+        argv.extend(args.iter().map(ImStr::as_str));
+        if !piped {
+            argv.push(run);
+        }
+        argv.extend(append_args.iter().map(ImStr::as_str));
+        Some(argv)
+    }
+
+    /// What to write to the stdin of a piped shell to run shell command `run`,
+    /// or `None` if the shell isn't piped.
+    ///
+    /// ```
+    /// use dmm::config::{PipedTerminator, Shell};
+    /// use dmm::imstr::ImStr;
+    ///
+    /// let piped = |terminator| Shell::Enabled {
+    ///     shell: vec![ImStr::new("sh")],
+    ///     piped: true,
+    ///     login: false,
+    ///     append_args: Vec::new(),
+    ///     terminator,
+    ///     forward_output: false,
+    /// };
+    ///
+    /// assert_eq!(piped(PipedTerminator::Eof).input("echo hi").unwrap(), "echo hi");
+    /// assert_eq!(piped(PipedTerminator::Newline).input("echo hi").unwrap(), "echo hi\n");
+    /// assert_eq!(piped(PipedTerminator::Exit).input("echo hi").unwrap(), "echo hi\nexit\n");
+    ///
+    /// assert_eq!(Shell::default().input("echo hi"), None);
+    /// ```
+    pub fn input(&self, run: &str) -> Option<String> {
+        match self {
+            Self::Enabled {
+                piped: true,
+                terminator,
+                ..
+            } => Some(format!("{run}{}", terminator.as_str())),
+            _ => None,
+        }
+    }
+}
+
+/// What's written to a piped shell's stdin after the command, before it's closed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PipedTerminator {
+    /// Nothing; closing stdin ends the input.
+    #[default]
+    Eof,
+    /// A newline, for shells that only run complete lines.
+    Newline,
+    /// A newline and `exit`, for shells that keep reading after the end of their input.
+    Exit,
+}
+
+impl PipedTerminator {
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Eof => "",
+            Self::Newline => "\n",
+            Self::Exit => "\nexit\n",
+        }
+    }
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Eof => "eof",
+            Self::Newline => "newline",
+            Self::Exit => "exit",
+        }
+    }
+}
+
+impl TryFrom<&Value> for PipedTerminator {
+    type Error = anyhow::Error;
+    fn try_from(terminator: &Value) -> anyhow::Result<Self> {
+        let terminator = try_into_string("config.shell.piped-terminator")(terminator)?;
+
+        match terminator.as_str() {
+            "eof" => Ok(Self::Eof),
+            "newline" => Ok(Self::Newline),
+            "exit" => Ok(Self::Exit),
+            terminator => Err(anyhow!(
+                "`{}` must be `{}`, `{}` or `{}`, but is `{}`",
+                style_stderr!(bold(), "config.shell.piped-terminator"),
+                style_stderr!(bold(), "eof"),
+                style_stderr!(bold(), "newline"),
+                style_stderr!(bold(), "exit"),
+                style_stderr!(bold(), "{terminator}"),
+            )),
+        }
+    }
+}
+
+impl ConfigItem for Shell {
+    fn name() -> &'static str {
+        "shell"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        match self {
+            Self::Disabled => Value::Boolean(false),
+            Self::Enabled {
+                shell,
+                piped,
+                login,
+                append_args,
+                terminator,
+                forward_output,
+            } => Value::Table(Map::from_iter([
+                (String::from("shell"), strings_to_value(shell)),
+                (String::from("piped"), Value::Boolean(*piped)),
+                (String::from("login"), Value::Boolean(*login)),
+                (String::from("append-args"), strings_to_value(append_args)),
+                (
+                    String::from("piped-terminator"),
+                    Value::from(terminator.name()),
+                ),
+                (
+                    String::from("forward-output"),
+                    Value::Boolean(*forward_output),
+                ),
+            ])),
+        }
+    }
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Self::Enabled {
+            shell: vec![ImStr::new("sh"), ImStr::new("-c")],
+            piped: false,
+            login: false,
+            append_args: Vec::new(),
+            terminator: PipedTerminator::Eof,
+            forward_output: false,
+        }
+    }
+}
+
+impl TryFrom<&Value> for Shell {
     type Error = anyhow::Error;
     fn try_from(shell: &Value) -> anyhow::Result<Self> {
         match shell {
@@ -306,10 +1606,15 @@ impl TryFrom<&Value> for Shell {
                     .iter()
                     .map(try_into_array_string("config.shell"))
                     .collect::<Result<Vec<ImStr>, _>>()?;
+                no_shell_program("config.shell", &shell)?;
 
                 Ok(Self::Enabled {
                     shell,
                     piped: false,
+                    login: false,
+                    append_args: Vec::new(),
+                    terminator: PipedTerminator::Eof,
+                    forward_output: false,
                 })
             }
             Value::Table(table) => {
@@ -325,6 +1630,7 @@ impl TryFrom<&Value> for Shell {
                     })
                     .transpose()?
                     .unwrap_or_default();
+                no_shell_program("config.shell.shell", &shell)?;
 
                 let piped = table
                     .get("piped")
@@ -332,7 +1638,41 @@ impl TryFrom<&Value> for Shell {
                     .transpose()?
                     .unwrap_or(false);
 
-                Ok(Self::Enabled { shell, piped })
+                let login = table
+                    .get("login")
+                    .map(try_into_boolean("config.shell.login"))
+                    .transpose()?
+                    .unwrap_or(false);
+
+                let append_args = table
+                    .get("append-args")
+                    .map(try_into_array("config.shell.append-args"))
+                    .transpose()?
+                    .into_iter()
+                    .flatten()
+                    .map(try_into_array_string("config.shell.append-args"))
+                    .collect::<Result<Vec<ImStr>, _>>()?;
+
+                let terminator = table
+                    .get("piped-terminator")
+                    .map(PipedTerminator::try_from)
+                    .transpose()?
+                    .unwrap_or_default();
+
+                let forward_output = table
+                    .get("forward-output")
+                    .map(try_into_boolean("config.shell.forward-output"))
+                    .transpose()?
+                    .unwrap_or(false);
+
+                Ok(Self::Enabled {
+                    shell,
+                    piped,
+                    login,
+                    append_args,
+                    terminator,
+                    forward_output,
+                })
             }
             other => type_error(
                 "config.shell",
@@ -343,8 +1683,21 @@ impl TryFrom<&Value> for Shell {
     }
 }
 
-#[derive(Debug, Clone)]
+/// Error if `shell` has no program to run shell commands with.
+fn no_shell_program(name: &str, shell: &[ImStr]) -> anyhow::Result<()> {
+    if shell.first().is_none_or(|program| program.is_empty()) {
+        return Err(anyhow!(
+            "`{}` must specify a shell program, like `{}`",
+            style_stderr!(bold(), "{name}"),
+            style_stderr!(bold(), "[\"sh\", \"-c\"]"),
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default, Clone)]
 pub enum Custom {
+    #[default]
     Disabled,
     Enabled,
 }
@@ -356,36 +1709,936 @@ impl ConfigItem for Custom {
     fn merge(self, _: Self) -> Self {
         self
     }
-}
-
-impl Default for Custom {
-    fn default() -> Self {
-        Self::Disabled
+    fn to_value(&self) -> Value {
+        Value::Boolean(matches!(self, Self::Enabled))
+    }
+}
+
+impl TryFrom<&Value> for Custom {
+    type Error = anyhow::Error;
+    fn try_from(custom: &Value) -> anyhow::Result<Self> {
+        if try_into_boolean("config.custom")(custom)? {
+            Ok(Self::Enabled)
+        } else {
+            Ok(Self::Disabled)
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub enum Numbered {
+    #[default]
+    Disabled,
+    Enabled(Separator),
+}
+
+impl Numbered {
+    pub fn separator(&self) -> &str {
+        match self {
+            Self::Disabled | Self::Enabled(Separator::Disabled) => "",
+            Self::Enabled(Separator::Enabled(separator)) => separator.as_str(),
+        }
+    }
+
+    pub const fn is_enabled(&self) -> bool {
+        match self {
+            Self::Disabled => false,
+            Self::Enabled(_) => true,
+        }
+    }
+}
+
+impl ConfigItem for Numbered {
+    fn name() -> &'static str {
+        "numbered"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        match self {
+            Self::Disabled => Value::Boolean(false),
+            Self::Enabled(separator) => Value::Table(Map::from_iter([
+                (String::from("numbered"), Value::Boolean(true)),
+                (
+                    String::from("separator"),
+                    match separator {
+                        Separator::Disabled => Value::Boolean(false),
+                        Separator::Enabled(separator) => Value::String(separator.to_string()),
+                    },
+                ),
+            ])),
+        }
+    }
+}
+
+impl TryFrom<&Value> for Numbered {
+    type Error = anyhow::Error;
+    fn try_from(numbered: &Value) -> anyhow::Result<Self> {
+        match numbered {
+            Value::Boolean(false) => Ok(Self::Disabled),
+            Value::Boolean(true) => Ok(Self::Enabled(Separator::default())),
+            Value::Table(numbered) => {
+                let enabled = numbered
+                    .get("numbered")
+                    .map(try_into_boolean("config.numbered.numbered"))
+                    .transpose()?
+                    .unwrap_or(false);
+
+                let separator = numbered
+                    .get("separator")
+                    .map(Separator::try_from)
+                    .transpose()?
+                    .unwrap_or_default();
+
+                if enabled {
+                    Ok(Self::Enabled(separator))
+                } else {
+                    Ok(Self::Disabled)
+                }
+            }
+            other => type_error("config.numbered", &["boolean", "table"], other.type_str()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Separator {
+    Disabled,
+    Enabled(ImStr),
+}
+
+impl Default for Separator {
+    fn default() -> Self {
+        Self::Enabled(ImStr::new(": "))
+    }
+}
+
+impl TryFrom<&Value> for Separator {
+    type Error = anyhow::Error;
+    fn try_from(separator: &Value) -> anyhow::Result<Self> {
+        match separator {
+            Value::Boolean(false) => Ok(Self::Disabled),
+            Value::Boolean(true) => Ok(Self::default()),
+            Value::String(separator) => {
+                // A tab lines names up, but a newline would split the entry across lines,
+                // and tag characters would be mistaken for part of the number's tag.
+                if separator.contains(|c: char| c.is_control() && c != '\t')
+                    || contains_tag_chars(separator)
+                {
+                    return Err(anyhow!(
+                        "`{}` can't contain control characters other than a tab",
+                        style_stderr!(bold(), "config.numbered.separator")
+                    ));
+                }
+                Ok(Self::Enabled(ImStr::from(separator)))
+            }
+            other => type_error(
+                "config.numbered.separator",
+                &["boolean", "string"],
+                other.type_str(),
+            ),
+        }
+    }
+}
+
+/// How long to wait for commands that are waited on before killing them.
+///
+/// Commands that aren't waited on are left running in the background, even with a timeout.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Timeout(pub Option<Duration>);
+
+impl ConfigItem for Timeout {
+    fn name() -> &'static str {
+        "timeout"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        self.0.map_or(Value::Boolean(false), |timeout| {
+            Value::Float(timeout.as_secs_f64())
+        })
+    }
+}
+
+impl TryFrom<&Value> for Timeout {
+    type Error = anyhow::Error;
+    fn try_from(timeout: &Value) -> anyhow::Result<Self> {
+        match timeout {
+            Value::Boolean(false) => Ok(Self(None)),
+            Value::Integer(_) | Value::Float(_) | Value::String(_) => {
+                try_into_duration("config.timeout")(timeout).map(|timeout| Self(Some(timeout)))
+            }
+            other => type_error(
+                "config.timeout",
+                &["integer", "float", "string", "boolean"],
+                other.type_str(),
+            ),
+        }
+    }
+}
+
+/// Whether dmm waits for the commands it runs to finish before exiting.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Wait(pub bool);
+
+impl ConfigItem for Wait {
+    fn name() -> &'static str {
+        "wait"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        Value::Boolean(self.0)
+    }
+}
+
+impl TryFrom<&Value> for Wait {
+    type Error = anyhow::Error;
+    fn try_from(wait: &Value) -> anyhow::Result<Self> {
+        try_into_boolean("config.wait")(wait).map(Self)
+    }
+}
+
+/// How entries are ordered within each group.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Sort {
+    /// Alphabetically, ignoring case.
+    #[default]
+    Name,
+    /// Alphabetically, by the collation rules of the user's locale.
+    ///
+    /// Falls back to [`Sort::Name`] if the locale has no collation rules.
+    Locale,
+    /// By how often and how recently each entry was run, then by name.
+    Frecency,
+    /// In the order they were written, with pattern entries before home config entries,
+    /// and entries found in `PATH` last.
+    None,
+}
+
+impl ConfigItem for Sort {
+    fn name() -> &'static str {
+        "sort"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        let sort = match self {
+            Self::Name => "name",
+            Self::Locale => "locale",
+            Self::Frecency => "frecency",
+            Self::None => "none",
+        };
+        Value::String(String::from(sort))
+    }
+}
+
+impl TryFrom<&Value> for Sort {
+    type Error = anyhow::Error;
+    fn try_from(sort: &Value) -> anyhow::Result<Self> {
+        let sort = try_into_string("config.sort")(sort)?;
+
+        match sort.as_str() {
+            "name" => Ok(Self::Name),
+            "locale" => Ok(Self::Locale),
+            "frecency" => Ok(Self::Frecency),
+            "none" => Ok(Self::None),
+            sort => Err(anyhow!(
+                "`{}` must be `{}`, `{}`, `{}` or `{}`, but is `{}`",
+                style_stderr!(bold(), "config.sort"),
+                style_stderr!(bold(), "name"),
+                style_stderr!(bold(), "locale"),
+                style_stderr!(bold(), "frecency"),
+                style_stderr!(bold(), "none"),
+                style_stderr!(bold(), "{sort}"),
+            )),
+        }
+    }
+}
+
+/// Whether to reverse the order of entries within each group, whatever `config.sort` is.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SortReverse(pub bool);
+
+impl ConfigItem for SortReverse {
+    fn name() -> &'static str {
+        "sort-reverse"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        Value::Boolean(self.0)
+    }
+}
+
+impl TryFrom<&Value> for SortReverse {
+    type Error = anyhow::Error;
+    fn try_from(reverse: &Value) -> anyhow::Result<Self> {
+        try_into_boolean("config.sort-reverse")(reverse).map(Self)
+    }
+}
+
+/// Whether sorting by name compares raw bytes, putting uppercase before lowercase,
+/// rather than ignoring case. Doesn't apply to `config.sort = "locale"`, which collates names.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SortCaseSensitive(pub bool);
+
+impl ConfigItem for SortCaseSensitive {
+    fn name() -> &'static str {
+        "sort-case-sensitive"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        Value::Boolean(self.0)
+    }
+}
+
+impl TryFrom<&Value> for SortCaseSensitive {
+    type Error = anyhow::Error;
+    fn try_from(case_sensitive: &Value) -> anyhow::Result<Self> {
+        try_into_boolean("config.sort-case-sensitive")(case_sensitive).map(Self)
+    }
+}
+
+/// How `config.sort = "frecency"` weighs recent runs against older ones.
+#[derive(Debug, Clone, Copy)]
+pub struct Frecency {
+    /// How long it takes for a run to count half as much.
+    pub half_life: Duration,
+}
+
+impl Default for Frecency {
+    fn default() -> Self {
+        Self {
+            half_life: Duration::from_secs(7 * 24 * 60 * 60),
+        }
+    }
+}
+
+impl ConfigItem for Frecency {
+    fn name() -> &'static str {
+        "frecency"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        Value::Table(Map::from_iter([(
+            String::from("half-life"),
+            Value::Float(self.half_life.as_secs_f64()),
+        )]))
+    }
+}
+
+impl TryFrom<&Value> for Frecency {
+    type Error = anyhow::Error;
+    fn try_from(frecency: &Value) -> anyhow::Result<Self> {
+        let frecency = try_into_table("config.frecency")(frecency)?;
+        let half_life = frecency
+            .get("half-life")
+            .map(try_into_duration("config.frecency.half-life"))
+            .transpose()?
+            .unwrap_or(Self::default().half_life);
+
+        Ok(Self { half_life })
+    }
+}
+
+/// How many custom commands to remember in the history.
+#[derive(Debug, Clone, Copy)]
+pub struct HistoryMax(pub u64);
+
+impl Default for HistoryMax {
+    fn default() -> Self {
+        Self(100)
+    }
+}
+
+impl ConfigItem for HistoryMax {
+    fn name() -> &'static str {
+        "history-max"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        Value::Integer(self.0.try_into().unwrap_or(i64::MAX))
+    }
+}
+
+impl TryFrom<&Value> for HistoryMax {
+    type Error = anyhow::Error;
+    fn try_from(max: &Value) -> anyhow::Result<Self> {
+        let max = try_into_integer("config.history-max")(max)?;
+        try_into_unsigned_integer("config.history-max")(max).map(Self)
+    }
+}
+
+/// Where to keep state that persists between runs.
+#[derive(Debug, Default, Clone)]
+pub enum CacheDir {
+    /// The cache directory for the platform, like `$XDG_CACHE_HOME/dmm`.
+    #[default]
+    Default,
+    /// Don't persist anything.
+    Disabled,
+    Custom(ImStr),
+}
+
+impl CacheDir {
+    /// Find the directory to use for the cache, if it's enabled and a default can be found.
+    ///
+    /// A custom directory has `~/` and environment variables expanded.
+    pub fn resolve(
+        &self,
+        dirs: Option<&ProjectDirs>,
+        base_dirs: Option<&BaseDirs>,
+    ) -> anyhow::Result<Option<PathBuf>> {
+        match self {
+            Self::Default => Ok(dirs.map(|dirs| dirs.cache_dir().to_path_buf())),
+            Self::Disabled => Ok(None),
+            Self::Custom(dir) => expand_path(dir, base_dirs).map(Some).context(format!(
+                "unable to expand `{}`",
+                style_stderr!(bold(), "config.cache-dir")
+            )),
+        }
+    }
+}
+
+impl ConfigItem for CacheDir {
+    fn name() -> &'static str {
+        "cache-dir"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        match self {
+            Self::Default => Value::Boolean(true),
+            Self::Disabled => Value::Boolean(false),
+            Self::Custom(dir) => Value::String(dir.to_string()),
+        }
+    }
+}
+
+impl TryFrom<&Value> for CacheDir {
+    type Error = anyhow::Error;
+    fn try_from(dir: &Value) -> anyhow::Result<Self> {
+        match dir {
+            Value::Boolean(true) => Ok(Self::Default),
+            Value::Boolean(false) => Ok(Self::Disabled),
+            Value::String(dir) => Ok(Self::Custom(ImStr::from(dir))),
+            other => type_error("config.cache-dir", &["string", "boolean"], other.type_str()),
+        }
+    }
+}
+
+/// How long the menu stays open before it's closed without a selection.
+///
+/// Without a timeout, the menu stays open until something is selected or it's dismissed.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MenuTimeout(pub Option<Duration>);
+
+impl ConfigItem for MenuTimeout {
+    fn name() -> &'static str {
+        "menu-timeout"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        self.0.map_or(Value::Boolean(false), |timeout| {
+            Value::Float(timeout.as_secs_f64())
+        })
+    }
+}
+
+impl TryFrom<&Value> for MenuTimeout {
+    type Error = anyhow::Error;
+    fn try_from(timeout: &Value) -> anyhow::Result<Self> {
+        match timeout {
+            Value::Boolean(false) => Ok(Self(None)),
+            Value::Integer(_) | Value::Float(_) | Value::String(_) => {
+                try_into_duration("config.menu-timeout")(timeout).map(|timeout| Self(Some(timeout)))
+            }
+            other => type_error(
+                "config.menu-timeout",
+                &["integer", "float", "string", "boolean"],
+                other.type_str(),
+            ),
+        }
+    }
+}
+
+/// How many times to reopen dmenu if it's dismissed without selecting anything.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Retry(pub u64);
+
+impl ConfigItem for Retry {
+    fn name() -> &'static str {
+        "retry"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        Value::Integer(self.0.try_into().unwrap_or(i64::MAX))
+    }
+}
+
+impl TryFrom<&Value> for Retry {
+    type Error = anyhow::Error;
+    fn try_from(retry: &Value) -> anyhow::Result<Self> {
+        let retry = try_into_integer("config.retry")(retry)?;
+        try_into_unsigned_integer("config.retry")(retry).map(Self)
+    }
+}
+
+/// Whether to run identical commands only once when several are selected.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DedupSelections(pub bool);
+
+impl ConfigItem for DedupSelections {
+    fn name() -> &'static str {
+        "dedup-selections"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        Value::Boolean(self.0)
+    }
+}
+
+impl TryFrom<&Value> for DedupSelections {
+    type Error = anyhow::Error;
+    fn try_from(dedup: &Value) -> anyhow::Result<Self> {
+        try_into_boolean("config.dedup-selections")(dedup).map(Self)
+    }
+}
+
+/// An env file of `KEY=value` lines whose variables are set for every command.
+#[derive(Debug, Default, Clone)]
+pub struct EnvFile(pub Option<ImStr>);
+
+impl EnvFile {
+    /// Read and parse the env file, if one is configured.
+    ///
+    /// A missing file only adds a warning, while any other problem is an error.
+    fn load(
+        &self,
+        base_dirs: Option<&BaseDirs>,
+        warnings: &mut Vec<Rc<anyhow::Error>>,
+    ) -> anyhow::Result<Vec<(ImStr, ImStr)>> {
+        let Some(path) = &self.0 else {
+            return Ok(Vec::new());
+        };
+        let path = expand_path(path, base_dirs).context(format!(
+            "unable to expand `{}`",
+            style_stderr!(bold(), "config.env-file")
+        ))?;
+        let file_error = || {
+            format!(
+                "problem with env file `{}`",
+                style_stderr!(bold(), "{}", path.display())
+            )
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                verbose!("reading env file `{}`", path.display());
+                dotenv::parse(&contents).context(file_error())
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                let err = anyhow!("it doesn't exist, so no variables were loaded from it")
+                    .context(file_error());
+                warnings.push(Rc::new(err));
+                Ok(Vec::new())
+            }
+            Err(err) => Err(anyhow!(err).context(file_error())),
+        }
+    }
+}
+
+impl ConfigItem for EnvFile {
+    fn name() -> &'static str {
+        "env-file"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        self.0.as_ref().map_or(Value::Boolean(false), |path| {
+            Value::String(path.to_string())
+        })
+    }
+}
+
+impl TryFrom<&Value> for EnvFile {
+    type Error = anyhow::Error;
+    fn try_from(path: &Value) -> anyhow::Result<Self> {
+        match path {
+            Value::Boolean(false) => Ok(Self(None)),
+            Value::String(path) => Ok(Self(Some(ImStr::from(path)))),
+            other => type_error("config.env-file", &["string", "boolean"], other.type_str()),
+        }
+    }
+}
+
+/// Whether to run commands with an empty environment, except for `config.env-passthrough`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CleanEnv(pub bool);
+
+impl ConfigItem for CleanEnv {
+    fn name() -> &'static str {
+        "clean-env"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        Value::Boolean(self.0)
+    }
+}
+
+impl TryFrom<&Value> for CleanEnv {
+    type Error = anyhow::Error;
+    fn try_from(clean: &Value) -> anyhow::Result<Self> {
+        try_into_boolean("config.clean-env")(clean).map(Self)
+    }
+}
+
+/// Environment variables kept when `config.clean-env` is enabled.
+#[derive(Debug, Clone)]
+pub struct EnvPassthrough(pub Vec<ImStr>);
+
+impl Default for EnvPassthrough {
+    /// Enough for graphical programs to find the display and other programs.
+    fn default() -> Self {
+        Self(vec![
+            ImStr::new("DISPLAY"),
+            ImStr::new("WAYLAND_DISPLAY"),
+            ImStr::new("XDG_RUNTIME_DIR"),
+            ImStr::new("PATH"),
+            ImStr::new("HOME"),
+        ])
+    }
+}
+
+impl ConfigItem for EnvPassthrough {
+    fn name() -> &'static str {
+        "env-passthrough"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        strings_to_value(&self.0)
+    }
+}
+
+impl TryFrom<&Value> for EnvPassthrough {
+    type Error = anyhow::Error;
+    fn try_from(vars: &Value) -> anyhow::Result<Self> {
+        try_into_array("config.env-passthrough")(vars)?
+            .iter()
+            .map(try_into_array_string("config.env-passthrough"))
+            .collect::<Result<Vec<ImStr>, _>>()
+            .map(Self)
+    }
+}
+
+/// Whether to run commands in their own transient systemd scope, with `systemd-run --user --scope`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemdScope(pub bool);
+
+impl ConfigItem for SystemdScope {
+    fn name() -> &'static str {
+        "systemd-scope"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        Value::Boolean(self.0)
+    }
+}
+
+impl TryFrom<&Value> for SystemdScope {
+    type Error = anyhow::Error;
+    fn try_from(scope: &Value) -> anyhow::Result<Self> {
+        try_into_boolean("config.systemd-scope")(scope).map(Self)
+    }
+}
+
+/// Whether to run commands in a new session, without a controlling terminal.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Setsid(pub bool);
+
+impl ConfigItem for Setsid {
+    fn name() -> &'static str {
+        "setsid"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        Value::Boolean(self.0)
+    }
+}
+
+impl TryFrom<&Value> for Setsid {
+    type Error = anyhow::Error;
+    fn try_from(setsid: &Value) -> anyhow::Result<Self> {
+        try_into_boolean("config.setsid")(setsid).map(Self)
+    }
+}
+
+/// Whether to expand glob arguments of bare commands, like a shell would.
+#[derive(Debug, Default, Clone, Copy)]
+pub enum GlobArgs {
+    #[default]
+    Disabled,
+    Enabled {
+        /// Drop globs that match nothing, rather than passing them on as they are.
+        nullglob: bool,
+    },
+}
+
+impl ConfigItem for GlobArgs {
+    fn name() -> &'static str {
+        "glob-args"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        match self {
+            Self::Disabled => Value::Boolean(false),
+            Self::Enabled { nullglob } => Value::Table(Map::from_iter([(
+                String::from("nullglob"),
+                Value::Boolean(*nullglob),
+            )])),
+        }
+    }
+}
+
+impl TryFrom<&Value> for GlobArgs {
+    type Error = anyhow::Error;
+    fn try_from(glob_args: &Value) -> anyhow::Result<Self> {
+        match glob_args {
+            Value::Boolean(false) => Ok(Self::Disabled),
+            Value::Boolean(true) => Ok(Self::Enabled { nullglob: false }),
+            Value::Table(table) => {
+                let nullglob = table
+                    .get("nullglob")
+                    .map(try_into_boolean("config.glob-args.nullglob"))
+                    .transpose()?
+                    .unwrap_or(false);
+                Ok(Self::Enabled { nullglob })
+            }
+            other => type_error("config.glob-args", &["boolean", "table"], other.type_str()),
+        }
+    }
+}
+
+/// Whether to run commands with a pseudo-terminal as their stdio,
+/// for programs that refuse to run when their output isn't a terminal.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Pty(pub bool);
+
+impl ConfigItem for Pty {
+    fn name() -> &'static str {
+        "pty"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        Value::Boolean(self.0)
+    }
+}
+
+impl TryFrom<&Value> for Pty {
+    type Error = anyhow::Error;
+    fn try_from(pty: &Value) -> anyhow::Result<Self> {
+        try_into_boolean("config.pty")(pty).map(Self)
+    }
+}
+
+/// How to get the user's attention the first time an error or warning is reported.
+#[derive(Debug, Default, Clone)]
+pub enum Bell {
+    #[default]
+    Disabled,
+    /// Ring the bell of the controlling terminal, if there is one.
+    Terminal,
+    /// Run a command, like one that plays a sound.
+    Command(Run),
+}
+
+impl ConfigItem for Bell {
+    fn name() -> &'static str {
+        "bell"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        match self {
+            Self::Disabled => Value::Boolean(false),
+            Self::Terminal => Value::Boolean(true),
+            Self::Command(run) => Value::from(run),
+        }
+    }
+}
+
+impl TryFrom<&Value> for Bell {
+    type Error = anyhow::Error;
+    fn try_from(bell: &Value) -> anyhow::Result<Self> {
+        match bell {
+            Value::Boolean(false) => Ok(Self::Disabled),
+            Value::Boolean(true) => Ok(Self::Terminal),
+            Value::String(_) | Value::Array(_) => {
+                try_into_run("config.bell")(bell).map(Self::Command)
+            }
+            other => type_error(
+                "config.bell",
+                &["boolean", "string", "array"],
+                other.type_str(),
+            ),
+        }
+    }
+}
+
+/// Whether to compose entry names into Unicode Normalization Form C,
+/// so names that look the same are treated as the same entry.
+#[derive(Debug, Clone, Copy)]
+pub struct NormalizeNames(pub bool);
+
+impl Default for NormalizeNames {
+    fn default() -> Self {
+        Self(true)
+    }
+}
+
+impl ConfigItem for NormalizeNames {
+    fn name() -> &'static str {
+        "normalize-names"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        Value::Boolean(self.0)
+    }
+}
+
+impl TryFrom<&Value> for NormalizeNames {
+    type Error = anyhow::Error;
+    fn try_from(normalize: &Value) -> anyhow::Result<Self> {
+        try_into_boolean("config.normalize-names")(normalize).map(Self)
+    }
+}
+
+/// Whether to add an ascii-folded copy of names with accented letters to their lines,
+/// so they can be found by typing plain letters.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FoldMatching(pub bool);
+
+impl ConfigItem for FoldMatching {
+    fn name() -> &'static str {
+        "fold-matching"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        Value::Boolean(self.0)
+    }
+}
+
+impl TryFrom<&Value> for FoldMatching {
+    type Error = anyhow::Error;
+    fn try_from(fold: &Value) -> anyhow::Result<Self> {
+        try_into_boolean("config.fold-matching")(fold).map(Self)
+    }
+}
+
+/// How selections are matched back to their entries.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TagMode {
+    /// Only use the tag hidden in each line.
+    #[default]
+    Strict,
+    /// Fall back to matching the selection against entry names if its tag is missing,
+    /// as happens with launchers that strip zero-width characters.
+    Auto,
+}
+
+impl ConfigItem for TagMode {
+    fn name() -> &'static str {
+        "tag"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        let mode = match self {
+            Self::Strict => "strict",
+            Self::Auto => "auto",
+        };
+        Value::String(String::from(mode))
     }
 }
 
-impl TryFrom<&Value> for Custom {
+impl TryFrom<&Value> for TagMode {
     type Error = anyhow::Error;
-    fn try_from(custom: &Value) -> anyhow::Result<Self> {
-        if try_into_boolean("config.custom")(custom)? {
-            Ok(Self::Disabled)
-        } else {
-            Ok(Self::Enabled)
+    fn try_from(mode: &Value) -> anyhow::Result<Self> {
+        let mode = try_into_string("config.tag")(mode)?;
+
+        match mode.as_str() {
+            "strict" => Ok(Self::Strict),
+            "auto" => Ok(Self::Auto),
+            mode => Err(anyhow!(
+                "`{}` must be `{}` or `{}`, but is `{}`",
+                style_stderr!(bold(), "config.tag"),
+                style_stderr!(bold(), "strict"),
+                style_stderr!(bold(), "auto"),
+                style_stderr!(bold(), "{mode}"),
+            )),
         }
     }
 }
 
-#[derive(Debug, Clone)]
-pub enum Numbered {
+#[derive(Debug, Default, Clone)]
+pub enum GroupHeaders {
+    #[default]
     Disabled,
-    Enabled(Separator),
+    Enabled(ImStr),
 }
 
-impl Numbered {
-    pub fn separator(&self) -> &str {
+impl GroupHeaders {
+    const DEFAULT_FORMAT: &'static str = "-- {group} --";
+
+    /// Create the header displayed before the entries of `group`.
+    ///
+    /// Any `{group}` in the format is replaced with the group's name if it has one,
+    /// or its number otherwise.
+    pub fn header(&self, group: i64, name: Option<&str>) -> Option<String> {
         match self {
-            Self::Disabled | Self::Enabled(Separator::Disabled) => "",
-            Self::Enabled(Separator::Enabled(separator)) => separator.as_str(),
+            Self::Disabled => None,
+            Self::Enabled(format) => {
+                let group = name.map_or_else(|| group.to_string(), str::to_owned);
+                Some(format.replace("{group}", &group))
+            }
         }
     }
 
@@ -397,91 +2650,407 @@ impl Numbered {
     }
 }
 
-impl ConfigItem for Numbered {
+impl ConfigItem for GroupHeaders {
     fn name() -> &'static str {
-        "numbered"
+        "group-headers"
     }
     fn merge(self, _: Self) -> Self {
         self
     }
-}
-
-impl Default for Numbered {
-    fn default() -> Self {
-        Self::Disabled
+    fn to_value(&self) -> Value {
+        match self {
+            Self::Disabled => Value::Boolean(false),
+            Self::Enabled(format) => Value::Table(Map::from_iter([
+                (String::from("group-headers"), Value::Boolean(true)),
+                (String::from("format"), Value::String(format.to_string())),
+            ])),
+        }
     }
 }
 
-impl TryFrom<&Value> for Numbered {
+impl TryFrom<&Value> for GroupHeaders {
     type Error = anyhow::Error;
-    fn try_from(numbered: &Value) -> anyhow::Result<Self> {
-        match numbered {
+    fn try_from(headers: &Value) -> anyhow::Result<Self> {
+        match headers {
             Value::Boolean(false) => Ok(Self::Disabled),
-            Value::Boolean(true) => Ok(Self::Enabled(Separator::default())),
-            Value::Table(numbered) => {
-                let enabled = numbered
-                    .get("numbered")
-                    .map(try_into_boolean("config.numbered.numbered"))
+            Value::Boolean(true) => Ok(Self::Enabled(ImStr::new(Self::DEFAULT_FORMAT))),
+            Value::Table(headers) => {
+                let enabled = headers
+                    .get("group-headers")
+                    .map(try_into_boolean("config.group-headers.group-headers"))
                     .transpose()?
                     .unwrap_or(false);
 
-                let separator = numbered
-                    .get("separator")
-                    .map(Separator::try_from)
+                let format = headers
+                    .get("format")
+                    .map(try_into_string("config.group-headers.format"))
                     .transpose()?
-                    .unwrap_or_default();
+                    .unwrap_or(ImStr::new(Self::DEFAULT_FORMAT));
 
                 if enabled {
-                    Ok(Self::Enabled(separator))
+                    Ok(Self::Enabled(format))
                 } else {
                     Ok(Self::Disabled)
                 }
             }
-            other => type_error("config.numbered", &["boolean", "table"], other.type_str()),
+            other => type_error(
+                "config.group-headers",
+                &["boolean", "table"],
+                other.type_str(),
+            ),
         }
     }
 }
 
-#[derive(Debug, Clone)]
-pub enum Separator {
-    Disabled,
-    Enabled(ImStr),
+/// Colors to display the names of each group's entries in, for backends that support markup.
+#[derive(Debug, Default, Clone)]
+pub struct GroupColors(pub HashMap<i64, ImStr>);
+
+impl GroupColors {
+    /// Render `name` as pango markup, in the color of `group` if it has one.
+    ///
+    /// ```
+    /// use dmm::config::GroupColors;
+    /// use dmm::imstr::ImStr;
+    ///
+    /// let colors = GroupColors([(1, ImStr::new("#8be9fd"))].into_iter().collect());
+    /// assert_eq!(
+    ///     colors.markup(1, "Tom & Jerry <3"),
+    ///     r##"<span color="#8be9fd">Tom &amp; Jerry &lt;3</span>"##,
+    /// );
+    /// // Names in groups without a color are only escaped.
+    /// assert_eq!(colors.markup(0, "Tom & Jerry"), "Tom &amp; Jerry");
+    /// ```
+    pub fn markup(&self, group: i64, name: &str) -> String {
+        let name = escape_markup(name);
+        match self.0.get(&group) {
+            Some(color) => format!(r#"<span color="{}">{name}</span>"#, escape_markup(color)),
+            None => name.into_owned(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.0.is_empty()
+    }
 }
 
-impl Default for Separator {
-    fn default() -> Self {
-        Self::Enabled(ImStr::new(": "))
+/// The entry to preselect in the menu, so it can be run by pressing enter.
+#[derive(Debug, Default, Clone)]
+pub enum DefaultEntry {
+    #[default]
+    None,
+    /// The entry with this name.
+    Entry(ImStr),
+    /// The entry that was run most recently, as remembered in the usage cache.
+    Last,
+}
+
+impl ConfigItem for DefaultEntry {
+    fn name() -> &'static str {
+        "default"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        match self {
+            Self::None => Value::Boolean(false),
+            Self::Entry(name) => Value::String(name.to_string()),
+            Self::Last => Value::String(String::from("last")),
+        }
     }
 }
 
-impl TryFrom<&Value> for Separator {
+impl TryFrom<&Value> for DefaultEntry {
     type Error = anyhow::Error;
-    fn try_from(separator: &Value) -> anyhow::Result<Self> {
-        match separator {
-            Value::Boolean(false) => Ok(Self::Disabled),
-            Value::Boolean(true) => Ok(Self::default()),
-            Value::String(separator) => Ok(Self::Enabled(ImStr::from(separator))),
-            other => type_error(
-                "config.numbered.separator",
-                &["boolean", "string"],
-                other.type_str(),
-            ),
+    fn try_from(default: &Value) -> anyhow::Result<Self> {
+        match default {
+            Value::Boolean(false) => Ok(Self::None),
+            Value::String(last) if last == "last" => Ok(Self::Last),
+            Value::String(name) => Ok(Self::Entry(ImStr::from(name))),
+            other => type_error("config.default", &["string", "false"], other.type_str()),
+        }
+    }
+}
+
+/// Key bindings that run an entry directly from the menu, for backends with custom key bindings.
+///
+/// Each is a key binding, like `Alt+q`, and the name of the entry it runs, in the order they're
+/// written; the first is bound to the backend's first custom key binding, and so on.
+#[derive(Debug, Default, Clone)]
+pub struct Accelerators(pub Vec<(ImStr, ImStr)>);
+
+impl Accelerators {
+    /// The most custom key bindings rofi has: `-kb-custom-1` to `-kb-custom-19`.
+    pub const MAX: usize = 19;
+
+    pub fn is_enabled(&self) -> bool {
+        !self.0.is_empty()
+    }
+}
+
+impl ConfigItem for Accelerators {
+    fn name() -> &'static str {
+        "accelerators"
+    }
+    fn merge(self, mut default: Self) -> Self {
+        default
+            .0
+            .retain(|(key, _)| !self.0.iter().any(|(other, _)| other == key));
+        default.0.extend(self.0);
+        default
+    }
+    fn to_value(&self) -> Value {
+        Value::Table(
+            self.0
+                .iter()
+                .map(|(key, name)| (key.to_string(), Value::String(name.to_string())))
+                .collect(),
+        )
+    }
+}
+
+impl TryFrom<&Value> for Accelerators {
+    type Error = anyhow::Error;
+    fn try_from(accelerators: &Value) -> anyhow::Result<Self> {
+        try_into_table("config.accelerators")(accelerators)?
+            .iter()
+            .map(|(key, name)| {
+                let name = try_into_string(&format!("config.accelerators.{key}"))(name)?;
+                Ok((ImStr::from(key.as_str()), name))
+            })
+            .collect::<anyhow::Result<Vec<(ImStr, ImStr)>>>()
+            .map(Self)
+    }
+}
+
+/// Escape the characters pango markup treats specially in `text`.
+pub fn escape_markup(text: &str) -> Cow<'_, str> {
+    if !text.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::from(text);
+    }
+
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
         }
     }
+    Cow::from(escaped)
+}
+
+impl ConfigItem for GroupColors {
+    fn name() -> &'static str {
+        "group-colors"
+    }
+    fn merge(self, mut default: Self) -> Self {
+        default.0.extend(self.0);
+        default
+    }
+    fn to_value(&self) -> Value {
+        let mut colors = self.0.iter().collect::<Vec<(&i64, &ImStr)>>();
+        colors.sort_unstable();
+        Value::Table(
+            colors
+                .into_iter()
+                .map(|(group, color)| (group.to_string(), Value::String(color.to_string())))
+                .collect(),
+        )
+    }
+}
+
+impl TryFrom<&Value> for GroupColors {
+    type Error = anyhow::Error;
+    fn try_from(colors: &Value) -> anyhow::Result<Self> {
+        try_into_table("config.group-colors")(colors)?
+            .iter()
+            .map(|(group, color)| {
+                let name = format!("config.group-colors.{group}");
+                let group = group.parse::<i64>().map_err(|_| {
+                    anyhow!(
+                        "`{}` must be a group number, like `{}`",
+                        style_stderr!(bold(), "{name}"),
+                        style_stderr!(bold(), "-1"),
+                    )
+                })?;
+                let color = try_into_string(&name)(color)?;
+                Ok((group, color))
+            })
+            .collect::<anyhow::Result<HashMap<i64, ImStr>>>()
+            .map(Self)
+    }
 }
 
+/// The order groups are displayed in, overriding their numeric order.
+///
+/// Groups that aren't listed come after those that are, from the highest number to the lowest.
+#[derive(Debug, Default, Clone)]
+pub struct GroupOrder(pub Vec<i64>);
+
+impl GroupOrder {
+    const NAME: &'static str = "group-order";
+
+    /// A key that sorts `group` into its place in the order.
+    ///
+    /// ```
+    /// use dmm::config::GroupOrder;
+    ///
+    /// let order = GroupOrder(vec![10, 20, 5]);
+    /// let mut groups = [-1, 5, 0, 20, 3, 10];
+    /// groups.sort_by_key(|&group| order.key(group));
+    /// assert_eq!(groups, [10, 20, 5, 3, 0, -1]);
+    ///
+    /// // Without an order, groups go from the highest number to the lowest.
+    /// let mut groups = [-1, 5, 0];
+    /// groups.sort_by_key(|&group| GroupOrder::default().key(group));
+    /// assert_eq!(groups, [5, 0, -1]);
+    /// ```
+    pub fn key(&self, group: i64) -> (usize, Reverse<i64>) {
+        let position = self.0.iter().position(|&listed| listed == group);
+        (position.unwrap_or(self.0.len()), Reverse(group))
+    }
+
+    fn try_new(order: &Value, groups: &HashMap<ImStr, i64>) -> anyhow::Result<Self> {
+        let name = format!("config.{}", Self::NAME);
+        let order = try_into_array(&name)(order)?
+            .iter()
+            .map(try_into_group(&name, groups))
+            .collect::<anyhow::Result<Vec<i64>>>()?;
+
+        Ok(Self(order))
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Array(self.0.iter().copied().map(Value::Integer).collect())
+    }
+}
+
+/// A directory searched for binaries by `config.path`.
+///
+/// ```
+/// use dmm::config::BinPath;
+/// use toml::Value;
+///
+/// let path = |toml: &str| BinPath::try_from(&toml.parse::<Value>().unwrap()["path"]);
+/// let BinPath::Enabled { path: dirs, group, .. } =
+///     path(r#"path = { path = [{ dir = "~/bin", group = 5 }, "/usr/bin"], group = -1 }"#).unwrap()
+/// else {
+///     panic!("the path is enabled");
+/// };
+/// let groups = dirs
+///     .iter()
+///     .map(|dir| (dir.dir.as_str(), dir.group.unwrap_or(group)))
+///     .collect::<Vec<_>>();
+///
+/// assert_eq!(groups, [("~/bin", 5), ("/usr/bin", -1)]);
+/// assert!(path(r#"path = [{ dir = "~/bin", group = 5 }]"#).is_ok());
+/// assert!(path("path = [{ group = 5 }]").is_err());
+/// ```
 #[derive(Debug, Clone)]
+pub struct PathDir {
+    pub dir: ImStr,
+    /// The group of the binaries found in it; `config.path.group` if unset.
+    pub group: Option<i64>,
+}
+
+impl PathDir {
+    /// Parse a directory, or a table with its `dir` and `group`.
+    fn try_new(name: &str, dir: &Value) -> anyhow::Result<Self> {
+        match dir {
+            Value::String(dir) => Ok(Self {
+                dir: ImStr::from(dir),
+                group: None,
+            }),
+            Value::Table(table) => {
+                let dir = table
+                    .get("dir")
+                    .map(try_into_string(&format!("{name}.dir")))
+                    .transpose()?
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "a directory in `{}` is missing its `{}`",
+                            style_stderr!(bold(), "{name}"),
+                            style_stderr!(bold(), "dir"),
+                        )
+                    })?;
+                let group = table
+                    .get("group")
+                    .map(try_into_integer(&format!("{name}.group")))
+                    .transpose()?;
+
+                Ok(Self { dir, group })
+            }
+            other => Err(anyhow!(
+                "the array `{}` must only contain elements of type `{}` or `{}`, but an element is of type `{}`",
+                style_stderr!(bold(), "{name}"),
+                style_stderr!(bold(), "string"),
+                style_stderr!(bold(), "table"),
+                style_stderr!(bold(), "{}", other.type_str())
+            )),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        let mut table = Map::from_iter([(String::from("dir"), Value::from(self.dir.as_str()))]);
+        if let Some(group) = self.group {
+            table.insert(String::from("group"), Value::Integer(group));
+        }
+        Value::Table(table)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 pub enum BinPath {
+    #[default]
     Disabled,
     Enabled {
-        path: Vec<ImStr>,
+        path: Vec<PathDir>,
         env: bool,
         replace: bool,
         recursive: bool,
         group: i64,
+        /// A command run for each binary found, whose first line of output describes it.
+        /// Any `{name}` in its arguments is replaced with the binary's name.
+        describe: Option<Vec<ImStr>>,
+        /// Run the binaries found with `config.shell`, rather than as bare commands.
+        shell: bool,
+        /// Leave out binaries named like any entry, even one hidden by its conditions.
+        hide_duplicates: bool,
     },
 }
 
+impl BinPath {
+    /// Whether the path is enabled, but has no directories to search:
+    /// no `path` was given, and `env` is false.
+    ///
+    /// ```
+    /// use dmm::config::BinPath;
+    /// use toml::Value;
+    ///
+    /// let path = |toml: &str| {
+    ///     let config = toml.parse::<Value>().unwrap();
+    ///     BinPath::try_from(&config["path"]).unwrap()
+    /// };
+    /// assert!(path("path = []").searches_nothing());
+    /// assert!(path("path = { path = [], env = false }").searches_nothing());
+    /// assert!(path("path = { recursive = true }").searches_nothing());
+    /// assert!(!path("path = true").searches_nothing());
+    /// assert!(!path("path = false").searches_nothing());
+    /// assert!(!path("path = [\"~/bin\"]").searches_nothing());
+    /// ```
+    pub fn searches_nothing(&self) -> bool {
+        match self {
+            Self::Disabled => false,
+            Self::Enabled { path, env, .. } => path.is_empty() && !env,
+        }
+    }
+}
+
 impl ConfigItem for BinPath {
     fn name() -> &'static str {
         "path"
@@ -489,11 +3058,47 @@ impl ConfigItem for BinPath {
     fn merge(self, _: Self) -> Self {
         self
     }
-}
-
-impl Default for BinPath {
-    fn default() -> Self {
-        Self::Disabled
+    fn to_value(&self) -> Value {
+        match self {
+            Self::Disabled => Value::Boolean(false),
+            Self::Enabled {
+                path,
+                env,
+                replace,
+                recursive,
+                group,
+                describe,
+                shell,
+                hide_duplicates,
+            } => {
+                // An array of both strings and tables can't be serialized, so either all are tables or none are.
+                let path_value = if path.iter().any(|dir| dir.group.is_some()) {
+                    Value::Array(path.iter().map(PathDir::to_value).collect())
+                } else {
+                    Value::Array(
+                        path.iter()
+                            .map(|dir| Value::from(dir.dir.as_str()))
+                            .collect(),
+                    )
+                };
+                let mut table = Map::from_iter([
+                    (String::from("path"), path_value),
+                    (String::from("env"), Value::Boolean(*env)),
+                    (String::from("replace"), Value::Boolean(*replace)),
+                    (String::from("recursive"), Value::Boolean(*recursive)),
+                    (String::from("group"), Value::Integer(*group)),
+                    (String::from("shell"), Value::Boolean(*shell)),
+                    (
+                        String::from("hide-duplicates"),
+                        Value::Boolean(*hide_duplicates),
+                    ),
+                ]);
+                if let Some(describe) = describe {
+                    table.insert(String::from("describe"), strings_to_value(describe));
+                }
+                Value::Table(table)
+            }
+        }
     }
 }
 
@@ -508,12 +3113,15 @@ impl TryFrom<&Value> for BinPath {
                 replace: false,
                 recursive: false,
                 group: 0,
+                describe: None,
+                shell: false,
+                hide_duplicates: false,
             }),
             Value::Array(array) => {
                 let path = array
                     .iter()
-                    .map(try_into_array_string("config.path"))
-                    .collect::<Result<Vec<ImStr>, _>>()?;
+                    .map(|dir| PathDir::try_new("config.path", dir))
+                    .collect::<anyhow::Result<Vec<PathDir>>>()?;
 
                 Ok(Self::Enabled {
                     path,
@@ -521,6 +3129,9 @@ impl TryFrom<&Value> for BinPath {
                     replace: false,
                     recursive: false,
                     group: 0,
+                    describe: None,
+                    shell: false,
+                    hide_duplicates: false,
                 })
             }
             Value::Table(table) => {
@@ -531,8 +3142,8 @@ impl TryFrom<&Value> for BinPath {
                     .map(|value| {
                         value
                             .iter()
-                            .map(try_into_array_string("config.path.path"))
-                            .collect::<Result<Vec<ImStr>, _>>()
+                            .map(|dir| PathDir::try_new("config.path.path", dir))
+                            .collect::<anyhow::Result<Vec<PathDir>>>()
                     })
                     .transpose()?
                     .unwrap_or_default();
@@ -561,16 +3172,61 @@ impl TryFrom<&Value> for BinPath {
                     .transpose()?
                     .unwrap_or(0);
 
+                let describe = table
+                    .get("describe")
+                    .map(|describe| {
+                        let describe = match describe {
+                            Value::String(describe) => {
+                                describe.split_whitespace().map(ImStr::from).collect()
+                            }
+                            Value::Array(describe) => describe
+                                .iter()
+                                .map(try_into_array_string("config.path.describe"))
+                                .collect::<Result<Vec<ImStr>, _>>()?,
+                            other => {
+                                return type_error(
+                                    "config.path.describe",
+                                    &["string", "array"],
+                                    other.type_str(),
+                                )
+                            }
+                        };
+                        if describe.first().is_none_or(|program| program.is_empty()) {
+                            return Err(anyhow!(
+                                "`{}` must specify a program, like `{}`",
+                                style_stderr!(bold(), "config.path.describe"),
+                                style_stderr!(bold(), "\"whatis\""),
+                            ));
+                        }
+                        Ok(describe)
+                    })
+                    .transpose()?;
+
+                let shell = table
+                    .get("shell")
+                    .map(try_into_boolean("config.path.shell"))
+                    .transpose()?
+                    .unwrap_or(false);
+
+                let hide_duplicates = table
+                    .get("hide-duplicates")
+                    .map(try_into_boolean("config.path.hide-duplicates"))
+                    .transpose()?
+                    .unwrap_or(false);
+
                 Ok(Self::Enabled {
                     path,
                     env,
                     replace,
                     recursive,
                     group,
+                    describe,
+                    shell,
+                    hide_duplicates,
                 })
             }
             other => type_error(
-                "config.numbered.separator",
+                "config.path",
                 &["boolean", "array", "table"],
                 other.type_str(),
             ),
@@ -580,6 +3236,9 @@ impl TryFrom<&Value> for BinPath {
 
 #[derive(Debug, Default, Clone)]
 pub struct Dmenu {
+    pub command: Option<Vec<ImStr>>,
+    pub wayland_command: Option<Vec<ImStr>>,
+    pub x11_command: Option<Vec<ImStr>>,
     pub prompt: Option<ImStr>,
     pub font: Option<ImStr>,
     pub background: Option<ImStr>,
@@ -590,31 +3249,205 @@ pub struct Dmenu {
     pub bottom: bool,
     pub case_sensitive: bool,
     pub fast: bool,
+    /// Center the menu on the screen; `-c` from dmenu's center patch.
+    pub center: bool,
+    /// Hide what's typed, for menus that ask for a secret; `-P` from dmenu's password patch.
+    pub password: bool,
+    /// Select an entry as soon as it's the only match; `-n` from dmenu's instant patch.
+    pub instant: bool,
+    /// Refuse input that matches no entry; `-R` from dmenu's reject-no-match patch.
+    pub reject_no_match: bool,
     pub monitor: Option<u64>,
     pub window_id: Option<ImStr>,
+    /// Append the number of entries in the menu to the prompt.
+    pub show_count: bool,
+    /// Text to prefill the search box with, for backends that can.
+    pub query: Option<ImStr>,
+    /// A full command line to launch dmenu with in place of `command`; see [`Dmenu::argv`].
+    pub template: Option<Vec<ImStr>>,
 }
 
+/// A `config.dmenu` key, the flag it's translated to, and whether to pass the flag.
+type BackendFlag = (&'static str, &'static str, bool);
+/// A `config.dmenu` key, the option it's translated to, and the option's value if it's set.
+type BackendOption<'a> = (&'static str, &'static str, Option<Cow<'a, str>>);
+
 impl Dmenu {
-    pub fn args(&self) -> Vec<Cow<'_, str>> {
-        let mut args = Vec::with_capacity(12);
-
-        let options = [
-            ("-p", self.prompt.as_deref().map(Cow::from)),
-            ("-fn", self.font.as_deref().map(Cow::from)),
-            ("-nb", self.background.as_deref().map(Cow::from)),
-            ("-nf", self.foreground.as_deref().map(Cow::from)),
-            ("-sb", self.selected_background.as_deref().map(Cow::from)),
-            ("-sf", self.selected_foreground.as_deref().map(Cow::from)),
-            ("-w", self.window_id.as_deref().map(Cow::from)),
-            ("-l", self.lines.map(|int| Cow::from(int.to_string()))),
-            ("-m", self.monitor.map(|int| Cow::from(int.to_string()))),
-        ];
+    /// Choose the command used to launch dmenu.
+    ///
+    /// Under a wayland or x11 session, the matching session specific command is preferred.
+    /// Otherwise, the plain command is used, defaulting to the backend's usual command.
+    pub fn command(&self, backend: Backend) -> &[ImStr] {
+        let session_command = match Session::detect() {
+            Some(Session::Wayland) => self.wayland_command.as_deref(),
+            Some(Session::X11) => self.x11_command.as_deref(),
+            None => None,
+        };
+
+        session_command
+            .or(self.command.as_deref())
+            .unwrap_or(backend.default_command())
+    }
+
+    /// The full command line that launches dmenu, given the `args` translated for `backend`.
+    ///
+    /// If `template` is set, it's used in place of any command: an element that's exactly
+    /// `{args}` is replaced by the arguments, and an `{args}` within a longer element
+    /// is replaced by the arguments quoted for a shell. Without an `{args}`, they're left out.
+    /// Otherwise, the arguments follow the command chosen by [`Dmenu::command`].
+    ///
+    /// ```
+    /// use std::borrow::Cow;
+    /// use dmm::config::{Backend, Dmenu};
+    /// use dmm::imstr::ImStr;
+    ///
+    /// let args = [Cow::from("-p"), Cow::from("run it:")];
+    /// let template = |template: &[&str]| Dmenu {
+    ///     template: Some(template.iter().copied().map(ImStr::from).collect()),
+    ///     ..Dmenu::default()
+    /// };
+    ///
+    /// let dmenu = template(&["my-launcher", "--dmenu", "{args}", "--last"]);
+    /// assert_eq!(
+    ///     dmenu.argv(Backend::Dmenu, &args),
+    ///     ["my-launcher", "--dmenu", "-p", "run it:", "--last"],
+    /// );
+    ///
+    /// let dmenu = template(&["sh", "-c", "dmenu {args} | tee -a ~/dmenu.log"]);
+    /// assert_eq!(
+    ///     dmenu.argv(Backend::Dmenu, &args),
+    ///     ["sh", "-c", "dmenu -p 'run it:' | tee -a ~/dmenu.log"],
+    /// );
+    /// ```
+    pub fn argv<'a>(&'a self, backend: Backend, args: &[Cow<'a, str>]) -> Vec<Cow<'a, str>> {
+        let Some(template) = &self.template else {
+            return self
+                .command(backend)
+                .iter()
+                .map(|arg| Cow::from(arg.as_str()))
+                .chain(args.iter().cloned())
+                .collect();
+        };
 
-        self.bottom.then(|| args.push(Cow::from("-b")));
-        (!self.case_sensitive).then(|| args.push(Cow::from("-i")));
-        self.fast.then(|| args.push(Cow::from("-f")));
+        let mut argv = Vec::with_capacity(template.len() + args.len());
+        for arg in template {
+            if arg.as_str() == "{args}" {
+                argv.extend(args.iter().cloned());
+            } else if arg.contains("{args}") {
+                let quoted = args
+                    .iter()
+                    .map(|arg| shell_quote(arg))
+                    .collect::<Vec<Cow<'_, str>>>()
+                    .join(" ");
+                argv.push(Cow::from(arg.replace("{args}", &quoted)));
+            } else {
+                argv.push(Cow::from(arg.as_str()));
+            }
+        }
+        argv
+    }
+
+    /// Replace each color option that names a color in the `colors` palette with its value.
+    ///
+    /// Colors that aren't in the palette, like `#1e1e2e`, are left as they are.
+    ///
+    /// ```
+    /// use ahash::HashMap;
+    /// use dmm::config::{Backend, Dmenu};
+    /// use dmm::imstr::ImStr;
+    ///
+    /// let colors = [(ImStr::new("base"), ImStr::new("#1e1e2e"))]
+    ///     .into_iter()
+    ///     .collect::<HashMap<ImStr, ImStr>>();
+    /// let mut dmenu = Dmenu {
+    ///     background: Some(ImStr::new("base")),
+    ///     foreground: Some(ImStr::new("#cdd6f4")),
+    ///     ..Dmenu::default()
+    /// };
+    /// dmenu.resolve_colors(&colors);
+    ///
+    /// assert_eq!(
+    ///     dmenu.args(Backend::Dmenu),
+    ///     ["-i", "-nb", "#1e1e2e", "-nf", "#cdd6f4"],
+    /// );
+    /// ```
+    pub fn resolve_colors(&mut self, colors: &HashMap<ImStr, ImStr>) {
+        for color in [
+            &mut self.background,
+            &mut self.foreground,
+            &mut self.selected_background,
+            &mut self.selected_foreground,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if let Some(value) = colors.get(color) {
+                *color = value.clone();
+            }
+        }
+    }
+
+    /// Translate the options into arguments understood by `backend`.
+    ///
+    /// Options that `backend` has no equivalent for are left out; see [`Dmenu::unsupported`].
+    ///
+    /// ```
+    /// use dmm::config::{Backend, Dmenu};
+    /// use dmm::imstr::ImStr;
+    ///
+    /// let dmenu = Dmenu {
+    ///     prompt: Some(ImStr::new("run:")),
+    ///     background: Some(ImStr::new("#101010")),
+    ///     selected_foreground: Some(ImStr::new("#f0f0f0")),
+    ///     ..Dmenu::default()
+    /// };
+    ///
+    /// assert_eq!(
+    ///     dmenu.args(Backend::Dmenu),
+    ///     ["-i", "-p", "run:", "-nb", "#101010", "-sf", "#f0f0f0"],
+    /// );
+    /// # #[cfg(feature = "rofi")]
+    /// assert_eq!(dmenu.args(Backend::Rofi), ["-i", "-p", "run:"]);
+    /// # #[cfg(feature = "wofi")]
+    /// assert_eq!(dmenu.args(Backend::Wofi), ["--insensitive", "--prompt", "run:"]);
+    ///
+    /// // Wofi is case sensitive unless it's told otherwise, like dmenu.
+    /// # #[cfg(feature = "wofi")]
+    /// {
+    /// let dmenu = Dmenu {
+    ///     case_sensitive: true,
+    ///     lines: Some(10),
+    ///     ..Dmenu::default()
+    /// };
+    /// assert_eq!(dmenu.args(Backend::Wofi), ["--lines", "10"]);
+    /// }
+    ///
+    /// // Bemenu colors each part of the menu, and needs `#rrggbb` colors.
+    /// # #[cfg(feature = "bemenu")]
+    /// {
+    /// let dmenu = Dmenu {
+    ///     background: Some(ImStr::new("#222")),
+    ///     selected_foreground: Some(ImStr::new("#eeeeee")),
+    ///     bottom: true,
+    ///     ..Dmenu::default()
+    /// };
+    /// assert_eq!(
+    ///     dmenu.args(Backend::Bemenu),
+    ///     ["-b", "-i", "--nb", "#222222", "--fb", "#222222", "--hf", "#eeeeee", "--tf", "#eeeeee"],
+    /// );
+    /// }
+    /// ```
+    pub fn args(&self, backend: Backend) -> Vec<Cow<'_, str>> {
+        let (flags, options) = self.translate(backend);
+        let mut args = Vec::with_capacity(flags.len() + options.len() * 2);
 
-        for (flag, option) in options {
+        for (_, flag, enabled) in flags {
+            if enabled {
+                args.push(Cow::from(flag));
+            }
+        }
+
+        for (_, flag, option) in options {
             if let Some(option) = option {
                 args.extend([Cow::from(flag), option]);
             }
@@ -622,28 +3455,464 @@ impl Dmenu {
 
         args
     }
+
+    /// The keys of any options that are set, but that `backend` has no equivalent for.
+    ///
+    /// ```
+    /// use dmm::config::{Backend, Dmenu};
+    /// use dmm::imstr::ImStr;
+    ///
+    /// let dmenu = Dmenu {
+    ///     font: Some(ImStr::new("monospace")),
+    ///     background: Some(ImStr::new("#101010")),
+    ///     foreground: Some(ImStr::new("#f0f0f0")),
+    ///     ..Dmenu::default()
+    /// };
+    ///
+    /// assert!(dmenu.unsupported(Backend::Dmenu).is_empty());
+    /// # #[cfg(feature = "rofi")]
+    /// assert_eq!(dmenu.unsupported(Backend::Rofi), ["background", "foreground"]);
+    /// # #[cfg(feature = "wofi")]
+    /// assert_eq!(dmenu.unsupported(Backend::Wofi), ["font", "background", "foreground"]);
+    ///
+    /// // Only rofi and fuzzel can prefill their search box.
+    /// let dmenu = Dmenu {
+    ///     query: Some(ImStr::new("ssh ")),
+    ///     ..Dmenu::default()
+    /// };
+    /// assert_eq!(dmenu.unsupported(Backend::Dmenu), ["query"]);
+    /// # #[cfg(feature = "rofi")]
+    /// assert!(dmenu.unsupported(Backend::Rofi).is_empty());
+    ///
+    /// // Only dmenu can grab the keyboard before reading its input, with `-f`.
+    /// let dmenu = Dmenu {
+    ///     fast: true,
+    ///     ..Dmenu::default()
+    /// };
+    /// assert!(dmenu.unsupported(Backend::Dmenu).is_empty());
+    /// assert_eq!(dmenu.args(Backend::Dmenu), ["-i", "-f"]);
+    /// # #[cfg(feature = "fuzzel")]
+    /// assert_eq!(dmenu.unsupported(Backend::Fuzzel), ["fast"]);
+    /// # #[cfg(feature = "fuzzel")]
+    /// assert!(dmenu.args(Backend::Fuzzel).is_empty());
+    ///
+    /// // Flags from dmenu patches map to rofi's and fuzzel's own equivalents, where they exist.
+    /// let dmenu = Dmenu {
+    ///     center: true,
+    ///     password: true,
+    ///     ..Dmenu::default()
+    /// };
+    /// assert_eq!(dmenu.args(Backend::Dmenu), ["-i", "-c", "-P"]);
+    /// # #[cfg(feature = "rofi")]
+    /// assert_eq!(dmenu.args(Backend::Rofi), ["-i", "-password"]);
+    /// # #[cfg(feature = "rofi")]
+    /// assert_eq!(dmenu.unsupported(Backend::Rofi), ["center"]);
+    /// ```
+    pub fn unsupported(&self, backend: Backend) -> Vec<&'static str> {
+        let set = [
+            ("prompt", self.prompt.is_some()),
+            ("font", self.font.is_some()),
+            ("background", self.background.is_some()),
+            ("foreground", self.foreground.is_some()),
+            ("selected-background", self.selected_background.is_some()),
+            ("selected-foreground", self.selected_foreground.is_some()),
+            ("lines", self.lines.is_some()),
+            ("bottom", self.bottom),
+            ("case-sensitive", self.case_sensitive),
+            ("fast", self.fast),
+            ("center", self.center),
+            ("password", self.password),
+            ("instant", self.instant),
+            ("reject-no-match", self.reject_no_match),
+            ("monitor", self.monitor.is_some()),
+            ("window-id", self.window_id.is_some()),
+            ("query", self.query.is_some()),
+        ];
+        let (flags, options) = self.translate(backend);
+        let supported = flags
+            .iter()
+            .map(|&(key, _, _)| key)
+            .chain(options.iter().map(|&(key, _, _)| key))
+            .collect::<Vec<&str>>();
+
+        set.into_iter()
+            .filter(|&(key, is_set)| is_set && !supported.contains(&key))
+            .map(|(key, _)| key)
+            .collect()
+    }
+
+    /// The flags and options `backend` has equivalents for, each with the key it comes from.
+    fn translate(&self, backend: Backend) -> (Vec<BackendFlag>, Vec<BackendOption<'_>>) {
+        fn string(option: &Option<ImStr>) -> Option<Cow<'_, str>> {
+            option.as_deref().map(Cow::from)
+        }
+        let integer = |option: Option<u64>| option.map(|int| Cow::from(int.to_string()));
+
+        match backend {
+            Backend::Dmenu => (
+                vec![
+                    ("bottom", "-b", self.bottom),
+                    ("case-sensitive", "-i", !self.case_sensitive),
+                    ("fast", "-f", self.fast),
+                    ("center", "-c", self.center),
+                    ("password", "-P", self.password),
+                    ("instant", "-n", self.instant),
+                    ("reject-no-match", "-R", self.reject_no_match),
+                ],
+                vec![
+                    ("prompt", "-p", string(&self.prompt)),
+                    ("font", "-fn", string(&self.font)),
+                    ("background", "-nb", string(&self.background)),
+                    ("foreground", "-nf", string(&self.foreground)),
+                    (
+                        "selected-background",
+                        "-sb",
+                        string(&self.selected_background),
+                    ),
+                    (
+                        "selected-foreground",
+                        "-sf",
+                        string(&self.selected_foreground),
+                    ),
+                    ("window-id", "-w", string(&self.window_id)),
+                    ("lines", "-l", integer(self.lines)),
+                    ("monitor", "-m", integer(self.monitor)),
+                ],
+            ),
+            // Rofi takes its colors from its theme.
+            #[cfg(feature = "rofi")]
+            Backend::Rofi => (
+                vec![
+                    ("case-sensitive", "-i", !self.case_sensitive),
+                    ("password", "-password", self.password),
+                    ("instant", "-auto-select", self.instant),
+                    ("reject-no-match", "-no-custom", self.reject_no_match),
+                ],
+                vec![
+                    ("prompt", "-p", string(&self.prompt)),
+                    ("query", "-filter", string(&self.query)),
+                    ("font", "-font", string(&self.font)),
+                    ("window-id", "-w", string(&self.window_id)),
+                    ("lines", "-l", integer(self.lines)),
+                    ("monitor", "-m", integer(self.monitor)),
+                ],
+            ),
+            #[cfg(feature = "fuzzel")]
+            Backend::Fuzzel => {
+                fn color(option: &Option<ImStr>) -> Option<Cow<'_, str>> {
+                    option.as_deref().map(fuzzel_color)
+                }
+                (
+                    vec![("password", "--password", self.password)],
+                    vec![
+                        ("prompt", "--prompt", string(&self.prompt)),
+                        ("query", "--search", string(&self.query)),
+                        ("font", "--font", string(&self.font)),
+                        ("background", "--background-color", color(&self.background)),
+                        ("foreground", "--text-color", color(&self.foreground)),
+                        (
+                            "selected-background",
+                            "--selection-color",
+                            color(&self.selected_background),
+                        ),
+                        (
+                            "selected-foreground",
+                            "--selection-text-color",
+                            color(&self.selected_foreground),
+                        ),
+                        ("lines", "--lines", integer(self.lines)),
+                    ],
+                )
+            }
+            // Wofi takes its colors and fonts from its stylesheet.
+            #[cfg(feature = "wofi")]
+            Backend::Wofi => (
+                vec![
+                    ("case-sensitive", "--insensitive", !self.case_sensitive),
+                    ("password", "--password", self.password),
+                ],
+                vec![
+                    ("prompt", "--prompt", string(&self.prompt)),
+                    ("query", "--search", string(&self.query)),
+                    ("lines", "--lines", integer(self.lines)),
+                ],
+            ),
+            // Bemenu has a color for each part of the menu, so dmenu's colors are spread over them:
+            // the prompt takes the selected colors like it does in dmenu, and the input the normal ones.
+            #[cfg(feature = "bemenu")]
+            Backend::Bemenu => {
+                fn color(option: &Option<ImStr>) -> Option<Cow<'_, str>> {
+                    option.as_deref().map(bemenu_color)
+                }
+                (
+                    vec![
+                        ("bottom", "-b", self.bottom),
+                        ("case-sensitive", "-i", !self.case_sensitive),
+                        ("center", "-c", self.center),
+                        ("password", "-x", self.password),
+                    ],
+                    vec![
+                        ("prompt", "-p", string(&self.prompt)),
+                        ("font", "--fn", string(&self.font)),
+                        ("background", "--nb", color(&self.background)),
+                        ("background", "--fb", color(&self.background)),
+                        ("foreground", "--nf", color(&self.foreground)),
+                        ("foreground", "--ff", color(&self.foreground)),
+                        (
+                            "selected-background",
+                            "--hb",
+                            color(&self.selected_background),
+                        ),
+                        (
+                            "selected-background",
+                            "--tb",
+                            color(&self.selected_background),
+                        ),
+                        (
+                            "selected-foreground",
+                            "--hf",
+                            color(&self.selected_foreground),
+                        ),
+                        (
+                            "selected-foreground",
+                            "--tf",
+                            color(&self.selected_foreground),
+                        ),
+                        ("lines", "-l", integer(self.lines)),
+                        ("monitor", "-m", integer(self.monitor)),
+                    ],
+                )
+            }
+        }
+    }
+}
+
+/// Convert a `#rrggbb` color into the `rrggbbaa` format used by fuzzel.
+#[cfg(feature = "fuzzel")]
+fn fuzzel_color(color: &str) -> Cow<'_, str> {
+    let color = color.strip_prefix('#').unwrap_or(color);
+    if color.len() == 6 {
+        Cow::from(format!("{color}ff"))
+    } else {
+        Cow::from(color)
+    }
+}
+
+/// Expand a `#rgb` color, which dmenu accepts, into the `#rrggbb` format bemenu requires.
+#[cfg(feature = "bemenu")]
+fn bemenu_color(color: &str) -> Cow<'_, str> {
+    match color.strip_prefix('#') {
+        Some(rgb) if rgb.len() == 3 && rgb.chars().all(|c| c.is_ascii_hexdigit()) => {
+            Cow::from(rgb.chars().fold(String::from("#"), |mut color, c| {
+                color.extend([c, c]);
+                color
+            }))
+        }
+        _ => Cow::from(color),
+    }
 }
 
 impl ConfigItem for Dmenu {
     fn name() -> &'static str {
         "dmenu"
     }
-    fn merge(self, default: Self) -> Self {
+    fn merge(self, default: Self) -> Self {
+        Self {
+            command: self.command.or(default.command),
+            wayland_command: self.wayland_command.or(default.wayland_command),
+            x11_command: self.x11_command.or(default.x11_command),
+            prompt: self.prompt.or(default.prompt),
+            font: self.font.or(default.font),
+            background: self.background.or(default.background),
+            foreground: self.foreground.or(default.foreground),
+            selected_background: self.selected_background.or(default.selected_background),
+            selected_foreground: self.selected_foreground.or(default.selected_foreground),
+            lines: self.lines.or(default.lines),
+            bottom: self.bottom || default.bottom,
+            case_sensitive: self.case_sensitive || default.case_sensitive,
+            fast: self.fast || default.fast,
+            center: self.center || default.center,
+            password: self.password || default.password,
+            instant: self.instant || default.instant,
+            reject_no_match: self.reject_no_match || default.reject_no_match,
+            monitor: self.monitor.or(default.monitor),
+            window_id: self.window_id.or(default.window_id),
+            show_count: self.show_count || default.show_count,
+            query: self.query.or(default.query),
+            template: self.template.or(default.template),
+        }
+    }
+    fn to_value(&self) -> Value {
+        let commands = [
+            ("command", &self.command),
+            ("wayland-command", &self.wayland_command),
+            ("x11-command", &self.x11_command),
+            ("template", &self.template),
+        ];
+        let strings = [
+            ("prompt", &self.prompt),
+            ("font", &self.font),
+            ("background", &self.background),
+            ("foreground", &self.foreground),
+            ("selected-background", &self.selected_background),
+            ("selected-foreground", &self.selected_foreground),
+            ("window-id", &self.window_id),
+            ("query", &self.query),
+        ];
+        let integers = [("lines", self.lines), ("monitor", self.monitor)];
+        let booleans = [
+            ("bottom", self.bottom),
+            ("case-sensitive", self.case_sensitive),
+            ("fast", self.fast),
+            ("center", self.center),
+            ("password", self.password),
+            ("instant", self.instant),
+            ("reject-no-match", self.reject_no_match),
+            ("show-count", self.show_count),
+        ];
+
+        let mut dmenu = Map::new();
+        for (key, command) in commands {
+            if let Some(command) = command {
+                dmenu.insert(String::from(key), strings_to_value(command));
+            }
+        }
+        for (key, string) in strings {
+            if let Some(string) = string {
+                dmenu.insert(String::from(key), Value::String(string.to_string()));
+            }
+        }
+        for (key, integer) in integers {
+            if let Some(integer) = integer {
+                dmenu.insert(String::from(key), Value::Integer(integer as i64));
+            }
+        }
+        for (key, boolean) in booleans {
+            dmenu.insert(String::from(key), Value::Boolean(boolean));
+        }
+
+        Value::Table(dmenu)
+    }
+}
+
+impl Dmenu {
+    /// The options that can be overridden from the command line, each with its value's name.
+    const CLI_STRINGS: [(&'static str, &'static str); 7] = [
+        ("prompt", "TEXT"),
+        ("font", "FONT"),
+        ("background", "COLOR"),
+        ("foreground", "COLOR"),
+        ("selected-background", "COLOR"),
+        ("selected-foreground", "COLOR"),
+        ("window-id", "ID"),
+    ];
+    const CLI_INTEGERS: [(&'static str, &'static str); 2] = [("lines", "N"), ("monitor", "N")];
+    /// Each boolean option, with the flag that disables it.
+    const CLI_BOOLEANS: [(&'static str, &'static str); 8] = [
+        ("bottom", "no-bottom"),
+        ("case-sensitive", "no-case-sensitive"),
+        ("fast", "no-fast"),
+        ("center", "no-center"),
+        ("password", "no-password"),
+        ("instant", "no-instant"),
+        ("reject-no-match", "no-reject-no-match"),
+        ("show-count", "no-show-count"),
+    ];
+
+    /// A flag for each option that can be overridden from the command line,
+    /// named after its key in `config.dmenu`.
+    /// Boolean options also have a `--no-` flag, and whichever of the two is given last wins.
+    fn args_from_cli() -> Vec<Arg> {
+        let help = |key: &str| format!("Override `config.dmenu.{key}`");
+        let strings = Self::CLI_STRINGS
+            .iter()
+            .map(|&(key, value)| Arg::new(key).help(help(key)).long(key).value_name(value));
+        let integers = Self::CLI_INTEGERS.iter().map(|&(key, value)| {
+            Arg::new(key)
+                .help(help(key))
+                .long(key)
+                .value_name(value)
+                .value_parser(clap::value_parser!(u64))
+        });
+        let booleans = Self::CLI_BOOLEANS.iter().flat_map(|&(key, no_key)| {
+            [
+                Arg::new(key)
+                    .help(format!("Enable `config.dmenu.{key}`"))
+                    .long(key)
+                    .action(ArgAction::SetTrue)
+                    .overrides_with(no_key),
+                Arg::new(no_key)
+                    .help(format!("Disable `config.dmenu.{key}`"))
+                    .long(no_key)
+                    .action(ArgAction::SetTrue)
+                    .overrides_with(key),
+            ]
+        });
+
+        strings.chain(integers).chain(booleans).collect()
+    }
+
+    /// Override these options with the ones given on the command line.
+    fn override_from_cli(self, args: &ArgMatches) -> Self {
+        let string = |key: &str, configured: Option<ImStr>| {
+            args.get_one::<String>(key)
+                .map(|value| ImStr::from(value.as_str()))
+                .or(configured)
+        };
+        let integer =
+            |key: &str, configured: Option<u64>| args.get_one::<u64>(key).copied().or(configured);
+        let boolean = |key: &str, configured: bool| {
+            (configured || args.get_flag(key)) && !args.get_flag(&format!("no-{key}"))
+        };
+
         Self {
-            prompt: self.prompt.or(default.prompt),
-            font: self.font.or(default.font),
-            background: self.background.or(default.background),
-            foreground: self.foreground.or(default.foreground),
-            selected_background: self.selected_background.or(default.selected_background),
-            selected_foreground: self.selected_foreground.or(default.selected_foreground),
-            lines: self.lines.or(default.lines),
-            bottom: self.bottom || default.bottom,
-            case_sensitive: self.case_sensitive || default.case_sensitive,
-            fast: self.fast || default.fast,
-            monitor: self.monitor.or(default.monitor),
-            window_id: self.window_id.or(default.window_id),
+            prompt: string("prompt", self.prompt),
+            font: string("font", self.font),
+            background: string("background", self.background),
+            foreground: string("foreground", self.foreground),
+            selected_background: string("selected-background", self.selected_background),
+            selected_foreground: string("selected-foreground", self.selected_foreground),
+            window_id: string("window-id", self.window_id),
+            query: string("query", self.query),
+            lines: integer("lines", self.lines),
+            monitor: integer("monitor", self.monitor),
+            bottom: boolean("bottom", self.bottom),
+            case_sensitive: boolean("case-sensitive", self.case_sensitive),
+            fast: boolean("fast", self.fast),
+            center: boolean("center", self.center),
+            password: boolean("password", self.password),
+            instant: boolean("instant", self.instant),
+            reject_no_match: boolean("reject-no-match", self.reject_no_match),
+            show_count: boolean("show-count", self.show_count),
+            ..self
         }
     }
+
+    /// The keys allowed in `config.dmenu`.
+    const KEYS: [&'static str; 22] = [
+        "command",
+        "wayland-command",
+        "x11-command",
+        "prompt",
+        "font",
+        "background",
+        "foreground",
+        "selected-background",
+        "selected-foreground",
+        "lines",
+        "bottom",
+        "case-sensitive",
+        "fast",
+        "center",
+        "password",
+        "instant",
+        "reject-no-match",
+        "monitor",
+        "window-id",
+        "show-count",
+        "query",
+        "template",
+    ];
 }
 
 impl TryFrom<&Value> for Dmenu {
@@ -652,6 +3921,18 @@ impl TryFrom<&Value> for Dmenu {
         let dmenu = try_into_table("config.dmenu")(dmenu)?;
 
         Ok(Self {
+            command: dmenu
+                .get("command")
+                .map(try_into_command("config.dmenu.command"))
+                .transpose()?,
+            wayland_command: dmenu
+                .get("wayland-command")
+                .map(try_into_command("config.dmenu.wayland-command"))
+                .transpose()?,
+            x11_command: dmenu
+                .get("x11-command")
+                .map(try_into_command("config.dmenu.x11-command"))
+                .transpose()?,
             prompt: dmenu
                 .get("prompt")
                 .map(try_into_string("config.dmenu.prompt"))
@@ -697,6 +3978,26 @@ impl TryFrom<&Value> for Dmenu {
                 .map(try_into_boolean("config.dmenu.fast"))
                 .transpose()?
                 .unwrap_or(false),
+            center: dmenu
+                .get("center")
+                .map(try_into_boolean("config.dmenu.center"))
+                .transpose()?
+                .unwrap_or(false),
+            password: dmenu
+                .get("password")
+                .map(try_into_boolean("config.dmenu.password"))
+                .transpose()?
+                .unwrap_or(false),
+            instant: dmenu
+                .get("instant")
+                .map(try_into_boolean("config.dmenu.instant"))
+                .transpose()?
+                .unwrap_or(false),
+            reject_no_match: dmenu
+                .get("reject-no-match")
+                .map(try_into_boolean("config.dmenu.reject-no-match"))
+                .transpose()?
+                .unwrap_or(false),
             monitor: dmenu
                 .get("monitor")
                 .map(try_into_integer("config.dmenu.monitor"))
@@ -707,21 +4008,256 @@ impl TryFrom<&Value> for Dmenu {
                 .get("window-id")
                 .map(try_into_string("config.dmenu.window-id"))
                 .transpose()?,
+            show_count: dmenu
+                .get("show-count")
+                .map(try_into_boolean("config.dmenu.show-count"))
+                .transpose()?
+                .unwrap_or(false),
+            query: dmenu
+                .get("query")
+                .map(try_into_string("config.dmenu.query"))
+                .transpose()?,
+            template: dmenu
+                .get("template")
+                .map(try_into_command("config.dmenu.template"))
+                .transpose()?,
         })
     }
 }
 
+/// The program used to display the menu, which decides how `config.dmenu` is translated into
+/// arguments.
+///
+/// Backends other than dmenu are each behind a cargo feature of the same name.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    #[default]
+    Dmenu,
+    #[cfg(feature = "rofi")]
+    Rofi,
+    #[cfg(feature = "fuzzel")]
+    Fuzzel,
+    #[cfg(feature = "wofi")]
+    Wofi,
+    #[cfg(feature = "bemenu")]
+    Bemenu,
+}
+
+impl Backend {
+    /// The names of all backends, including those not compiled into this build.
+    const NAMES: &'static [&'static str] = &["dmenu", "rofi", "fuzzel", "wofi", "bemenu"];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Dmenu => "dmenu",
+            #[cfg(feature = "rofi")]
+            Self::Rofi => "rofi",
+            #[cfg(feature = "fuzzel")]
+            Self::Fuzzel => "fuzzel",
+            #[cfg(feature = "wofi")]
+            Self::Wofi => "wofi",
+            #[cfg(feature = "bemenu")]
+            Self::Bemenu => "bemenu",
+        }
+    }
+
+    /// Whether the backend can display pango markup in its entries, like the spans of
+    /// `config.group-colors`.
+    pub const fn supports_markup(self) -> bool {
+        match self {
+            Self::Dmenu => false,
+            #[cfg(feature = "rofi")]
+            Self::Rofi => true,
+            #[cfg(feature = "fuzzel")]
+            Self::Fuzzel => false,
+            #[cfg(feature = "wofi")]
+            Self::Wofi => false,
+            #[cfg(feature = "bemenu")]
+            Self::Bemenu => false,
+        }
+    }
+
+    /// Whether the backend can bind custom keys, reporting which was pressed in its exit status,
+    /// like the keys of `config.accelerators`.
+    pub const fn supports_accelerators(self) -> bool {
+        match self {
+            Self::Dmenu => false,
+            #[cfg(feature = "rofi")]
+            Self::Rofi => true,
+            #[cfg(feature = "fuzzel")]
+            Self::Fuzzel => false,
+            #[cfg(feature = "wofi")]
+            Self::Wofi => false,
+            #[cfg(feature = "bemenu")]
+            Self::Bemenu => false,
+        }
+    }
+
+    /// The flag that preselects a menu line by its index, like the line of `config.default`,
+    /// or `None` if the backend can't preselect a line.
+    pub const fn preselect_flag(self) -> Option<&'static str> {
+        match self {
+            Self::Dmenu => None,
+            #[cfg(feature = "rofi")]
+            Self::Rofi => Some("-selected-row"),
+            #[cfg(feature = "fuzzel")]
+            Self::Fuzzel => Some("--select-index"),
+            #[cfg(feature = "wofi")]
+            Self::Wofi => None,
+            #[cfg(feature = "bemenu")]
+            Self::Bemenu => None,
+        }
+    }
+
+    /// The command used to launch the backend if `config.dmenu.command` isn't set.
+    pub const fn default_command(self) -> &'static [ImStr] {
+        const DMENU: &[ImStr] = &[ImStr::new("dmenu")];
+        #[cfg(feature = "rofi")]
+        const ROFI: &[ImStr] = &[ImStr::new("rofi"), ImStr::new("-dmenu")];
+        #[cfg(feature = "fuzzel")]
+        const FUZZEL: &[ImStr] = &[ImStr::new("fuzzel"), ImStr::new("--dmenu")];
+        #[cfg(feature = "wofi")]
+        const WOFI: &[ImStr] = &[ImStr::new("wofi"), ImStr::new("--dmenu")];
+        #[cfg(feature = "bemenu")]
+        const BEMENU: &[ImStr] = &[ImStr::new("bemenu")];
+
+        match self {
+            Self::Dmenu => DMENU,
+            #[cfg(feature = "rofi")]
+            Self::Rofi => ROFI,
+            #[cfg(feature = "fuzzel")]
+            Self::Fuzzel => FUZZEL,
+            #[cfg(feature = "wofi")]
+            Self::Wofi => WOFI,
+            #[cfg(feature = "bemenu")]
+            Self::Bemenu => BEMENU,
+        }
+    }
+}
+
+impl ConfigItem for Backend {
+    fn name() -> &'static str {
+        "backend"
+    }
+    fn merge(self, _: Self) -> Self {
+        self
+    }
+    fn to_value(&self) -> Value {
+        Value::String(String::from(Self::name(*self)))
+    }
+}
+
+impl TryFrom<&Value> for Backend {
+    type Error = anyhow::Error;
+    fn try_from(backend: &Value) -> anyhow::Result<Self> {
+        let backend = try_into_string("config.backend")(backend)?;
+
+        match backend.as_str() {
+            "dmenu" => Ok(Self::Dmenu),
+            #[cfg(feature = "rofi")]
+            "rofi" => Ok(Self::Rofi),
+            #[cfg(feature = "fuzzel")]
+            "fuzzel" => Ok(Self::Fuzzel),
+            #[cfg(feature = "wofi")]
+            "wofi" => Ok(Self::Wofi),
+            #[cfg(feature = "bemenu")]
+            "bemenu" => Ok(Self::Bemenu),
+            name if Self::NAMES.contains(&name) => Err(anyhow!(
+                "the `{}` backend isn't available in this build of dmm; \
+                 it must be built with the `{}` feature enabled",
+                style_stderr!(bold(), "{name}"),
+                style_stderr!(bold(), "{name}"),
+            )),
+            name => Err(anyhow!(
+                "`{}` must be one of {}, but is `{}`",
+                style_stderr!(bold(), "config.backend"),
+                Self::NAMES
+                    .iter()
+                    .map(|name| format!("`{}`", style_stderr!(bold(), "{name}")))
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                style_stderr!(bold(), "{name}"),
+            )),
+        }
+    }
+}
+
+/// The type of graphical session dmm is running in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Session {
+    Wayland,
+    X11,
+}
+
+impl Session {
+    /// Detect the session type from `XDG_SESSION_TYPE`,
+    /// falling back to checking for `WAYLAND_DISPLAY` or `DISPLAY`.
+    pub fn detect() -> Option<Self> {
+        match env::var("XDG_SESSION_TYPE").as_deref() {
+            Ok("wayland") => Some(Self::Wayland),
+            Ok("x11") => Some(Self::X11),
+            _ => {
+                if env::var_os("WAYLAND_DISPLAY").is_some() {
+                    Some(Self::Wayland)
+                } else if env::var_os("DISPLAY").is_some() {
+                    Some(Self::X11)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub args: ArgMatches,
-    pub dirs: ProjectDirs,
-    pub base_dirs: BaseDirs,
+    /// `None` if no home directory could be detected.
+    pub dirs: Option<ProjectDirs>,
+    /// `None` if no home directory could be detected.
+    pub base_dirs: Option<BaseDirs>,
     pub entries: Vec<Entry>,
     pub shell: Shell,
     pub custom: Custom,
     pub numbered: Numbered,
+    pub group_headers: GroupHeaders,
+    pub group_colors: GroupColors,
+    pub group_order: GroupOrder,
+    pub timeout: Timeout,
+    pub wait: Wait,
+    pub retry: Retry,
+    pub menu_timeout: MenuTimeout,
+    pub dedup_selections: DedupSelections,
+    pub setsid: Setsid,
+    pub pty: Pty,
+    pub glob_args: GlobArgs,
+    pub bell: Bell,
+    pub accelerators: Accelerators,
+    pub default: DefaultEntry,
+    pub systemd_scope: SystemdScope,
+    pub clean_env: CleanEnv,
+    pub env_passthrough: EnvPassthrough,
+    pub env_file: EnvFile,
+    /// The variables loaded from `env_file`, in the order they were written.
+    pub env: Vec<(ImStr, ImStr)>,
+    pub tag: TagMode,
+    pub normalize_names: NormalizeNames,
+    pub fold_matching: FoldMatching,
+    pub sort: Sort,
+    pub sort_reverse: SortReverse,
+    pub sort_case_sensitive: SortCaseSensitive,
+    pub frecency: Frecency,
+    pub history_max: HistoryMax,
     pub path: BinPath,
+    pub backend: Backend,
     pub dmenu: Dmenu,
+    pub cache_dir: CacheDir,
+    pub cache: Cache,
+    pub groups: HashMap<ImStr, i64>,
+    /// Named colors that the color options of `config.dmenu` can refer to.
+    pub colors: HashMap<ImStr, ImStr>,
+    /// Problems with the config that don't prevent it from being used.
+    pub warnings: Vec<Rc<anyhow::Error>>,
 }
 
 impl Config {
@@ -729,36 +4265,414 @@ impl Config {
         config: &Value,
         home_config: Option<&Value>,
         args: ArgMatches,
-        dirs: ProjectDirs,
-        base_dirs: BaseDirs,
+        dirs: Option<ProjectDirs>,
+        base_dirs: Option<BaseDirs>,
     ) -> anyhow::Result<Self> {
-        let config_path = dirs.config_dir().join("config.toml");
+        let config_path = dirs
+            .as_ref()
+            .map(|dirs| home_config_path(dirs.config_dir()))
+            .unwrap_or_default();
+        let groups = try_get_groups(config, home_config, &config_path)?;
+        let mut warnings = unknown_keys(config, target_config_error());
+        if let Some(home_config) = home_config {
+            warnings.extend(unknown_keys(home_config, &home_config_error(&config_path)));
+
+            let candidates = dirs
+                .as_ref()
+                .map(|dirs| home_config_candidates(dirs.config_dir()))
+                .unwrap_or_default();
+            if let [_, ignored @ ..] = candidates.as_slice() {
+                if !ignored.is_empty() {
+                    let ignored = ignored
+                        .iter()
+                        .map(|path| format!("`{}`", style_stderr!(bold(), "{}", path.display())))
+                        .collect::<Vec<String>>()
+                        .join(", ");
+                    warnings.push(Rc::new(
+                        anyhow!("only the first is read, so {ignored} will be ignored")
+                            .context("found more than one home config"),
+                    ));
+                }
+            }
+        }
+        if dirs.is_none() {
+            warnings.push(Rc::new(no_home_error()));
+        }
+
+        let cache_dir = try_get_config::<CacheDir>(config, home_config, &config_path)?;
+        let cache = Cache::new(cache_dir.resolve(dirs.as_ref(), base_dirs.as_ref())?);
+        verbose!(
+            "using cache directory `{}`",
+            cache.dir().map_or_else(
+                || String::from("<disabled>"),
+                |dir| dir.display().to_string()
+            )
+        );
+
+        let normalize_names = try_get_config::<NormalizeNames>(config, home_config, &config_path)?;
+        let env_file = try_get_config::<EnvFile>(config, home_config, &config_path)?;
+        let env = env_file.load(base_dirs.as_ref(), &mut warnings)?;
+
+        let backend = try_get_config::<Backend>(config, home_config, &config_path)?;
+        // Options given on the command line take precedence over every config.
+        let mut dmenu =
+            try_get_config::<Dmenu>(config, home_config, &config_path)?.override_from_cli(&args);
+        let colors = try_get_colors(config, home_config, &config_path)?;
+        dmenu.resolve_colors(&colors);
+        let unsupported = dmenu.unsupported(backend);
+        if !unsupported.is_empty() {
+            let keys = unsupported
+                .iter()
+                .map(|key| format!("`{}`", style_stderr!(bold(), "config.dmenu.{key}")))
+                .collect::<Vec<String>>()
+                .join(", ");
+            let pronoun = if unsupported.len() == 1 {
+                "it's"
+            } else {
+                "they're"
+            };
+            warnings.push(Rc::new(anyhow!(
+                "{} has no equivalent for {keys}, so {pronoun} ignored",
+                backend.name()
+            )));
+        }
+
+        let custom = try_get_config::<Custom>(config, home_config, &config_path)?;
+        if matches!(custom, Custom::Enabled) && dmenu.reject_no_match {
+            warnings.push(Rc::new(anyhow!(
+                "`{}` stops the menu from accepting anything but entries, so `{}` has no effect",
+                style_stderr!(bold(), "config.dmenu.reject-no-match"),
+                style_stderr!(bold(), "config.custom"),
+            )));
+        }
+
+        let group_colors = try_get_config::<GroupColors>(config, home_config, &config_path)?;
+        if group_colors.is_enabled() && !backend.supports_markup() {
+            warnings.push(Rc::new(anyhow!(
+                "{} can't display markup, so `{}` is ignored",
+                backend.name(),
+                style_stderr!(bold(), "config.group-colors"),
+            )));
+        }
+
+        let accelerators = try_get_config::<Accelerators>(config, home_config, &config_path)?;
+        // Checked once merged, since the home config and pattern may each bind some of them.
+        if accelerators.0.len() > Accelerators::MAX {
+            return Err(anyhow!(
+                "`{}` can have at most {} key bindings, but it has {}",
+                style_stderr!(bold(), "config.accelerators"),
+                Accelerators::MAX,
+                accelerators.0.len(),
+            ));
+        }
+        if accelerators.is_enabled() && !backend.supports_accelerators() {
+            warnings.push(Rc::new(anyhow!(
+                "{} can't bind custom keys, so `{}` is ignored",
+                backend.name(),
+                style_stderr!(bold(), "config.accelerators"),
+            )));
+        }
+
+        let path = try_get_config::<BinPath>(config, home_config, &config_path)?;
+        if path.searches_nothing() {
+            warnings.push(Rc::new(anyhow!(
+                "`{}` is enabled without any directories, so nothing will be found; \
+                 set `{}` to search `PATH`",
+                style_stderr!(bold(), "config.path"),
+                style_stderr!(bold(), "config.path.env = true"),
+            )));
+        }
+        let shell = try_get_config::<Shell>(config, home_config, &config_path)?;
+        if matches!(path, BinPath::Enabled { shell: true, .. }) && !shell.is_enabled() {
+            warnings.push(Rc::new(anyhow!(
+                "`{}` is disabled, so `{}` is ignored and programs found run as bare commands",
+                style_stderr!(bold(), "config.shell"),
+                style_stderr!(bold(), "config.path.shell"),
+            )));
+        }
+
         Ok(Self {
-            entries: try_get_entries(config, home_config, &config_path)?,
-            shell: try_get_config::<Shell>(config, home_config, &config_path)?,
-            custom: try_get_config::<Custom>(config, home_config, &config_path)?,
+            entries: try_get_entries(config, home_config, &groups, &config_path, normalize_names)?,
+            shell,
+            custom,
             numbered: try_get_config::<Numbered>(config, home_config, &config_path)?,
-            path: try_get_config::<BinPath>(config, home_config, &config_path)?,
-            dmenu: try_get_config::<Dmenu>(config, home_config, &config_path)?,
+            group_headers: try_get_config::<GroupHeaders>(config, home_config, &config_path)?,
+            group_colors,
+            group_order: try_get_group_order(config, home_config, &groups, &config_path)?,
+            timeout: try_get_config::<Timeout>(config, home_config, &config_path)?,
+            wait: try_get_config::<Wait>(config, home_config, &config_path)?,
+            retry: try_get_config::<Retry>(config, home_config, &config_path)?,
+            menu_timeout: try_get_config::<MenuTimeout>(config, home_config, &config_path)?,
+            dedup_selections: try_get_config::<DedupSelections>(config, home_config, &config_path)?,
+            setsid: try_get_config::<Setsid>(config, home_config, &config_path)?,
+            pty: try_get_config::<Pty>(config, home_config, &config_path)?,
+            glob_args: try_get_config::<GlobArgs>(config, home_config, &config_path)?,
+            bell: try_get_config::<Bell>(config, home_config, &config_path)?,
+            accelerators,
+            default: try_get_config::<DefaultEntry>(config, home_config, &config_path)?,
+            systemd_scope: try_get_config::<SystemdScope>(config, home_config, &config_path)?,
+            clean_env: try_get_config::<CleanEnv>(config, home_config, &config_path)?,
+            env_passthrough: try_get_config::<EnvPassthrough>(config, home_config, &config_path)?,
+            env_file,
+            env,
+            tag: try_get_config::<TagMode>(config, home_config, &config_path)?,
+            normalize_names,
+            fold_matching: try_get_config::<FoldMatching>(config, home_config, &config_path)?,
+            sort: try_get_config::<Sort>(config, home_config, &config_path)?,
+            sort_reverse: try_get_config::<SortReverse>(config, home_config, &config_path)?,
+            sort_case_sensitive: try_get_config::<SortCaseSensitive>(
+                config,
+                home_config,
+                &config_path,
+            )?,
+            frecency: try_get_config::<Frecency>(config, home_config, &config_path)?,
+            history_max: try_get_config::<HistoryMax>(config, home_config, &config_path)?,
+            path,
+            backend,
+            dmenu,
+            cache_dir,
+            cache,
+            groups,
+            colors,
+            warnings,
             args,
             dirs,
             base_dirs,
         })
     }
+
+    /// Serialize the menu entries, groups, and options back into a toml config.
+    ///
+    /// Runtime state, like command line arguments and directories, is omitted.
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        let menu = self
+            .entries
+            .iter()
+            .map(|entry| (entry.name().to_string(), entry.to_value()))
+            .collect::<Map<String, Value>>();
+        // Sorted by name, since the maps keep the order they're filled in.
+        let mut groups = self.groups.iter().collect::<Vec<(&ImStr, &i64)>>();
+        groups.sort_unstable();
+        let groups = groups
+            .into_iter()
+            .map(|(name, group)| (name.to_string(), Value::Integer(*group)))
+            .collect::<Map<String, Value>>();
+        let mut colors = self.colors.iter().collect::<Vec<(&ImStr, &ImStr)>>();
+        colors.sort_unstable();
+        let colors = colors
+            .into_iter()
+            .map(|(name, color)| (name.to_string(), Value::String(color.to_string())))
+            .collect::<Map<String, Value>>();
+        let config = Map::from_iter([
+            (String::from(Shell::name()), self.shell.to_value()),
+            (String::from(Custom::name()), self.custom.to_value()),
+            (String::from(Numbered::name()), self.numbered.to_value()),
+            (
+                String::from(GroupHeaders::name()),
+                self.group_headers.to_value(),
+            ),
+            (
+                String::from(GroupColors::name()),
+                self.group_colors.to_value(),
+            ),
+            (String::from(GroupOrder::NAME), self.group_order.to_value()),
+            (String::from(Timeout::name()), self.timeout.to_value()),
+            (String::from(Wait::name()), self.wait.to_value()),
+            (String::from(Retry::name()), self.retry.to_value()),
+            (
+                String::from(MenuTimeout::name()),
+                self.menu_timeout.to_value(),
+            ),
+            (
+                String::from(DedupSelections::name()),
+                self.dedup_selections.to_value(),
+            ),
+            (String::from(Setsid::name()), self.setsid.to_value()),
+            (String::from(Pty::name()), self.pty.to_value()),
+            (String::from(GlobArgs::name()), self.glob_args.to_value()),
+            (String::from(Bell::name()), self.bell.to_value()),
+            (
+                String::from(Accelerators::name()),
+                self.accelerators.to_value(),
+            ),
+            (String::from(DefaultEntry::name()), self.default.to_value()),
+            (
+                String::from(SystemdScope::name()),
+                self.systemd_scope.to_value(),
+            ),
+            (String::from(CleanEnv::name()), self.clean_env.to_value()),
+            (
+                String::from(EnvPassthrough::name()),
+                self.env_passthrough.to_value(),
+            ),
+            (String::from(EnvFile::name()), self.env_file.to_value()),
+            (String::from(TagMode::name()), self.tag.to_value()),
+            (
+                String::from(NormalizeNames::name()),
+                self.normalize_names.to_value(),
+            ),
+            (
+                String::from(FoldMatching::name()),
+                self.fold_matching.to_value(),
+            ),
+            (String::from(Sort::name()), self.sort.to_value()),
+            (
+                String::from(SortReverse::name()),
+                self.sort_reverse.to_value(),
+            ),
+            (
+                String::from(SortCaseSensitive::name()),
+                self.sort_case_sensitive.to_value(),
+            ),
+            (String::from(Frecency::name()), self.frecency.to_value()),
+            (
+                String::from(HistoryMax::name()),
+                self.history_max.to_value(),
+            ),
+            (String::from(BinPath::name()), self.path.to_value()),
+            (
+                String::from(<Backend as ConfigItem>::name()),
+                self.backend.to_value(),
+            ),
+            (String::from(Dmenu::name()), self.dmenu.to_value()),
+            (String::from(CacheDir::name()), self.cache_dir.to_value()),
+        ]);
+
+        let config = Value::Table(Map::from_iter([
+            (String::from("menu"), Value::Table(menu)),
+            (String::from("groups"), Value::Table(groups)),
+            (String::from("colors"), Value::Table(colors)),
+            (String::from("config"), Value::Table(config)),
+        ]));
+
+        toml::to_string(&config).context("failed to serialize config??")
+    }
+
+    /// Find the name given to `group` in the `groups` table, if any.
+    ///
+    /// If several names refer to the same group, the alphabetically first is chosen.
+    pub fn group_name(&self, group: i64) -> Option<&ImStr> {
+        self.groups
+            .iter()
+            .filter(|(_, &value)| value == group)
+            .map(|(name, _)| name)
+            .min()
+    }
+}
+
+fn try_get_groups(
+    config: &Value,
+    home_config: Option<&Value>,
+    config_path: &Path,
+) -> anyhow::Result<HashMap<ImStr, i64>> {
+    let read_groups = |config: &Value| {
+        config
+            .get("groups")
+            .map(try_into_table("groups"))
+            .transpose()?
+            .into_iter()
+            .flatten()
+            .map(|(name, value)| {
+                try_into_integer(&format!("groups.{name}"))(value)
+                    .map(|group| (ImStr::from(name), group))
+            })
+            .collect::<anyhow::Result<Vec<(ImStr, i64)>>>()
+    };
+
+    let mut groups = home_config
+        .map(read_groups)
+        .transpose()
+        .context(home_config_error(config_path))?
+        .unwrap_or_default()
+        .into_iter()
+        .collect::<HashMap<ImStr, i64>>();
+
+    groups.extend(read_groups(config).context(target_config_error())?);
+
+    Ok(groups)
+}
+
+/// Read the `colors` palette, with colors in the target config overriding those in the home config.
+fn try_get_colors(
+    config: &Value,
+    home_config: Option<&Value>,
+    config_path: &Path,
+) -> anyhow::Result<HashMap<ImStr, ImStr>> {
+    let read_colors = |config: &Value| {
+        config
+            .get("colors")
+            .map(try_into_table("colors"))
+            .transpose()?
+            .into_iter()
+            .flatten()
+            .map(|(name, value)| {
+                try_into_string(&format!("colors.{name}"))(value)
+                    .map(|color| (ImStr::from(name), color))
+            })
+            .collect::<anyhow::Result<Vec<(ImStr, ImStr)>>>()
+    };
+
+    let mut colors = home_config
+        .map(read_colors)
+        .transpose()
+        .context(home_config_error(config_path))?
+        .unwrap_or_default()
+        .into_iter()
+        .collect::<HashMap<ImStr, ImStr>>();
+
+    colors.extend(read_colors(config).context(target_config_error())?);
+
+    Ok(colors)
+}
+
+/// Read `config.group-order`, from the target config if it's set there or else the home config.
+fn try_get_group_order(
+    config: &Value,
+    home_config: Option<&Value>,
+    groups: &HashMap<ImStr, i64>,
+    config_path: &Path,
+) -> anyhow::Result<GroupOrder> {
+    let order = |config: &Value| {
+        config
+            .get("config")
+            .and_then(|config| config.get(GroupOrder::NAME))
+            .map(|order| GroupOrder::try_new(order, groups))
+            .transpose()
+    };
+
+    if let Some(order) = order(config).context(target_config_error())? {
+        return Ok(order);
+    }
+    Ok(home_config
+        .map(order)
+        .transpose()
+        .context(home_config_error(config_path))?
+        .flatten()
+        .unwrap_or_default())
 }
 
 fn try_get_entries(
     config: &Value,
     home_config: Option<&Value>,
+    groups: &HashMap<ImStr, i64>,
     config_path: &Path,
+    normalize_names: NormalizeNames,
 ) -> anyhow::Result<Vec<Entry>> {
+    let entry_name = |name: &String| {
+        normalize_names
+            .0
+            .then(|| nfc(name))
+            .flatten()
+            .map_or_else(|| ImStr::from(name), ImStr::from)
+    };
+
     let mut menu = config
         .get("menu")
         .map(try_into_table("menu"))
         .transpose()?
         .into_iter()
         .flatten()
-        .map(|(name, value)| Entry::try_new(ImStr::from(name), value))
+        .map(|(name, value)| Entry::try_new(entry_name(name), value, groups, Source::Pattern))
         .collect::<Result<Vec<Entry>, _>>()
         .context(target_config_error())?;
 
@@ -768,17 +4682,15 @@ fn try_get_entries(
         .transpose()?
         .into_iter()
         .flatten()
-        .map(|(name, value)| Entry::try_new(ImStr::from(name), value))
+        .map(|(name, value)| Entry::try_new(entry_name(name), value, groups, Source::Home))
         .collect::<Result<Vec<Entry>, _>>()
         .context(home_config_error(config_path))?;
 
-    let entry_names = menu.iter().map(Entry::name).collect::<HashSet<ImStr>>();
-
-    menu.extend(
-        home_menu
-            .into_iter()
-            .filter(|entry| !entry_names.contains(&entry.name())),
-    );
+    // Home entries are overridden by pattern entries of the same name.
+    // Once normalized, a table can also have several entries of the same name; the first is kept.
+    let mut entry_names = HashSet::default();
+    menu.extend(home_menu);
+    menu.retain(|entry| entry_names.insert(entry.name()));
 
     Ok(menu)
 }
@@ -817,6 +4729,15 @@ fn try_get_config<'a, T: ConfigItem>(
     }
 }
 
+fn strings_to_value(strings: &[ImStr]) -> Value {
+    Value::Array(
+        strings
+            .iter()
+            .map(|string| Value::String(string.to_string()))
+            .collect(),
+    )
+}
+
 fn type_error<T>(name: &str, valid: &[&str], found: &str) -> anyhow::Result<T> {
     let mut types = String::new();
     match valid {
@@ -865,6 +4786,24 @@ fn try_into_integer(name: &str) -> impl Fn(&Value) -> anyhow::Result<i64> + '_ {
     }
 }
 
+fn try_into_group<'a>(
+    name: &'a str,
+    groups: &'a HashMap<ImStr, i64>,
+) -> impl Fn(&Value) -> anyhow::Result<i64> + 'a {
+    move |value| match value {
+        Value::Integer(group) => Ok(*group),
+        Value::String(group) => groups.get(group.as_str()).copied().with_context(|| {
+            format!(
+                "`{}` refers to the unknown group `{}`; it must be defined in `{}`",
+                style_stderr!(bold(), "{name}"),
+                style_stderr!(bold(), "{group}"),
+                style_stderr!(bold(), "groups"),
+            )
+        }),
+        other => type_error(name, &["integer", "string"], other.type_str()),
+    }
+}
+
 fn try_into_table(name: &str) -> impl Fn(&Value) -> anyhow::Result<&Map<String, Value>> + '_ {
     move |value| match value {
         Value::Table(value) => Ok(value),
@@ -893,6 +4832,100 @@ fn try_into_array_string(name: &str) -> impl Fn(&Value) -> anyhow::Result<ImStr>
     }
 }
 
+/// Parse a shell command string or a bare command array, which must not be empty.
+fn try_into_run(name: &str) -> impl Fn(&Value) -> anyhow::Result<Run> + '_ {
+    move |value| {
+        let run = match value {
+            Value::String(run) => Run::Shell(ImStr::from(run)),
+            Value::Array(run) => run
+                .iter()
+                .map(try_into_array_string(name))
+                .collect::<Result<Vec<ImStr>, _>>()
+                .map(Run::Bare)?,
+            other => return type_error(name, &["string", "array"], other.type_str()),
+        };
+
+        // An empty command would silently do nothing when selected.
+        let is_empty = match &run {
+            Run::Shell(run) => run.trim().is_empty(),
+            Run::Bare(run) => run.first().is_none_or(|bin| bin.is_empty()),
+        };
+        if is_empty {
+            return Err(anyhow!(
+                "`{}` can't be an empty command",
+                style_stderr!(bold(), "{name}")
+            ));
+        }
+
+        Ok(run)
+    }
+}
+
+/// Parse a number of seconds, or a string with a unit like `30s`, `5m`, `2h`, `7d`, or `2w`.
+fn try_into_duration(name: &str) -> impl Fn(&Value) -> anyhow::Result<Duration> + '_ {
+    move |value| {
+        let seconds = match value {
+            Value::Integer(seconds) => Some(*seconds as f64),
+            Value::Float(seconds) => Some(*seconds),
+            Value::String(duration) => parse_duration(duration),
+            other => return type_error(name, &["integer", "float", "string"], other.type_str()),
+        };
+
+        seconds
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            .ok_or_else(|| {
+                anyhow!(
+                    "`{}` must be a positive number of seconds, or a duration like `{}` or `{}`",
+                    style_stderr!(bold(), "{name}"),
+                    style_stderr!(bold(), "30s"),
+                    style_stderr!(bold(), "7d"),
+                )
+            })
+    }
+}
+
+/// Parse a duration like `1.5h` into seconds.
+fn parse_duration(duration: &str) -> Option<f64> {
+    let unit_start = duration.find(|c: char| c.is_ascii_alphabetic())?;
+    let (number, unit) = duration.split_at(unit_start);
+    let multiplier = match unit {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 60.0 * 60.0,
+        "d" => 24.0 * 60.0 * 60.0,
+        "w" => 7.0 * 24.0 * 60.0 * 60.0,
+        _ => return None,
+    };
+
+    number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .map(|number| number * multiplier)
+}
+
+fn try_into_command(name: &str) -> impl Fn(&Value) -> anyhow::Result<Vec<ImStr>> + '_ {
+    move |value| {
+        let command = match value {
+            Value::String(command) => vec![ImStr::from(command)],
+            Value::Array(command) => command
+                .iter()
+                .map(try_into_array_string(name))
+                .collect::<Result<Vec<ImStr>, _>>()?,
+            other => return type_error(name, &["string", "array"], other.type_str()),
+        };
+
+        if command.is_empty() {
+            Err(anyhow!(
+                "`{}` must contain at least a program to run",
+                style_stderr!(bold(), "{name}")
+            ))
+        } else {
+            Ok(command)
+        }
+    }
+}
+
 fn try_into_unsigned_integer(name: &str) -> impl Fn(i64) -> anyhow::Result<u64> + '_ {
     move |value| {
         value.try_into().map_err(|_| {
@@ -904,6 +4937,97 @@ fn try_into_unsigned_integer(name: &str) -> impl Fn(i64) -> anyhow::Result<u64>
     }
 }
 
+/// Find any keys in `config` or `config.dmenu` that dmm doesn't recognize,
+/// suggesting the closest known key for each.
+fn unknown_keys(config: &Value, context: &str) -> Vec<Rc<anyhow::Error>> {
+    let config_keys = [
+        Shell::name(),
+        Custom::name(),
+        Numbered::name(),
+        GroupHeaders::name(),
+        GroupColors::name(),
+        GroupOrder::NAME,
+        Timeout::name(),
+        Wait::name(),
+        Retry::name(),
+        MenuTimeout::name(),
+        DedupSelections::name(),
+        Setsid::name(),
+        Pty::name(),
+        GlobArgs::name(),
+        Bell::name(),
+        Accelerators::name(),
+        DefaultEntry::name(),
+        SystemdScope::name(),
+        CleanEnv::name(),
+        EnvPassthrough::name(),
+        EnvFile::name(),
+        TagMode::name(),
+        NormalizeNames::name(),
+        FoldMatching::name(),
+        Sort::name(),
+        SortReverse::name(),
+        SortCaseSensitive::name(),
+        Frecency::name(),
+        HistoryMax::name(),
+        BinPath::name(),
+        <Backend as ConfigItem>::name(),
+        Dmenu::name(),
+        CacheDir::name(),
+    ]
+    .map(|key| format!("config.{key}"));
+    let dmenu_keys = Dmenu::KEYS.map(|key| format!("config.dmenu.{key}"));
+    let known = config_keys
+        .iter()
+        .chain(&dmenu_keys)
+        .map(String::as_str)
+        .collect::<Vec<&str>>();
+
+    let config = config.get("config").and_then(Value::as_table);
+    let dmenu = config
+        .and_then(|config| config.get(Dmenu::name()))
+        .and_then(Value::as_table);
+    let config_keys = config
+        .into_iter()
+        .flat_map(Map::keys)
+        .map(|key| format!("config.{key}"));
+    let dmenu_keys = dmenu
+        .into_iter()
+        .flat_map(Map::keys)
+        .map(|key| format!("config.dmenu.{key}"));
+
+    config_keys
+        .chain(dmenu_keys)
+        .filter(|key| !known.contains(&key.as_str()))
+        .map(|key| {
+            let mut message = format!("`{}` isn't a known option", style_stderr!(bold(), "{key}"));
+            if let Some(closest) = closest_key(&key, &known) {
+                write!(
+                    message,
+                    "; did you mean `{}`?",
+                    style_stderr!(bold(), "{closest}")
+                )
+                .expect("unreachable");
+            }
+            Rc::new(anyhow!(message).context(String::from(context)))
+        })
+        .collect()
+}
+
+/// Find the known key whose last segment is closest to the last segment of `key`.
+fn closest_key<'a>(key: &str, known: &[&'a str]) -> Option<&'a str> {
+    let last_segment = |key: &'a str| key.rsplit('.').next().unwrap_or(key);
+    let name = key.rsplit('.').next().unwrap_or(key);
+    let max_distance = (name.chars().count() / 3).max(2);
+
+    known
+        .iter()
+        .map(|known| (strsim::levenshtein(name, last_segment(known)), *known))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, known)| known)
+}
+
 fn home_config_error(path: &Path) -> String {
     format!(
         "found a problem with home config `{}`",
@@ -918,4 +5042,183 @@ const fn target_config_error() -> &'static str {
 trait ConfigItem: for<'a> TryFrom<&'a Value, Error = anyhow::Error> + Default {
     fn name() -> &'static str;
     fn merge(self, default: Self) -> Self;
+    fn to_value(&self) -> Value;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(toml: &str) -> anyhow::Result<Entry> {
+        let value = toml.parse::<Value>().unwrap();
+        Entry::try_new(
+            ImStr::from("entry"),
+            &value["entry"],
+            &HashMap::default(),
+            Source::Pattern,
+        )
+    }
+
+    #[test]
+    fn custom_true_enables_custom_commands() {
+        let custom = |toml: &str| Custom::try_from(&toml.parse::<Value>().unwrap()["custom"]);
+        assert!(matches!(custom("custom = true"), Ok(Custom::Enabled)));
+        assert!(matches!(custom("custom = false"), Ok(Custom::Disabled)));
+        assert!(matches!(Custom::default(), Custom::Disabled));
+    }
+
+    #[test]
+    fn dumped_custom_reads_back_the_same() {
+        for custom in [Custom::Enabled, Custom::Disabled] {
+            let dumped = custom.to_value();
+            assert_eq!(
+                matches!(Custom::try_from(&dumped), Ok(Custom::Enabled)),
+                matches!(custom, Custom::Enabled)
+            );
+        }
+    }
+
+    #[test]
+    fn dumped_groups_and_colors_are_sorted() {
+        let toml = "[groups]\nvim = 1\napt = 2\nzsh = 3\nbash = 4\n\
+                    [colors]\nvim = \"#fff\"\napt = \"#000\"\nzsh = \"#111\"\nbash = \"#222\"";
+        let args = cli(None).try_get_matches_from(["dmm"]).unwrap();
+        let config = Config::try_new(&toml.parse().unwrap(), None, args, None, None).unwrap();
+        let dumped = config.to_toml().unwrap().parse::<Value>().unwrap();
+
+        for table in ["groups", "colors"] {
+            let names = dumped[table]
+                .as_table()
+                .unwrap()
+                .keys()
+                .collect::<Vec<&String>>();
+            assert_eq!(names, ["apt", "bash", "vim", "zsh"]);
+        }
+    }
+
+    #[test]
+    fn boolean_run_alone() {
+        assert!(matches!(
+            entry("entry = { run = true }"),
+            Ok(Entry::Name { .. })
+        ));
+        assert!(matches!(
+            entry("entry = { run = false }"),
+            Ok(Entry::Filter(_))
+        ));
+    }
+
+    #[test]
+    fn boolean_run_with_other_keys() {
+        assert!(entry("entry = { run = true, when = { host = \"laptop\" } }").is_err());
+        assert!(entry("entry = { run = true, if = \"false\" }").is_err());
+        assert!(entry("entry = { run = false, group = 1 }").is_err());
+        assert!(entry("entry = { run = \"firefox\", when = { host = \"laptop\" } }").is_ok());
+    }
+
+    #[test]
+    fn unknown_keys_suggest_the_closest_key() {
+        let toml = "[config]\npromptt = \"run\"\n[config.dmenu]\nfonts = \"mono\"";
+        let warnings = unknown_keys(&toml.parse().unwrap(), "context")
+            .iter()
+            .map(|warning| format!("{warning:#}"))
+            .collect::<Vec<String>>();
+
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("config.promptt"));
+        assert!(warnings[0].contains("did you mean `config.dmenu.prompt`?"));
+        assert!(warnings[1].contains("config.dmenu.fonts"));
+        assert!(warnings[1].contains("did you mean `config.dmenu.font`?"));
+    }
+
+    #[test]
+    fn when_host() {
+        let host = hostname().unwrap();
+        assert!(!host.contains('\0'));
+        let when = |host: &str| When {
+            env: Vec::new(),
+            host: Some(ImStr::from(host)),
+        };
+
+        assert!(when(&host).is_met());
+        assert!(!when(&format!("{host}-elsewhere")).is_met());
+        assert!(!when("").is_met());
+    }
+
+    #[test]
+    fn dmenu_flags_override_the_config() {
+        let dmenu = |args: &[&str]| {
+            let toml = "[config.dmenu]\nprompt = \"run\"\nlines = 10\nbottom = true";
+            let args = cli(None)
+                .try_get_matches_from(["dmm"].iter().chain(args))
+                .unwrap();
+            Config::try_new(&toml.parse().unwrap(), None, args, None, None)
+                .unwrap()
+                .dmenu
+        };
+
+        let configured = dmenu(&[]);
+        assert_eq!(configured.prompt.as_deref(), Some("run"));
+        assert!(configured.bottom && !configured.center);
+
+        let overridden = dmenu(&["--prompt", "go", "--lines", "5", "--no-bottom", "--center"]);
+        assert_eq!(overridden.prompt.as_deref(), Some("go"));
+        assert_eq!(overridden.lines, Some(5));
+        assert!(!overridden.bottom && overridden.center);
+
+        assert!(dmenu(&["--no-bottom", "--bottom"]).bottom);
+        assert!(!dmenu(&["--center", "--no-center"]).center);
+    }
+
+    #[test]
+    fn empty_commands_are_rejected() {
+        let run = |toml: &str| try_into_run("menu.entry")(&toml.parse::<Value>().unwrap()["run"]);
+
+        assert!(run("run = \"\"").is_err());
+        assert!(run("run = \" \\t\\n\"").is_err());
+        assert!(run("run = []").is_err());
+        assert!(run("run = [\"\", \"arg\"]").is_err());
+        assert_eq!(run("run = \"ls\"").unwrap(), Run::Shell(ImStr::from("ls")));
+        assert_eq!(
+            run("run = [\"ls\", \"\"]").unwrap(),
+            Run::Bare(vec![ImStr::from("ls"), ImStr::from("")])
+        );
+    }
+
+    #[test]
+    fn shells_without_a_program_are_rejected() {
+        let shell = |toml: &str| Shell::try_from(&toml.parse::<Value>().unwrap()["shell"]);
+        let error = |toml: &str| format!("{:#}", shell(toml).unwrap_err());
+
+        assert!(error("shell = []").contains("`config.shell` must specify a shell program"));
+        assert!(error("shell = [\"\", \"-c\"]").contains("`config.shell` must"));
+        assert!(error("shell = {}").contains("`config.shell.shell` must specify a shell program"));
+        assert!(error("shell = { shell = [], piped = true }").contains("`config.shell.shell` must"));
+        assert!(shell("shell = [\"bash\", \"-c\"]").is_ok());
+        assert!(shell("shell = { shell = [\"bash\"], piped = true }").is_ok());
+    }
+
+    #[test]
+    fn no_home_directory() {
+        let args = cli(None).try_get_matches_from(["dmm"]).unwrap();
+        let toml = "config.path = [\"~/bin\"]".parse().unwrap();
+        let config = Config::try_new(&toml, None, args, None, None).unwrap();
+        let warnings = config
+            .warnings
+            .iter()
+            .map(|warning| format!("{warning:#}"))
+            .collect::<Vec<String>>();
+        assert!(warnings
+            .iter()
+            .any(|warning| warning.starts_with("no home directory could be detected")));
+
+        let err = expand_path("~/bin", None).unwrap_err();
+        assert_eq!(format!("{err:#}"), format!("{:#}", no_home_error()));
+        assert_eq!(expand_path("/bin", None).unwrap(), PathBuf::from("/bin"));
+
+        let args = cli(None).try_get_matches_from(["dmm"]).unwrap();
+        let toml = "config.cache-dir = \"~/cache\"".parse().unwrap();
+        let err = Config::try_new(&toml, None, args, None, None).unwrap_err();
+        assert!(format!("{err:#}").contains("no home directory could be detected"));
+    }
 }
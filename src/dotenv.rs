@@ -0,0 +1,187 @@
+use std::env;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use anyhow::anyhow;
+
+use crate::imstr::ImStr;
+use crate::style::{bold, style_stderr};
+
+/// Parse the `KEY=value` lines of an env file, like those read by `config.env-file`.
+///
+/// Blank lines and lines starting with `#` are ignored, as is a leading `export`.
+/// Values may be unquoted, where a `#` after whitespace starts a comment,
+/// double quoted, where `\"`, `\\`, `\n` and `\t` are escapes,
+/// or single quoted, where everything up to the closing quote is kept as is.
+/// Outside of single quotes, `$VAR` and `${VAR}` are replaced with the value of `VAR`,
+/// from earlier in the file or else from the environment, or nothing if it isn't set.
+///
+/// ```
+/// use dmm::dotenv::parse;
+///
+/// std::env::set_var("DMM_DOTENV_EXAMPLE", "env");
+/// let vars = parse(r#"
+/// ## A comment, then a blank line.
+///
+/// export PLAIN=some value # a trailing comment
+/// DOUBLE="quoted # not a comment\tand \"escaped\""
+/// SINGLE='literal $PLAIN\t'
+/// EXPANDED="${PLAIN}, $DMM_DOTENV_EXAMPLE and [$DMM_DOTENV_UNSET]"
+/// EMPTY=
+/// "#).unwrap();
+///
+/// let vars = vars
+///     .iter()
+///     .map(|(key, value)| (key.as_str(), value.as_str()))
+///     .collect::<Vec<_>>();
+/// assert_eq!(vars, [
+///     ("PLAIN", "some value"),
+///     ("DOUBLE", "quoted # not a comment\tand \"escaped\""),
+///     ("SINGLE", "literal $PLAIN\\t"),
+///     ("EXPANDED", "some value, env and []"),
+///     ("EMPTY", ""),
+/// ]);
+///
+/// assert!(parse("NOT AN ASSIGNMENT").is_err());
+/// assert!(parse("UNCLOSED=\"value").is_err());
+/// ```
+pub fn parse(contents: &str) -> anyhow::Result<Vec<(ImStr, ImStr)>> {
+    let mut vars = Vec::<(ImStr, ImStr)>::new();
+
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").map_or(line, str::trim_start);
+
+        let parsed = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected `KEY=value`"))
+            .and_then(|(key, value)| {
+                let key = key.trim_end();
+                if !is_var_name(key) {
+                    return Err(anyhow!(
+                        "`{}` isn't a valid variable name",
+                        style_stderr!(bold(), "{key}")
+                    ));
+                }
+                let value = parse_value(value.trim_start(), &vars)?;
+                Ok((ImStr::from(key), ImStr::from(value)))
+            })
+            .map_err(|err| err.context(format!("problem on line {}", number + 1)))?;
+
+        vars.push(parsed);
+    }
+
+    Ok(vars)
+}
+
+fn is_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_value(value: &str, vars: &[(ImStr, ImStr)]) -> anyhow::Result<String> {
+    let mut parsed = String::new();
+    let mut chars = value.chars().peekable();
+
+    match chars.peek() {
+        Some('\'') => {
+            chars.next();
+            let literal = chars.by_ref().take_while(|&c| c != '\'');
+            parsed.extend(literal);
+            if !value[1..].contains('\'') {
+                return Err(anyhow!("the value is missing a closing `'`"));
+            }
+        }
+        Some('"') => {
+            chars.next();
+            loop {
+                match chars.next() {
+                    None => return Err(anyhow!("the value is missing a closing `\"`")),
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => parsed.push('\n'),
+                        Some('t') => parsed.push('\t'),
+                        Some(c @ ('"' | '\\' | '$')) => parsed.push(c),
+                        Some(c) => {
+                            parsed.push('\\');
+                            parsed.push(c);
+                        }
+                        None => return Err(anyhow!("the value is missing a closing `\"`")),
+                    },
+                    Some('$') => expand_var(&mut chars, vars, &mut parsed)?,
+                    Some(c) => parsed.push(c),
+                }
+            }
+        }
+        _ => {
+            let mut last = ' ';
+            while let Some(c) = chars.next() {
+                if c == '#' && last.is_whitespace() {
+                    break;
+                }
+                if c == '$' {
+                    expand_var(&mut chars, vars, &mut parsed)?;
+                } else {
+                    parsed.push(c);
+                }
+                last = c;
+            }
+            let trimmed = parsed.trim_end().len();
+            parsed.truncate(trimmed);
+            return Ok(parsed);
+        }
+    }
+
+    let rest = chars.collect::<String>();
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(anyhow!(
+            "unexpected `{}` after the closing quote",
+            style_stderr!(bold(), "{rest}")
+        ));
+    }
+
+    Ok(parsed)
+}
+
+/// Push the value of the variable named after a `$` onto `out`,
+/// or the `$` itself if no name follows.
+fn expand_var(
+    chars: &mut Peekable<Chars<'_>>,
+    vars: &[(ImStr, ImStr)],
+    out: &mut String,
+) -> anyhow::Result<()> {
+    let braced = chars.next_if_eq(&'{').is_some();
+    let mut name = String::new();
+    while let Some(c) = chars.next_if(|&c| c.is_ascii_alphanumeric() || c == '_') {
+        name.push(c);
+    }
+    if braced && chars.next_if_eq(&'}').is_none() {
+        return Err(anyhow!("`${{{name}` is missing a closing `}}`"));
+    }
+
+    if name.is_empty() {
+        out.push('$');
+        if braced {
+            out.push_str("{}");
+        }
+        return Ok(());
+    }
+
+    match vars.iter().rev().find(|(key, _)| key.as_str() == name) {
+        Some((_, value)) => out.push_str(value),
+        None => {
+            if let Some(value) = env::var_os(&name) {
+                out.push_str(&value.to_string_lossy());
+            }
+        }
+    }
+
+    Ok(())
+}
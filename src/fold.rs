@@ -0,0 +1,108 @@
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+/// Compose `string` into Unicode Normalization Form C,
+/// so names that look the same compare equal however their accents were encoded.
+///
+/// Returns `None` if `string` is already in that form.
+///
+/// ```
+/// use dmm::fold::nfc;
+///
+/// // `É` as one character, and as `E` followed by a combining acute accent.
+/// let composed = "Évince";
+/// let decomposed = "E\u{301}vince";
+/// assert_ne!(composed, decomposed);
+/// assert_eq!(nfc(decomposed).as_deref(), Some(composed));
+/// assert_eq!(nfc(composed), None);
+/// ```
+pub fn nfc(string: &str) -> Option<String> {
+    match is_nfc_quick(string.chars()) {
+        IsNormalized::Yes => None,
+        IsNormalized::No | IsNormalized::Maybe => {
+            let normalized = string.nfc().collect::<String>();
+            (normalized != string).then_some(normalized)
+        }
+    }
+}
+
+/// Replace accented Latin letters in `string` with their plain ascii forms,
+/// so `Évince` can be found by typing `evince`.
+///
+/// Combining accents are removed, and other characters are kept as they are.
+/// Returns `None` if folding wouldn't change `string`.
+///
+/// ```
+/// use dmm::fold::fold_ascii;
+///
+/// assert_eq!(fold_ascii("Évince").as_deref(), Some("Evince"));
+/// assert_eq!(fold_ascii("Straße Œuvre").as_deref(), Some("Strasse OEuvre"));
+/// // `e` followed by a combining acute accent.
+/// assert_eq!(fold_ascii("caf\u{301}e").as_deref(), Some("cafe"));
+/// assert_eq!(fold_ascii("Ωmega дом").as_deref(), None);
+/// assert_eq!(fold_ascii("firefox"), None);
+/// ```
+pub fn fold_ascii(string: &str) -> Option<String> {
+    if string.is_ascii() || !string.chars().any(|c| fold_char(c).is_some()) {
+        return None;
+    }
+
+    let mut folded = String::with_capacity(string.len());
+    for c in string.chars() {
+        match fold_char(c) {
+            Some(plain) if c.is_lowercase() => {
+                folded.extend(plain.chars().map(|c| c.to_ascii_lowercase()));
+            }
+            Some(plain) => folded.push_str(plain),
+            None => folded.push(c),
+        }
+    }
+
+    Some(folded)
+}
+
+/// The plain uppercase form of an accented Latin letter,
+/// an empty string for a combining accent, or `None` for anything else.
+fn fold_char(c: char) -> Option<&'static str> {
+    let plain = match c {
+        // Combining diacritical marks.
+        '\u{300}'..='\u{36f}' => "",
+        // Latin-1 Supplement.
+        'À'..='Å' | 'à'..='å' => "A",
+        'Æ' | 'æ' => "AE",
+        'Ç' | 'ç' => "C",
+        'È'..='Ë' | 'è'..='ë' => "E",
+        'Ì'..='Ï' | 'ì'..='ï' => "I",
+        'Ð' | 'ð' => "D",
+        'Ñ' | 'ñ' => "N",
+        'Ò'..='Ö' | 'Ø' | 'ò'..='ö' | 'ø' => "O",
+        'Ù'..='Ü' | 'ù'..='ü' => "U",
+        'Ý' | 'ý' | 'ÿ' => "Y",
+        'Þ' | 'þ' => "TH",
+        'ß' => "SS",
+        // Latin Extended-A.
+        'Ā'..='ą' => "A",
+        'Ć'..='č' => "C",
+        'Ď'..='đ' => "D",
+        'Ē'..='ě' => "E",
+        'Ĝ'..='ģ' => "G",
+        'Ĥ'..='ħ' => "H",
+        'Ĩ'..='ı' => "I",
+        'Ĳ' | 'ĳ' => "IJ",
+        'Ĵ' | 'ĵ' => "J",
+        'Ķ'..='ĸ' => "K",
+        'Ĺ'..='ł' => "L",
+        'Ń'..='ŋ' => "N",
+        'Ō'..='ő' => "O",
+        'Œ' | 'œ' => "OE",
+        'Ŕ'..='ř' => "R",
+        'Ś'..='š' | 'ſ' => "S",
+        'Ţ'..='ŧ' => "T",
+        'Ũ'..='ų' => "U",
+        'Ŵ' | 'ŵ' => "W",
+        'Ŷ'..='Ÿ' => "Y",
+        'Ź'..='ž' => "Z",
+        _ => return None,
+    };
+
+    Some(plain)
+}
@@ -0,0 +1,74 @@
+/// Score how well `name` matches `query`, or `None` if it doesn't match.
+///
+/// A name matches if it contains every character of the query in order, ignoring case,
+/// like `ffx` in `firefox`. Higher scores are better matches: characters that follow
+/// each other in the name, or start it or one of its words, score more than scattered ones.
+/// An empty query matches every name.
+///
+/// ```
+/// use dmm::fuzzy::score;
+///
+/// assert!(score("ffx", "Firefox").is_some());
+/// assert!(score("xff", "Firefox").is_none());
+/// assert!(score("", "anything").is_some());
+///
+/// // Consecutive and word-start matches beat scattered ones.
+/// assert!(score("fire", "Firefox") > score("fire", "fast image resizer"));
+/// assert!(score("term", "Terminal") > score("term", "alacritty terminal"));
+/// assert!(score("term", "alacritty terminal") > score("term", "the remote"));
+/// ```
+pub fn score(query: &str, name: &str) -> Option<i64> {
+    let query = query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .collect::<Vec<char>>();
+    let name = name.chars().collect::<Vec<char>>();
+    let Some(&first) = query.first() else {
+        return Some(0);
+    };
+
+    // Matching greedily from the first possible character can miss a better match later,
+    // like the word `terminal` in `alacritty terminal`, so try each place the query could start.
+    (0..name.len())
+        .filter(|&start| name[start].to_lowercase().eq([first]))
+        .filter_map(|start| score_from(&query, &name, start))
+        .max()
+}
+
+/// Score the match of `query` in `name` starting at `start`, matching each character greedily.
+fn score_from(query: &[char], name: &[char], start: usize) -> Option<i64> {
+    const MATCH: i64 = 1;
+    const CONSECUTIVE: i64 = 4;
+    const WORD_START: i64 = 3;
+    const NAME_START: i64 = 6;
+    const GAP: i64 = -1;
+
+    let mut query = query.iter().peekable();
+    let mut score = 0;
+    let mut last_matched = false;
+
+    for (i, &c) in name.iter().enumerate().skip(start) {
+        let Some(&&wanted) = query.peek() else {
+            break;
+        };
+
+        if c.to_lowercase().eq([wanted]) {
+            query.next();
+            score += MATCH;
+            if last_matched {
+                score += CONSECUTIVE;
+            }
+            if i == 0 {
+                score += NAME_START;
+            } else if !name[i - 1].is_alphanumeric() {
+                score += WORD_START;
+            }
+            last_matched = true;
+        } else {
+            score += GAP;
+            last_matched = false;
+        }
+    }
+
+    query.peek().is_none().then_some(score)
+}
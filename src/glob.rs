@@ -0,0 +1,81 @@
+use std::ffi::{CStr, CString, OsStr, OsString};
+use std::mem;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::Path;
+
+/// Whether `arg` contains any of the characters a shell expands as a glob: `*`, `?` or `[`.
+pub fn is_glob(arg: &OsStr) -> bool {
+    arg.as_bytes().iter().any(|c| b"*?[".contains(c))
+}
+
+/// Expand the glob `pattern` into the paths it matches, sorted, like a shell would.
+///
+/// A leading `~/` is replaced with `home` first, if it's given.
+/// If nothing matches, the pattern is kept as it is, or dropped if `nullglob` is set.
+/// Arguments that aren't globs are always kept as they are, even if no file has their name.
+///
+/// ```
+/// use std::ffi::OsString;
+/// use std::fs;
+///
+/// use dmm::glob::expand;
+///
+/// let dir = std::env::temp_dir().join(format!("dmm-glob-{}", std::process::id()));
+/// fs::create_dir_all(&dir).unwrap();
+/// for file in ["b.jpg", "a.jpg", "c.png"] {
+///     fs::write(dir.join(file), "").unwrap();
+/// }
+/// let path = |name: &str| OsString::from(dir.join(name));
+///
+/// assert_eq!(expand(&path("*.jpg"), None, false), [path("a.jpg"), path("b.jpg")]);
+/// assert_eq!(expand(&OsString::from("~/*.png"), Some(&dir), false), [path("c.png")]);
+///
+/// // Without a match, the pattern is kept, unless `nullglob` is set.
+/// assert_eq!(expand(&path("*.gif"), None, false), [path("*.gif")]);
+/// assert!(expand(&path("*.gif"), None, true).is_empty());
+///
+/// // Arguments that aren't globs are never dropped.
+/// assert_eq!(expand(&OsString::from("--fullscreen"), None, true), ["--fullscreen"]);
+///
+/// fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn expand(pattern: &OsStr, home: Option<&Path>, nullglob: bool) -> Vec<OsString> {
+    if !is_glob(pattern) {
+        return vec![pattern.to_owned()];
+    }
+
+    let expanded = match (pattern.as_bytes().strip_prefix(b"~/"), home) {
+        (Some(rest), Some(home)) => home.join(OsStr::from_bytes(rest)).into_os_string(),
+        _ => pattern.to_owned(),
+    };
+    let Ok(c_pattern) = CString::new(expanded.as_bytes()) else {
+        return vec![pattern.to_owned()];
+    };
+
+    // SAFETY: `glob_t` is a plain C struct, for which all zeroes is a valid empty value.
+    let mut matches = unsafe { mem::zeroed::<libc::glob_t>() };
+    // SAFETY: The pattern is a valid C string, and `matches` is only read after `glob` fills it.
+    // `glob` allocates `gl_pathv` with `gl_pathc` valid C strings, which are copied before
+    // `globfree` releases them.
+    let paths = unsafe {
+        let result = libc::glob(c_pattern.as_ptr(), 0, None, &mut matches);
+        let paths = if result == 0 {
+            (0..matches.gl_pathc)
+                .map(|i| {
+                    let path = CStr::from_ptr(*matches.gl_pathv.add(i));
+                    OsString::from_vec(path.to_bytes().to_vec())
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        libc::globfree(&mut matches);
+        paths
+    };
+
+    if paths.is_empty() && !nullglob {
+        vec![pattern.to_owned()]
+    } else {
+        paths
+    }
+}
@@ -1,12 +1,16 @@
 use std::{
     borrow::{Borrow, Cow},
     cmp::Ordering,
+    error::Error,
     fmt::{self, Display},
     hash::{Hash, Hasher},
     ops::Deref,
+    path::{Path, PathBuf},
     rc::Rc,
 };
 
+use crate::style::{bold, style_stderr};
+
 /// Immutable string that is cheap to Clone.
 ///
 /// Use [`Self::from`] to create heap allocated strings from [`String`], [`&str`], or [`Rc<str>`].
@@ -73,6 +77,64 @@ impl From<&str> for ImStr {
     }
 }
 
+impl TryFrom<PathBuf> for ImStr {
+    type Error = InvalidUnicodePath;
+
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        path.into_os_string()
+            .into_string()
+            .map(Self::from)
+            .map_err(|path| InvalidUnicodePath(PathBuf::from(path)))
+    }
+}
+
+impl TryFrom<&Path> for ImStr {
+    type Error = InvalidUnicodePath;
+
+    fn try_from(path: &Path) -> Result<Self, Self::Error> {
+        path.to_str()
+            .map(Self::from)
+            .ok_or_else(|| InvalidUnicodePath(path.to_path_buf()))
+    }
+}
+
+/// The error returned when converting a path that isn't valid unicode into an [`ImStr`].
+///
+/// ```
+/// use std::ffi::OsStr;
+/// use std::os::unix::ffi::OsStrExt;
+/// use std::path::Path;
+///
+/// use dmm::imstr::ImStr;
+///
+/// let valid = ImStr::try_from(Path::new("/usr/bin/dmenu"));
+/// assert_eq!(valid.unwrap(), ImStr::new("/usr/bin/dmenu"));
+///
+/// let invalid = Path::new(OsStr::from_bytes(b"/usr/bin/\xff"));
+/// let err = ImStr::try_from(invalid.to_path_buf()).unwrap_err();
+/// assert_eq!(err.path(), invalid);
+/// ```
+#[derive(Debug, Clone)]
+pub struct InvalidUnicodePath(PathBuf);
+
+impl InvalidUnicodePath {
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Display for InvalidUnicodePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the path `{}` contained invalid unicode",
+            style_stderr!(bold(), "{}", self.0.display())
+        )
+    }
+}
+
+impl Error for InvalidUnicodePath {}
+
 impl Default for ImStr {
     fn default() -> Self {
         Self::Static("")
@@ -133,3 +195,31 @@ impl Hash for ImStr {
         self.as_str().hash(state);
     }
 }
+
+/// Serializes as a plain string, regardless of variant.
+///
+/// ```
+/// use dmm::imstr::ImStr;
+/// use toml::Value;
+///
+/// let heap = Value::try_from(ImStr::from("dmenu")).unwrap();
+/// let unallocated = Value::try_from(ImStr::new("dmenu")).unwrap();
+/// assert_eq!(heap, unallocated);
+///
+/// let string: ImStr = heap.try_into().unwrap();
+/// assert_eq!(string, ImStr::new("dmenu"));
+/// ```
+#[cfg(feature = "serde")]
+impl serde::Serialize for ImStr {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self)
+    }
+}
+
+/// Deserializes from a string into [`ImStr::Heap`].
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ImStr {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self::from)
+    }
+}
@@ -1,4 +1,13 @@
+pub mod cache;
+pub mod collate;
 pub mod config;
+pub mod dotenv;
+pub mod fold;
+pub mod fuzzy;
+pub mod glob;
 pub mod imstr;
+pub mod log;
+pub mod menu;
 pub mod style;
 pub mod tag;
+pub mod usage;
@@ -0,0 +1,88 @@
+use std::fmt::Arguments;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use termcolor::{Color, ColorSpec, StandardStream};
+
+use crate::style::{stderr_color_choice, write_style};
+
+/// Whether diagnostics should be written to stderr.
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+/// Whether errors, warnings and diagnostics should be written to stderr as lines of json.
+static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn set_verbose(verbose: bool) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+pub fn is_verbose() -> bool {
+    VERBOSE.load(Ordering::Relaxed)
+}
+
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::Relaxed);
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Represent `err` as a json object, for `--error-format json`.
+///
+/// The object has the `level` it's reported at, the outermost `message`,
+/// and the `chain` of causes below it, outermost first.
+///
+/// ```
+/// use anyhow::{anyhow, Context};
+/// use dmm::log::error_json;
+///
+/// let err = Err::<(), _>(anyhow!("it doesn't exist"))
+///     .context("unable to read config file `missing.toml`")
+///     .unwrap_err();
+///
+/// let line = error_json("error", &err).to_string();
+/// let parsed = serde_json::from_str::<serde_json::Value>(&line).unwrap();
+/// assert_eq!(parsed["level"], "error");
+/// assert_eq!(parsed["message"], "unable to read config file `missing.toml`");
+/// assert_eq!(parsed["chain"], serde_json::json!(["it doesn't exist"]));
+/// ```
+pub fn error_json(level: &str, err: &anyhow::Error) -> serde_json::Value {
+    let mut chain = err.chain().map(ToString::to_string);
+    let message = chain.next().unwrap_or_default();
+
+    serde_json::json!({
+        "level": level,
+        "message": message,
+        "chain": chain.collect::<Vec<String>>(),
+    })
+}
+
+/// Write a diagnostic message to stderr, regardless of whether verbose output is enabled.
+///
+/// Prefer [`verbose!`], which only formats the message if verbose output is enabled.
+pub fn write_verbose(message: Arguments<'_>) {
+    if is_json() {
+        let message = serde_json::json!({ "level": "info", "message": message.to_string() });
+        eprintln!("{message}");
+        return;
+    }
+
+    let mut stderr = StandardStream::stderr(stderr_color_choice());
+    let style = ColorSpec::new()
+        .set_fg(Some(Color::Cyan))
+        .set_bold(true)
+        .clone();
+
+    write_style!(stderr, style, "info: ");
+    eprintln!("{message}");
+}
+
+#[macro_export]
+macro_rules! verbose {
+    ($($token:tt)+) => {
+        if $crate::log::is_verbose() {
+            $crate::log::write_verbose(format_args!($($token)+));
+        }
+    }
+}
+
+pub use verbose;
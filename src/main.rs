@@ -1,86 +1,585 @@
 use std::borrow::Cow;
+use std::cmp::Reverse;
 use std::ffi::OsString;
 use std::fs::ReadDir;
-use std::io::Write;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
-use std::{env, fs, panic, process, thread};
+use std::io::{self, BufRead, ErrorKind, Write};
+use std::ops::RangeInclusive;
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use std::{env, fs, mem, process, ptr, thread};
 
-use ahash::HashMap;
+use ahash::{HashMap, HashSet};
 use anyhow::{anyhow, Context};
+use ignore::{WalkBuilder, WalkState};
 use is_executable::IsExecutable;
 use termcolor::{Color, ColorSpec, StandardStream};
 
-use dmm::config::{self, BinPath, Config, Custom, Entry, Run, Shell};
+use dmm::cache::Cache;
+use dmm::collate::Collator;
+use dmm::config::{
+    self, escape_markup, shell_quote, Bell, BinPath, Config, Custom, DefaultEntry, Entry, GlobArgs,
+    Run, RunOptions, Shell, Sort, Source, TagMode,
+};
+use dmm::fold::{fold_ascii, nfc};
+use dmm::fuzzy;
+use dmm::glob;
 use dmm::imstr::ImStr;
+use dmm::log::verbose;
+use dmm::menu;
 use dmm::style::{bold, stderr_color_choice, style_stderr, write_style};
-use dmm::tag::{Binary, Decimal, Tag};
+use dmm::tag::{contains_tag_chars, strip_tags, Encoding};
+use dmm::usage::{self, Usage};
 
 #[derive(Debug, Clone)]
 struct RunEntry {
     name: ImStr,
+    /// The output of the entry's label command, displayed in place of its name.
+    label: Option<ImStr>,
+    /// What `config.path.describe` printed for the entry, displayed after its name.
+    description: Option<ImStr>,
     run: Run,
     group: i64,
+    options: RunOptions,
+    source: Source,
 }
 
 impl RunEntry {
     fn try_from(entry: Entry, shell_is_enabled: bool) -> Option<Self> {
         match entry {
-            Entry::Full { name, run, group } => Some(Self { name, run, group }),
-            Entry::Name(name) => Some(Self {
+            Entry::Full {
+                name,
+                run,
+                group,
+                options,
+                source,
+                ..
+            } => Some(Self {
+                name,
+                label: None,
+                description: None,
+                run,
+                group,
+                options: *options,
+                source,
+            }),
+            Entry::Name { name, source } => Some(Self {
                 run: if shell_is_enabled {
                     Run::Shell(name.clone())
                 } else {
                     Run::binary(name.clone())
                 },
                 name,
+                label: None,
+                description: None,
                 group: 0,
+                options: RunOptions::default(),
+                source,
             }),
             Entry::Filter(_) => None,
         }
     }
+
+    /// The text shown for the entry in the menu: its label if it has one, or else its name,
+    /// followed by its description.
+    fn display_name(&self) -> Cow<'_, str> {
+        let name = self.label.as_ref().unwrap_or(&self.name);
+        match &self.description {
+            Some(description) => Cow::from(format!("{name} - {description}")),
+            None => Cow::from(name.as_str()),
+        }
+    }
+}
+
+/// How long each phase of the run took, or `None` unless `--time` was passed.
+static TIMINGS: Mutex<Option<Vec<Timing>>> = Mutex::new(None);
+
+/// The bell to ring the first time an error or warning is reported.
+/// It's taken once rung, so it only rings once per run.
+static BELL: Mutex<Option<Ring>> = Mutex::new(None);
+
+/// The cache file that keeps the last label each label command printed.
+const LABELS_FILE: &str = "labels.toml";
+/// The cache file that keeps the descriptions of programs found in `config.path`.
+const DESCRIPTIONS_FILE: &str = "descriptions.toml";
+
+/// How `config.bell` is rung, prepared ahead of time since it may ring from any thread.
+enum Ring {
+    Terminal,
+    /// The command, and the shell command to write to its stdin if the shell is piped.
+    Command(Box<Command>, Option<String>),
+}
+
+struct Timing {
+    phase: &'static str,
+    elapsed: Duration,
+    /// Whether the phase was spent waiting on the user, rather than on dmm.
+    interactive: bool,
 }
 
 fn main() {
-    if let Err(err) = (|| -> anyhow::Result<()> {
-        let config = config::get()?;
+    let start = Instant::now();
+    let result = (|| -> anyhow::Result<()> {
+        let mut config = config::get()?;
+        set_bell(&config);
+        // Whether to time the run isn't known until the config is loaded, so it's timed from the start.
+        if config.args.get_flag("time") {
+            *TIMINGS.lock().expect("unreachable") = Some(vec![Timing {
+                phase: "loading the config",
+                elapsed: start.elapsed(),
+                interactive: false,
+            }]);
+        }
+        for warning in &config.warnings {
+            warn_error(warning);
+        }
+
+        if config.args.get_flag("dump-config") {
+            print!("{}", config.to_toml()?);
+            return Ok(());
+        }
+
+        if config.args.get_flag("export-history") {
+            let usage = Usage::load(&config.cache).to_json();
+            println!("{}", serde_json::to_string_pretty(&usage)?);
+            return Ok(());
+        }
+
+        if let Some(path) = config.args.get_one::<String>("import-history") {
+            return import_history(&config, path);
+        }
+
+        if let Some(what) = config.args.get_one::<String>("clear-cache") {
+            return clear_cache(&config, what);
+        }
+
+        if let Some(name) = config.args.get_one::<String>("which").cloned() {
+            return print_entry(&mut config, &name);
+        }
 
-        let commands = if config.numbered.is_enabled() {
-            get_selection::<Decimal>(&config)?
+        let encoding = if config.numbered.is_enabled() {
+            Encoding::Decimal
         } else {
-            get_selection::<Binary>(&config)?
+            Encoding::Binary
+        };
+        let mut usage = time("loading history", false, || Usage::load(&config.cache));
+        if config.args.get_flag("list") {
+            return list_entries(&mut config, &usage);
+        }
+        let mut commands = match config.args.get_one::<usize>("run-index") {
+            Some(&index) => vec![entry_at_index(&mut config, &usage, index)?],
+            None => get_selection(&mut config, &usage, encoding)?,
         };
 
-        run_commands(&commands, &config)
-    })() {
+        if config.args.get_one::<String>("output").is_some() {
+            print_selection_json(&commands)?;
+            return Ok(());
+        }
+
+        commands.retain(
+            |entry| match time("confirming", true, || confirm(&config, entry)) {
+                Ok(confirmed) => confirmed,
+                Err(err) => {
+                    warn_error(&err.context(format!(
+                        "not running `{}`",
+                        style_stderr!(bold(), "{}", entry.name)
+                    )));
+                    false
+                }
+            },
+        );
+
+        if config.args.get_flag("no-run") {
+            let mut tty = fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open("/dev/tty")
+                .ok();
+            if tty.is_none() {
+                verbose!("there's no terminal to ask on, so commands are output instead of run");
+            }
+            commands.retain(|entry| {
+                match time("asking to run", true, || {
+                    ask_to_run(&config, entry, tty.as_mut())
+                }) {
+                    Ok(run) => run,
+                    Err(err) => {
+                        warn_error(&err.context(format!(
+                            "not running `{}`",
+                            style_stderr!(bold(), "{}", entry.name)
+                        )));
+                        false
+                    }
+                }
+            });
+        }
+
+        record_and_run(&config, &mut usage, &commands)
+    })();
+
+    print_timings(start);
+    if let Err(err) = result {
         display_error(&err);
         process::exit(1);
     }
 }
 
-fn get_selection<T: Tag>(config: &Config) -> anyhow::Result<Vec<Run>> {
-    let entries = build_entries(config)?;
-    let menu_display = display_entries::<T>(config, &entries);
-    let choices = run_dmenu(menu_display, &config.dmenu.args()).context("problem running dmenu")?;
+/// Record the runs of `commands` in the cache, then run them.
+///
+/// Failing to save the runs is only a warning, so the commands still run.
+fn record_and_run(config: &Config, usage: &mut Usage, commands: &[RunEntry]) -> anyhow::Result<()> {
+    let history_max = config.history_max.0.try_into().unwrap_or(usize::MAX);
+    if record_usage(usage, commands, history_max, usage::now()) {
+        if let Err(err) = usage.save(&config.cache) {
+            warn_error(&err);
+        }
+    }
+
+    time("running commands", false, || run_commands(commands, config))
+}
+
+/// Count a run of each of `commands` at `now`, adding custom commands to the history instead,
+/// and returning whether anything was recorded.
+///
+/// Entries with `track = false` aren't recorded.
+fn record_usage(usage: &mut Usage, commands: &[RunEntry], history_max: usize, now: u64) -> bool {
+    let mut recorded = false;
+    for entry in commands {
+        if entry.options.track == Some(false) {
+            verbose!(
+                "not recording the run of `{}`, since it isn't tracked",
+                entry.name
+            );
+            continue;
+        }
+
+        if entry.source == Source::Custom {
+            usage.add_history(entry.name.clone(), history_max);
+        } else {
+            usage.add_run(entry.name.clone(), now);
+        }
+        recorded = true;
+    }
+
+    recorded
+}
+
+/// Run `f`, recording how long it took as `phase` if `--time` was passed.
+fn time<T>(phase: &'static str, interactive: bool, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    if let Some(timings) = TIMINGS.lock().expect("unreachable").as_mut() {
+        timings.push(Timing {
+            phase,
+            elapsed: start.elapsed(),
+            interactive,
+        });
+    }
+    result
+}
+
+/// Output the time each recorded phase took, then dmm's overhead:
+/// the total time, minus the time spent waiting on the user.
+fn print_timings(start: Instant) {
+    let total = start.elapsed();
+    let Some(timings) = TIMINGS.lock().expect("unreachable").take() else {
+        return;
+    };
+    let waiting = timings
+        .iter()
+        .filter(|timing| timing.interactive)
+        .map(|timing| timing.elapsed)
+        .sum::<Duration>();
+    let overhead = total.saturating_sub(waiting);
+
+    if dmm::log::is_json() {
+        let phases = timings
+            .iter()
+            .map(|timing| {
+                serde_json::json!({
+                    "phase": timing.phase,
+                    "seconds": timing.elapsed.as_secs_f64(),
+                    "interactive": timing.interactive,
+                })
+            })
+            .collect::<Vec<serde_json::Value>>();
+        let timings = serde_json::json!({
+            "level": "time",
+            "phases": phases,
+            "overhead": overhead.as_secs_f64(),
+            "waiting": waiting.as_secs_f64(),
+        });
+        eprintln!("{timings}");
+        return;
+    }
+
+    let width = timings
+        .iter()
+        .map(|timing| timing.phase.len())
+        .max()
+        .unwrap_or_default();
+    for timing in &timings {
+        let waiting = if timing.interactive { " (waiting)" } else { "" };
+        eprintln!(
+            "time: {:width$}  {:>10.3?}{waiting}",
+            timing.phase, timing.elapsed
+        );
+    }
+    eprintln!("time: overhead {overhead:.3?}, plus {waiting:.3?} waiting for input");
+}
+
+/// Output `commands` as json instead of running them: an object for one, or an array for several.
+fn print_selection_json(commands: &[RunEntry]) -> anyhow::Result<()> {
+    let selections = commands
+        .iter()
+        .map(|entry| {
+            let source = match entry.source {
+                Source::Pattern => "pattern",
+                Source::Home => "home",
+                Source::Path => "path",
+                Source::Custom => "custom",
+            };
+            let command = match &entry.run {
+                Run::Shell(run) => serde_json::Value::from(run.as_str()),
+                Run::Bare(run) => run.iter().map(ImStr::as_str).collect(),
+            };
+
+            serde_json::json!({
+                "name": entry.name.as_str(),
+                "command": command,
+                "source": source,
+                "custom": entry.source == Source::Custom,
+            })
+        })
+        .collect::<Vec<serde_json::Value>>();
+
+    match selections.as_slice() {
+        [] => {}
+        [selection] => println!("{}", serde_json::to_string_pretty(selection)?),
+        _ => println!("{}", serde_json::to_string_pretty(&selections)?),
+    }
+
+    Ok(())
+}
+
+/// Merge usage exported as json from the file at `path`, or stdin if it's `-`, into the cache.
+fn import_history(config: &Config, path: &str) -> anyhow::Result<()> {
+    let json = if path == "-" {
+        config::read_stdin()?
+    } else {
+        fs::read_to_string(path).context(format!(
+            "unable to read history file `{}`",
+            style_stderr!(bold(), "{path}")
+        ))?
+    };
+    let imported = serde_json::from_str(&json)
+        .map_err(anyhow::Error::from)
+        .and_then(|json| Usage::from_json(&json))
+        .context(format!(
+            "{} doesn't contain history exported by `{}`",
+            if path == "-" {
+                String::from("stdin")
+            } else {
+                format!("`{}`", style_stderr!(bold(), "{path}"))
+            },
+            style_stderr!(bold(), "--export-history")
+        ))?;
+
+    if config.cache.dir().is_none() {
+        return Err(anyhow!(
+            "there's nowhere to save the history, since `{}` is disabled",
+            style_stderr!(bold(), "config.cache-dir")
+        ));
+    }
+
+    let mut usage = Usage::load(&config.cache);
+    let history_max = config.history_max.0.try_into().unwrap_or(usize::MAX);
+    usage.merge(imported, history_max);
+    usage.save(&config.cache)
+}
+
+/// Delete `what` from the cache, outputting what was removed:
+/// `frequency`, `history`, `labels`, `path`, or `all` of them.
+fn clear_cache(config: &Config, what: &str) -> anyhow::Result<()> {
+    let Some(dir) = config.cache.dir() else {
+        return Err(anyhow!(
+            "there's no cache to clear, since `{}` is disabled",
+            style_stderr!(bold(), "config.cache-dir")
+        ));
+    };
+
+    let all = what == "all";
+    let mut cleared = Vec::new();
+    let frequency = all || what == "frequency";
+    let history = all || what == "history";
+    if (frequency || history) && Usage::clear(&config.cache, frequency, history)? {
+        cleared.push(match (frequency, history) {
+            (true, true) => "frequency and history",
+            (true, false) => "frequency",
+            _ => "history",
+        });
+    }
+    if (all || what == "labels") && config.cache.remove(LABELS_FILE)? {
+        cleared.push("labels");
+    }
+    if (all || what == "path") && config.cache.remove(DESCRIPTIONS_FILE)? {
+        cleared.push("path descriptions");
+    }
+
+    if cleared.is_empty() {
+        println!("nothing to clear in `{}`", dir.display());
+    }
+    for cleared in cleared {
+        println!("cleared {cleared} from `{}`", dir.display());
+    }
+    Ok(())
+}
+
+fn get_selection(
+    config: &mut Config,
+    usage: &Usage,
+    encoding: Encoding,
+) -> anyhow::Result<Vec<RunEntry>> {
+    let entries = time("building entries", false, || build_entries(config, usage))?;
+    let (shown, menu_display, default_line) = time("rendering the menu", false, || {
+        let mut shown = filter_entries(config, &entries);
+        let default_line = place_default(config, usage, &mut shown);
+        let menu_display = display_entries(config, &shown, encoding);
+        (shown, menu_display, default_line)
+    });
+    if config.dmenu.show_count {
+        let count = shown.len();
+        config.dmenu.prompt = Some(ImStr::from(match &config.dmenu.prompt {
+            Some(prompt) => format!("{prompt} ({count})"),
+            None => format!("({count})"),
+        }));
+    }
+    let mut dmenu_args = config.dmenu.args(config.backend);
+    // Rofi is the only backend that supports markup.
+    if uses_markup(config) {
+        dmenu_args.push(Cow::from("-markup-rows"));
+    }
+    if let (Some(flag), Some(line)) = (config.backend.preselect_flag(), default_line) {
+        dmenu_args.push(Cow::from(flag));
+        dmenu_args.push(Cow::from(line.to_string()));
+    }
+    if config.backend.supports_accelerators() {
+        for (i, (key, _)) in config.accelerators.0.iter().enumerate() {
+            dmenu_args.push(Cow::from(format!("-kb-custom-{}", i + 1)));
+            dmenu_args.push(Cow::from(key.as_str()));
+        }
+    }
+    let dmenu_argv = config.dmenu.argv(config.backend, &dmenu_args);
+    verbose!(
+        "running dmenu `{}` with arguments `{}`",
+        dmenu_argv[0],
+        dmenu_argv[1..].join(" ")
+    );
+    if config.args.get_flag("debug-menu") {
+        print_menu_debug(&dmenu_argv, &menu_display);
+    }
+    let (choices, status) = time("in dmenu", true, || {
+        run_dmenu_with_retries(config, menu_display, &dmenu_argv)
+    })?;
+
+    // An accelerator runs its entry no matter what was highlighted or typed.
+    if let Some((key, name)) = accelerator(config, status) {
+        verbose!("`{key}` was pressed, so `{name}` is run");
+        let entry = entries
+            .iter()
+            .find(|entry| entry.name == *name)
+            .with_context(|| {
+                format!(
+                    "`{}` runs `{}`, but no entry has that name",
+                    style_stderr!(bold(), "config.accelerators.{key}"),
+                    style_stderr!(bold(), "{name}"),
+                )
+            })?;
+        return Ok(vec![entry.clone()]);
+    }
+    let headers = shown
+        .iter()
+        .filter_map(|(_, entry)| group_header(config, entry.group))
+        .collect::<HashSet<String>>();
+
+    Ok(parse_choices(
+        config, &entries, &headers, &choices, encoding,
+    ))
+}
+
+/// Find the entry for each line dmenu output, skipping group headers and blank lines.
+///
+/// Any other line is warned about and skipped, unless it can run as a custom command.
+/// A line matching a header is skipped too, with a warning if custom commands are enabled,
+/// since it might have been typed rather than selected.
+fn parse_choices(
+    config: &Config,
+    entries: &[RunEntry],
+    headers: &HashSet<String>,
+    choices: &str,
+    encoding: Encoding,
+) -> Vec<RunEntry> {
     let choices = choices
         .split('\n')
         .filter(|choice| !choice.trim().is_empty());
 
-    let commands = choices
+    let mut commands = choices
         .filter_map(|choice| {
-            if let Some(id) = T::pop_tag(choice) {
-                let entry = entries
-                    .get(id)
-                    .expect("logic error: mismatch between entry tag and entry index");
+            // A typed command is indistinguishable from the header it matches.
+            if headers.contains(choice) {
+                if let Custom::Enabled = config.custom {
+                    warn_error(
+                        &anyhow!("it matches a group header, so it can't run as a custom command")
+                            .context(format!(
+                                "skipping selection `{}`",
+                                style_stderr!(&bold(), "{choice}")
+                            )),
+                    );
+                }
+                None
+            } else if let Some(id) = encoding.pop_tag(choice) {
+                let entry = entries.get(id).cloned();
+                if entry.is_none() {
+                    let err = anyhow!(
+                        "its tag refers to entry {id}, but there are only {} entries; \
+                         the launcher may have altered it",
+                        entries.len()
+                    )
+                    .context(format!(
+                        "skipping selection `{}`",
+                        style_stderr!(&bold(), "{choice}")
+                    ));
+                    warn_error(&err);
+                }
 
-                Some(entry.run.clone())
+                entry
+            } else if let Some(entry) = find_by_name(config, entries, choice) {
+                Some(entry.clone())
             } else if let Custom::Enabled = config.custom {
-                Some(Run::Shell(choice.into()))
+                Some(RunEntry {
+                    name: choice.into(),
+                    label: None,
+                    description: None,
+                    run: Run::Shell(choice.into()),
+                    group: 0,
+                    options: RunOptions::default(),
+                    source: Source::Custom,
+                })
             } else {
-                let err = anyhow!(
-                    "ad-hoc commands are disabled; consider setting `config.custom = true`"
-                )
-                .context(format!(
+                let mut message = String::from(
+                    "ad-hoc commands are disabled; consider setting `config.custom = true`",
+                );
+                let names = entries.iter().map(|entry| entry.name.as_str());
+                if let Some(similar) = similar_names(choice.trim(), names).first() {
+                    message.push_str(&format!(
+                        "; did you mean `{}`?",
+                        style_stderr!(&bold(), "{similar}")
+                    ));
+                }
+                let err = anyhow!(message).context(format!(
                     "can't run `{}`",
                     style_stderr!(&bold(), "{choice}")
                 ));
@@ -89,331 +588,1765 @@ fn get_selection<T: Tag>(config: &Config) -> anyhow::Result<Vec<Run>> {
                 None
             }
         })
-        .collect();
+        .collect::<Vec<RunEntry>>();
+
+    if config.dedup_selections.0 {
+        let mut seen = HashSet::default();
+        commands.retain(|entry| seen.insert(entry.run.clone()));
+    }
+
+    commands
+}
+
+/// The entries to show in the menu, paired with their index in `entries`.
+///
+/// With `--filter`, only entries whose names fuzzy-match the query are shown,
+/// best matches first within each group.
+fn filter_entries<'a>(config: &Config, entries: &'a [RunEntry]) -> Vec<(usize, &'a RunEntry)> {
+    let query = config
+        .args
+        .get_one::<String>("filter")
+        .filter(|query| !query.is_empty());
+    let Some(query) = query else {
+        return entries.iter().enumerate().collect();
+    };
+
+    let mut matches = entries
+        .iter()
+        .enumerate()
+        .filter_map(|(i, entry)| Some((fuzzy::score(query, &entry.display_name())?, i, entry)))
+        .collect::<Vec<(i64, usize, &RunEntry)>>();
+    // Entries are already ordered by group, so a stable sort keeps each group together.
+    matches.sort_by_key(|&(score, _, entry)| (config.group_order.key(entry.group), Reverse(score)));
+    verbose!(
+        "{} of {} entries match the filter `{query}`",
+        matches.len(),
+        entries.len()
+    );
+
+    matches
+        .into_iter()
+        .map(|(_, i, entry)| (i, entry))
+        .collect()
+}
+
+/// Find the entry `config.default` refers to in `shown`, returning the menu line to preselect.
+///
+/// Backends that can't preselect a line start at the top, so the entry is moved there instead.
+fn place_default(
+    config: &Config,
+    usage: &Usage,
+    shown: &mut Vec<(usize, &RunEntry)>,
+) -> Option<usize> {
+    let name = match &config.default {
+        DefaultEntry::None => return None,
+        DefaultEntry::Entry(name) => name,
+        DefaultEntry::Last => usage.last_run()?,
+    };
+    let Some(i) = shown.iter().position(|(_, entry)| entry.name == *name) else {
+        verbose!("the default entry `{name}` isn't in the menu");
+        return None;
+    };
+
+    if config.backend.preselect_flag().is_none() {
+        let default = shown.remove(i);
+        shown.insert(0, default);
+        return Some(0);
+    }
+
+    // Group headers are lines of the menu too.
+    let mut last_group = None;
+    let headers = shown[..=i]
+        .iter()
+        .filter(|(_, entry)| {
+            let starts_group = last_group != Some(entry.group);
+            last_group = Some(entry.group);
+            starts_group && group_header(config, entry.group).is_some()
+        })
+        .count();
+    Some(i + headers)
+}
+
+/// Find the entry whose name matches an untagged `choice`, if `config.tag` allows it.
+fn find_by_name<'a>(
+    config: &Config,
+    entries: &'a [RunEntry],
+    choice: &str,
+) -> Option<&'a RunEntry> {
+    if config.tag != TagMode::Auto {
+        return None;
+    }
+
+    let name = strip_tags(choice);
+    let name = strip_number(config, name.trim());
+    let entry = entries.iter().find(|entry| {
+        let display_name = entry.display_name();
+        let entry_name = sanitize_name(&display_name);
+        entry_name == name
+            || folded_region(config, &entry_name)
+                .is_some_and(|folded| format!("{entry_name}{folded}") == name)
+    })?;
+    verbose!("`{name}` has no tag, so it was matched to an entry by name");
+
+    Some(entry)
+}
+
+/// Remove the index and separator that `config.numbered` shows before each name.
+fn strip_number<'a>(config: &Config, line: &'a str) -> &'a str {
+    if !config.numbered.is_enabled() {
+        return line;
+    }
+
+    let rest = line.trim_start_matches(|c: char| c.is_ascii_digit());
+    if rest.len() == line.len() {
+        return line;
+    }
+    rest.strip_prefix(config.numbered.separator())
+        .unwrap_or(line)
+}
+
+/// Build the entries and order them as the menu would show them, without running dmenu.
+fn menu_order(config: &mut Config, usage: &Usage) -> anyhow::Result<Vec<RunEntry>> {
+    let entries = time("building entries", false, || build_entries(config, usage))?;
+    let mut shown = filter_entries(config, &entries);
+    place_default(config, usage, &mut shown);
+    Ok(shown.into_iter().map(|(_, entry)| entry.clone()).collect())
+}
+
+/// Output each entry the menu would show, after its index for `--run-index`.
+fn list_entries(config: &mut Config, usage: &Usage) -> anyhow::Result<()> {
+    let mut stdout = io::stdout().lock();
+    for (i, entry) in menu_order(config, usage)?.iter().enumerate() {
+        writeln!(stdout, "{i}\t{}", entry.display_name())?;
+    }
+    Ok(())
+}
+
+/// Find the entry at `index` in the menu, as `--list` numbers them.
+fn entry_at_index(config: &mut Config, usage: &Usage, index: usize) -> anyhow::Result<RunEntry> {
+    let mut entries = menu_order(config, usage)?;
+    if index >= entries.len() {
+        return Err(match entries.len() {
+            0 => anyhow!("there's no entry at index {index}, since the menu is empty"),
+            len => anyhow!(
+                "there's no entry at index {index}; the menu's indices are 0 to {}",
+                len - 1
+            ),
+        });
+    }
+    let entry = entries.swap_remove(index);
+    verbose!("entry {index} is `{}`", entry.name);
+    Ok(entry)
+}
+
+fn print_entry(config: &mut Config, name: &str) -> anyhow::Result<()> {
+    let entries = build_entries(config, &Usage::default())?;
+    let entry = entries.iter().find(|entry| entry.name.as_str() == name);
+
+    if let Some(entry) = entry {
+        let kind = match entry.run {
+            Run::Shell(_) => "shell",
+            Run::Bare(_) => "bare",
+        };
+
+        println!("name: {}", entry.name);
+        if let Some(label) = &entry.label {
+            println!("label: {label}");
+        }
+        println!("source: {}", entry.source);
+        println!("group: {}", entry.group);
+        println!("{kind}: {}", entry.run);
+        if let Some(stdin) = &entry.options.stdin {
+            println!("stdin: {}", stdin.escape_debug());
+        }
+
+        Ok(())
+    } else {
+        let similar = similar_names(name, entries.iter().map(|entry| entry.name.as_str()));
+        let no_entry = format!(
+            "no entry named `{}` exists",
+            style_stderr!(bold(), "{name}")
+        );
+
+        if similar.is_empty() {
+            Err(anyhow!(no_entry))
+        } else {
+            let similar = similar
+                .iter()
+                .map(|name| format!("`{}`", style_stderr!(bold(), "{name}")))
+                .collect::<Vec<String>>()
+                .join(", ");
+            Err(anyhow!("similarly named entries: {similar}").context(no_entry))
+        }
+    }
+}
+
+/// Find up to five names similar to `name`, ordered from most to least similar.
+///
+/// Names containing `name` only count when `name` makes up at least half of them.
+fn similar_names<'a>(name: &str, names: impl Iterator<Item = &'a str>) -> Vec<&'a str> {
+    let name = name.to_lowercase();
+    let length = name.chars().count();
+    let max_distance = (length / 3).max(2);
+
+    let mut similar = names
+        .filter_map(|other| {
+            let lowercase = other.to_lowercase();
+            let distance = strsim::levenshtein(&name, &lowercase);
+
+            if distance <= max_distance {
+                Some((distance, other))
+            } else if length * 2 >= lowercase.chars().count() && lowercase.contains(&name) {
+                Some((max_distance + 1, other))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<(usize, &str)>>();
 
-    Ok(commands)
+    similar.sort_unstable();
+    similar.into_iter().take(5).map(|(_, name)| name).collect()
 }
 
-fn build_entries(config: &Config) -> anyhow::Result<Vec<RunEntry>> {
+/// Collect the entries to display in the menu, sorted by group and then by `config.sort`.
+///
+/// The entries are moved out of `config.entries`, leaving it empty.
+fn build_entries(config: &mut Config, usage: &Usage) -> anyhow::Result<Vec<RunEntry>> {
+    // Set up collation before starting any threads, since it changes the process's locale.
+    let collator = match config.sort {
+        Sort::Locale => {
+            // SAFETY: No other threads have been started yet, so none can be using the locale.
+            let collator = unsafe { Collator::new() };
+            if collator.is_none() {
+                verbose!("the locale has no collation rules; sorting entries by name instead");
+            }
+            collator
+        }
+        _ => None,
+    };
+
+    // Every entry's name, even if its conditions hide it, for `config.path.hide-duplicates`.
+    let entry_names = match config.path {
+        BinPath::Enabled {
+            hide_duplicates: true,
+            ..
+        } => config.entries.iter().map(Entry::name).collect(),
+        _ => HashSet::default(),
+    };
+    let config_entries = probe_entries(
+        mem::take(&mut config.entries)
+            .into_iter()
+            .filter(|entry| entry.when().is_met()),
+        &config.shell,
+    );
+    let labels = label_entries(&config_entries, &config.shell, &config.cache);
+
     let mut entries = if let BinPath::Enabled {
         path,
         env,
         replace,
         recursive,
         group,
+        describe,
+        shell,
+        ..
     } = &config.path
     {
-        let mut entries = Vec::new();
-        let mut menu_entries = config
-            .entries
+        let mut bin_entries = Vec::new();
+        // Kept in a `Vec` to preserve the order of the config, with an index to look up names.
+        let menu_index = config_entries
             .iter()
-            .map(|entry| {
-                (
-                    entry.name(),
-                    RunEntry::try_from(entry.clone(), !config.shell.is_enabled()),
-                )
-            })
-            .collect::<HashMap<ImStr, Option<RunEntry>>>();
+            .enumerate()
+            .map(|(i, entry)| (entry.name(), i))
+            .collect::<HashMap<ImStr, usize>>();
+        let mut menu_entries = config_entries
+            .into_iter()
+            .map(|entry| RunEntry::try_from(entry, config.shell.is_enabled()))
+            .collect::<Vec<Option<RunEntry>>>();
 
         let env_paths = env.then(|| env::var_os("PATH")).flatten();
         let env_paths = env_paths
             .as_ref()
             .map(env::split_paths)
             .into_iter()
-            .flatten();
+            .flatten()
+            .map(|path| (path, *group));
 
+        // Each directory's binaries are in its own group, if it has one.
         let paths = path
             .iter()
-            .map(|pathstr| {
-                if pathstr.starts_with("~/") {
-                    let start = '~'.len_utf8() + '/'.len_utf8();
-                    let mut path = PathBuf::new();
-                    path.push(config.base_dirs.home_dir());
-                    path.push(&pathstr[start..]);
-                    path
+            .filter_map(|path_dir| {
+                let pathstr = &path_dir.dir;
+                let group = path_dir.group.unwrap_or(*group);
+                if let Some(relative) = pathstr.strip_prefix("~/") {
+                    let Some(base_dirs) = &config.base_dirs else {
+                        verbose!("skipping directory `{pathstr}`: no home directory was detected");
+                        return None;
+                    };
+                    Some((base_dirs.home_dir().join(relative), group))
                 } else {
-                    PathBuf::from(pathstr.as_str())
+                    Some((PathBuf::from(pathstr.as_str()), group))
                 }
             })
             .chain(env_paths);
 
-        let path_bins = paths.filter_map(|path| {
-            let mut files = Vec::new();
-            let mut recur = Vec::new();
+        let path_bins = paths.filter_map(|(path, group)| {
+            if *recursive {
+                if !path.is_dir() {
+                    verbose!("skipping directory `{}`: not a directory", path.display());
+                    return None;
+                }
+                verbose!("searching directory `{}` recursively", path.display());
+                return Some((Ok(walk_dir_recursive(&path)), group));
+            }
 
             match fs::read_dir(&path) {
                 Ok(dir) => {
-                    if let Err(err) = walk_dir(dir, &mut recur, &mut files) {
-                        return Some(Err(err));
-                    }
+                    verbose!("searching directory `{}`", path.display());
+                    Some((walk_dir(dir), group))
                 }
-                Err(_) => return None,
-            }
-
-            if *recursive {
-                while let Some(path) = recur.pop() {
-                    match fs::read_dir(&path) {
-                        Ok(dir) => {
-                            if let Err(err) = walk_dir(dir, &mut recur, &mut files) {
-                                return Some(Err(err));
-                            }
-                        }
-                        Err(_) => continue,
-                    }
+                Err(err) => {
+                    verbose!("skipping directory `{}`: {err}", path.display());
+                    None
                 }
             }
-
-            Some(Ok(files))
         });
 
-        for bins in path_bins {
+        for (bins, group) in path_bins {
             let bins = bins?;
-            let mut bin_entries = Vec::new();
 
             for (path, name) in bins {
-                let path = path.into_string().map_err(|path| {
-                    anyhow!(
-                        "the path `{}` contained invalid unicode",
-                        style_stderr!(bold(), "{}", path.to_string_lossy())
-                    )
-                });
-                let path = match path {
-                    Ok(path) => ImStr::from(path),
+                let name = config
+                    .normalize_names
+                    .0
+                    .then(|| nfc(&name))
+                    .flatten()
+                    .map_or(name, ImStr::from);
+                let path = match ImStr::try_from(PathBuf::from(path)) {
+                    Ok(path) => path,
                     Err(err) => {
-                        warn_error(&err);
+                        warn_error(&anyhow!(err));
                         continue;
                     }
                 };
 
-                if menu_entries.contains_key(&name) {
-                    if *replace {
-                        let menu_entry = menu_entries.get_mut(&name).expect("unreachable");
-                        if menu_entry.is_some() {
-                            let run_entry = menu_entry.take().expect("unreachable");
-                            bin_entries.push(RunEntry {
-                                name,
-                                run: Run::binary(path),
-                                group: run_entry.group,
-                            });
-                        }
+                if let Some(&i) = menu_index.get(&name) {
+                    let menu_entry = &mut menu_entries[i];
+                    // Only the first program found replaces an entry, like a shell searching PATH.
+                    let replaceable = menu_entry
+                        .as_ref()
+                        .is_some_and(|entry| entry.source != Source::Path);
+                    if *replace && replaceable {
+                        let run_entry = menu_entry.take().expect("unreachable");
+                        *menu_entry = Some(RunEntry {
+                            name,
+                            label: run_entry.label,
+                            description: None,
+                            run: Run::binary(path),
+                            group: run_entry.group,
+                            options: run_entry.options,
+                            source: Source::Path,
+                        });
                     }
+                } else if entry_names.contains(&name) {
+                    verbose!("leaving out `{name}`, since an entry has the same name");
                 } else {
                     bin_entries.push(RunEntry {
                         name,
+                        label: None,
+                        description: None,
                         run: Run::binary(path),
-                        group: *group,
+                        group,
+                        options: RunOptions::default(),
+                        source: Source::Path,
                     });
                 }
             }
+        }
 
-            entries.extend(bin_entries);
+        if let Some(describe) = describe {
+            let descriptions = time("describing entries", false, || {
+                describe_entries(&bin_entries, describe, &config.cache)
+            });
+            for entry in &mut bin_entries {
+                entry.description = entry
+                    .run
+                    .program()
+                    .and_then(|path| descriptions.get(path))
+                    .cloned();
+            }
         }
 
-        entries.extend(menu_entries.into_iter().filter_map(|(_, entry)| entry));
+        let mut entries = menu_entries
+            .into_iter()
+            .flatten()
+            .chain(bin_entries)
+            .collect::<Vec<RunEntry>>();
+
+        // Only once they're described, since descriptions are found by the binary's path.
+        if *shell && config.shell.is_enabled() {
+            for entry in entries
+                .iter_mut()
+                .filter(|entry| entry.source == Source::Path)
+            {
+                if let Some(path) = entry.run.program() {
+                    entry.run = Run::Shell(ImStr::from(shell_quote(path)));
+                }
+            }
+        }
 
         entries
     } else {
-        config
-            .entries
-            .iter()
-            .filter_map(|entry| RunEntry::try_from(entry.clone(), !config.shell.is_enabled()))
+        config_entries
+            .into_iter()
+            .filter_map(|entry| RunEntry::try_from(entry, config.shell.is_enabled()))
             .collect::<Vec<RunEntry>>()
     };
 
-    entries.sort_unstable_by(|l, r| {
-        let by_group = l.group.cmp(&r.group).reverse();
-        let by_lowercase_name = || {
-            l.name
-                .to_ascii_lowercase()
-                .cmp(&r.name.to_ascii_lowercase())
-        };
-        let by_name = || l.name.cmp(&r.name);
+    for entry in &mut entries {
+        entry.label = labels.get(&entry.name).cloned();
+    }
 
-        by_group.then_with(by_lowercase_name).then_with(by_name)
-    });
+    // Sort by group, then by frecency if enabled, then by name ignoring case,
+    // or by the locale's collation if enabled, then by name to break ties.
+    // Each key is only computed once, rather than in every comparison.
+    // Without sorting, only group entries; the sort is stable, keeping the source order.
+    let now = usage::now();
+    if config.sort == Sort::None {
+        entries.sort_by_key(|entry| config.group_order.key(entry.group));
+    } else {
+        entries.sort_by_cached_key(|entry| {
+            let frecency = match config.sort {
+                Sort::Name | Sort::Locale | Sort::None => 0.0,
+                Sort::Frecency => usage
+                    .record(&entry.name)
+                    .frecency(now, config.frecency.half_life),
+            };
 
-    Ok(entries)
-}
+            (
+                config.group_order.key(entry.group),
+                // Frecency is never negative, and the bits of positive floats sort like the floats.
+                Reverse(frecency.to_bits()),
+                match &collator {
+                    Some(collator) => collator.sort_key(&entry.name),
+                    None if config.sort_case_sensitive.0 => entry.name.as_bytes().to_vec(),
+                    None => entry.name.to_ascii_lowercase().into_bytes(),
+                },
+                entry.name.clone(),
+            )
+        });
+    }
 
-fn walk_dir(
-    dir: ReadDir,
-    recur: &mut Vec<PathBuf>,
-    files: &mut Vec<(OsString, ImStr)>,
-) -> anyhow::Result<()> {
-    for entry in dir {
-        let entry = entry.context("error trying to walk PATH directory")?;
-        let filetype = entry.file_type().context("error reading file metadata")?;
-        let follow_symlink_is_dir = || {
-            fs::metadata(entry.path())
-                .context("error reading file metadata")
-                .map(|entry| entry.is_dir())
-                .map_err(|err| {
-                    err.context(format!("symlink `{}` is broken", entry.path().display()))
-                })
-                .unwrap_or_else(|err| {
-                    warn_error(&err);
-                    false
-                })
-        };
+    // Groups keep their order; only the entries within each are reversed.
+    if config.sort_reverse.0 {
+        entries
+            .chunk_by_mut(|a, b| a.group == b.group)
+            .for_each(<[RunEntry]>::reverse);
+    }
 
-        if filetype.is_dir() || follow_symlink_is_dir() {
-            recur.push(entry.path());
-        } else if entry.path().is_executable() {
-            files.push((
-                entry.path().into_os_string(),
-                entry.file_name().to_string_lossy().into(),
-            ));
+    for entry in &entries {
+        check_name(config, entry);
+    }
+
+    if dmm::log::is_verbose() {
+        for source in [Source::Pattern, Source::Home, Source::Path] {
+            let count = entries
+                .iter()
+                .filter(|entry| entry.source == source)
+                .count();
+            verbose!("found {count} entries from the {source}");
         }
     }
 
-    Ok(())
+    Ok(entries)
 }
 
-fn display_entries<T: Tag>(config: &Config, entries: &[RunEntry]) -> String {
-    let mut display = String::new();
-
-    if config.numbered.is_enabled() {
-        for (i, entry) in entries.iter().enumerate() {
-            T::push_tag(i, &mut display);
-            display.push_str(config.numbered.separator());
-            display.push_str(&entry.name);
-            display.push('\n');
-        }
+/// Warn about entry names that may make their menu line unselectable.
+fn check_name(config: &Config, entry: &RunEntry) {
+    let problem = if contains_tag_chars(&entry.name) {
+        "it contains zero-width characters used to tag menu lines"
+    } else if config.numbered.is_enabled()
+        && entry.source != Source::Path
+        && entry.name.starts_with(|c: char| c.is_ascii_digit())
+    {
+        // Programs in PATH like `2to3` are common enough that warning about them would be noise.
+        "it starts with a number, which may be confused with its entry number"
     } else {
-        for (i, entry) in entries.iter().enumerate() {
-            display.push_str(&entry.name);
-            T::push_tag(i, &mut display);
-            display.push('\n');
-        }
+        return;
+    };
+
+    let err = anyhow!("{problem}, so selecting it may not work").context(format!(
+        "entry `{}` may be unselectable",
+        style_stderr!(bold(), "{}", entry.name)
+    ));
+    warn_error(&err);
+}
+
+/// Run the probe of each entry in parallel, keeping only entries whose probe succeeded.
+///
+/// Entries without a probe are always kept.
+/// Probes that don't finish within `PROBE_TIMEOUT` are killed and count as failed.
+fn probe_entries(entries: impl Iterator<Item = Entry>, shell: &Shell) -> Vec<Entry> {
+    const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+    let mut probes = entries
+        .map(|entry| {
+            let child = entry.probe().map(|probe| {
+                spawn_probe(probe, shell)
+                    .context(format!(
+                        "failed to check the condition of entry `{}`",
+                        style_stderr!(bold(), "{}", entry.name())
+                    ))
+                    .map_err(|err| warn_error(&err))
+                    .ok()
+            });
+            // The result is `None` while the probe is still running.
+            let passed = match child {
+                None => Some(true),
+                Some(None) => Some(false),
+                Some(Some(_)) => None,
+            };
+
+            (entry, child.flatten(), passed)
+        })
+        .collect::<Vec<(Entry, Option<Child>, Option<bool>)>>();
+
+    let deadline = Instant::now() + PROBE_TIMEOUT;
+    loop {
+        let mut pending = false;
+        for (_, child, passed) in &mut probes {
+            if let (Some(child), None) = (child, &passed) {
+                match child.try_wait() {
+                    Ok(Some(status)) => *passed = Some(status.success()),
+                    Ok(None) => pending = true,
+                    Err(_) => *passed = Some(false),
+                }
+            }
+        }
+
+        if !pending {
+            break;
+        }
+
+        if Instant::now() >= deadline {
+            for (entry, child, passed) in &mut probes {
+                if let (Some(child), None) = (child, &passed) {
+                    let err =
+                        anyhow!("the condition took too long to check, so it is assumed false")
+                            .context(format!(
+                                "skipping entry `{}`",
+                                style_stderr!(bold(), "{}", entry.name())
+                            ));
+                    warn_error(&err);
+                    kill_process_group(child);
+                    *passed = Some(false);
+                }
+            }
+            break;
+        }
+
+        thread::sleep(Duration::from_millis(5));
     }
 
-    display
+    probes
+        .into_iter()
+        .filter(|(_, _, passed)| *passed == Some(true))
+        .map(|(entry, _, _)| entry)
+        .collect()
 }
 
-fn run_dmenu(menu_display: String, dmenu_args: &[Cow<'_, str>]) -> anyhow::Result<String> {
-    let mut dmenu = Command::new("dmenu")
-        .args(
-            dmenu_args
-                .iter()
-                .map(Cow::as_ref)
-                .collect::<Vec<&str>>()
-                .as_slice(),
-        )
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
+fn spawn_probe(probe: &Run, shell: &Shell) -> anyhow::Result<Child> {
+    let (mut command, input) =
+        build_command(probe, shell)?.context("the condition command is empty")?;
+    if input.is_none() {
+        command.stdin(Stdio::null());
+    }
+
+    // In its own process group, so anything it starts is killed with it if it takes too long.
+    let mut child = command
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
         .spawn()
         .context(format!(
-            "failed to run command `{}` (is it installed?)",
-            style_stderr!(bold(), "dmenu")
+            "failed to run command `{}`",
+            style_stderr!(bold(), "{probe}")
         ))?;
-    let mut stdin = dmenu
-        .stdin
-        .take()
-        .context("failed to establish pipe to dmenu??")?;
-
-    let thread = thread::spawn(move || {
-        stdin
-            .write_all(menu_display.as_bytes())
-            .context("failed to write to dmenu stdin??")
+
+    if let Err(err) = write_input(&mut child, input.as_deref()) {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(err);
+    }
+
+    Ok(child)
+}
+
+/// Run the label command of each entry in parallel, returning the labels by entry name.
+///
+/// A label is the first line its command prints.
+/// Labels are cached, so if a command fails or doesn't finish within `LABEL_TIMEOUT`,
+/// its entry keeps the label from the last time the command succeeded, if any.
+fn label_entries(entries: &[Entry], shell: &Shell, cache: &Cache) -> HashMap<ImStr, ImStr> {
+    const LABEL_TIMEOUT: Duration = Duration::from_secs(1);
+
+    let children = entries
+        .iter()
+        .filter_map(|entry| {
+            let label = entry.label()?;
+            Some((entry.name(), spawn_label(label, shell)))
+        })
+        .collect::<Vec<(ImStr, anyhow::Result<Child>)>>();
+    if children.is_empty() {
+        return HashMap::default();
+    }
+
+    let cached = cache
+        .read(LABELS_FILE)
+        .and_then(|labels| labels.parse::<toml::Value>().ok())
+        .and_then(|labels| match labels {
+            toml::Value::Table(labels) => Some(labels),
+            _ => None,
+        })
+        .unwrap_or_default();
+
+    // Every command is already running, so they all share one deadline.
+    let deadline = Instant::now() + LABEL_TIMEOUT;
+    let mut labels = HashMap::default();
+    for (name, child) in children {
+        let label = child.and_then(|mut child| {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            wait_with_timeout(&mut child, timeout).map_err(|_| {
+                anyhow!(
+                    "its label command took longer than {}s",
+                    LABEL_TIMEOUT.as_secs_f64()
+                )
+            })?;
+            read_label(&mut child)
+        });
+
+        match label {
+            Ok(label) => {
+                labels.insert(name, label);
+            }
+            Err(err) => {
+                let fallback = cached.get(name.as_str()).and_then(toml::Value::as_str);
+                let context = match fallback {
+                    Some(_) => "using its last label",
+                    None => "using its name",
+                };
+                warn_error(&err.context(format!(
+                    "couldn't get the label of entry `{}`; {context}",
+                    style_stderr!(bold(), "{name}")
+                )));
+                if let Some(fallback) = fallback {
+                    labels.insert(name, ImStr::from(fallback));
+                }
+            }
+        }
+    }
+
+    let changed = labels.iter().any(|(name, label)| {
+        cached.get(name.as_str()).and_then(toml::Value::as_str) != Some(label)
     });
-    match thread.join() {
-        Ok(result) => result?,
-        Err(err) => panic::resume_unwind(err),
-    }
-
-    let output = dmenu
-        .wait_with_output()
-        .context("failed to read dmenu stdout??")?;
-
-    Ok(String::from_utf8(output.stdout)?)
-}
-
-fn run_commands(commands: &[Run], config: &Config) -> anyhow::Result<()> {
-    for command in commands {
-        match command {
-            Run::Bare(run) => {
-                if let Some(bin) = run.first() {
-                    let args = &run[1..].iter().map(ImStr::as_str).collect::<Vec<&str>>();
-                    let result = Command::new(bin.as_str())
-                        .args(args)
-                        .spawn()
-                        .context(format!(
-                            "couldn't run bare command `{}`",
-                            style_stderr!(bold(), "{command}")
-                        ));
-
-                    if let Err(err) = result {
-                        warn_error(&err);
-                    }
+    if changed {
+        let mut table = cached;
+        for (name, label) in &labels {
+            table.insert(name.to_string(), toml::Value::from(label.as_str()));
+        }
+        // Sorted by name, so the file only changes where the labels did.
+        let mut table = table.into_iter().collect::<Vec<(String, toml::Value)>>();
+        table.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+        let table = table.into_iter().collect();
+        let result = toml::to_string(&toml::Value::Table(table))
+            .context("failed to serialize labels??")
+            .and_then(|labels| cache.write(LABELS_FILE, &labels));
+        if let Err(err) = result {
+            warn_error(&err);
+        }
+    }
+
+    labels
+}
+
+/// Describe each entry found in `PATH` with the first line of output of `describe`,
+/// returning the descriptions by the path of the entry's binary.
+///
+/// Descriptions are cached by the binary's path and modification time, so `describe` only runs
+/// again for a binary once it changes. Uncached binaries are described in parallel, at most
+/// `MAX_RUNNING` at a time, until `DESCRIBE_TIMEOUT` runs out; any that weren't started by then,
+/// or were stopped by it before `COMMAND_TIMEOUT` was up, are described on later runs.
+fn describe_entries(
+    entries: &[RunEntry],
+    describe: &[ImStr],
+    cache: &Cache,
+) -> HashMap<ImStr, ImStr> {
+    const DESCRIBE_TIMEOUT: Duration = Duration::from_secs(2);
+    const COMMAND_TIMEOUT: Duration = Duration::from_secs(1);
+    const MAX_RUNNING: usize = 32;
+
+    let cached = cache
+        .read(DESCRIPTIONS_FILE)
+        .and_then(|descriptions| descriptions.parse::<toml::Value>().ok())
+        .and_then(|descriptions| match descriptions {
+            toml::Value::Table(descriptions) => Some(descriptions),
+            _ => None,
+        })
+        .unwrap_or_default();
+
+    let modified = |path: &str| {
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified());
+        let since_epoch = modified.ok()?.duration_since(UNIX_EPOCH).ok()?;
+        i64::try_from(since_epoch.as_secs()).ok()
+    };
+    // Commands that fail or use all of `COMMAND_TIMEOUT` are cached as an empty description,
+    // so a binary that never describes itself doesn't slow down every run.
+    let mut described = HashMap::default();
+    let mut pending = Vec::new();
+    for entry in entries {
+        let Some(path) = entry.run.program() else {
+            continue;
+        };
+        let Some(modified) = modified(path) else {
+            continue;
+        };
+        let fresh = cached
+            .get(path.as_str())
+            .filter(|cached| cached.get("modified") == Some(&toml::Value::Integer(modified)))
+            .and_then(|cached| cached.get("description")?.as_str());
+        match fresh {
+            Some(description) => {
+                described.insert(path.clone(), (modified, ImStr::from(description)));
+            }
+            None => pending.push((path, modified, &entry.name)),
+        }
+    }
+
+    if !pending.is_empty() {
+        verbose!("describing {} entries that aren't cached", pending.len());
+    }
+    let deadline = Instant::now() + DESCRIBE_TIMEOUT;
+    let mut ran = 0;
+    'describe: for chunk in pending.chunks(MAX_RUNNING) {
+        if Instant::now() >= deadline {
+            verbose!(
+                "describing entries took longer than {}s; {} more are described on later runs",
+                DESCRIBE_TIMEOUT.as_secs_f64(),
+                pending.len() - ran,
+            );
+            break;
+        }
+        let started = Instant::now();
+        let mut children = Vec::with_capacity(chunk.len());
+        for &(path, modified, name) in chunk {
+            match spawn_describe(describe, name) {
+                Ok(child) => children.push((path, modified, name, child)),
+                Err(err) => {
+                    warn_error(&err.context("`config.path.describe` can't be run"));
+                    break 'describe;
                 }
             }
-            Run::Shell(run) => {
-                if !run.is_empty() {
-                    match &config.shell {
-                        Shell::Disabled => {
-                            let err = anyhow!(
-                                "shell execution is disabled; to enable, set `config.shell = true`"
-                            )
-                            .context(format!(
-                                "can't execute shell command `{}`",
-                                style_stderr!(bold(), "{run}")
-                            ));
+        }
+
+        // The shared deadline may stop the chunk before each command has had its full time.
+        let cut_short = deadline < started + COMMAND_TIMEOUT;
+        let chunk_deadline = deadline.min(started + COMMAND_TIMEOUT);
+        for (path, modified, name, mut child) in children {
+            let timeout = chunk_deadline.saturating_duration_since(Instant::now());
+            ran += 1;
+            if let Err(err) = wait_with_timeout(&mut child, timeout) {
+                if cut_short {
+                    verbose!(
+                        "`{name}` is described on a later run, since describing took too long"
+                    );
+                } else {
+                    verbose!("couldn't describe `{name}`: {err:#}");
+                    described.insert(path.clone(), (modified, ImStr::default()));
+                }
+                continue;
+            }
+            let description = read_description(&mut child, name).unwrap_or_else(|err| {
+                verbose!("couldn't describe `{name}`: {err:#}");
+                ImStr::default()
+            });
+            described.insert(path.clone(), (modified, description));
+        }
+    }
 
-                            warn_error(&err);
-                        }
-                        Shell::Enabled { shell, piped } => {
-                            if let Some(shell_name) = shell.first() {
-                                let args =
-                                    &shell[1..].iter().map(ImStr::as_str).collect::<Vec<&str>>();
-                                if *piped {
-                                    let mut shell = Command::new(shell_name.as_str())
-                                        .args(args)
-                                        .stdin(Stdio::piped())
-                                        .stdout(Stdio::piped())
-                                        .stderr(Stdio::piped())
-                                        .spawn()
-                                        .context(format!(
-                                            "failed to run shell `{}` (is it installed?)",
-                                            style_stderr!(bold(), "{shell_name}")
-                                        ))?;
-                                    let mut stdin = shell
-                                        .stdin
-                                        .take()
-                                        .context("failed to establish pipe to shell??")?;
-
-                                    stdin
-                                        .write_all(run.as_bytes())
-                                        .context("failed to write to shell stdin??")?;
-                                } else {
-                                    let result = Command::new(shell_name.as_str())
-                                        .args(args)
-                                        .arg(run.as_str())
-                                        .spawn()
-                                        .context(format!(
-                                            "problem running shell command `{}`",
-                                            style_stderr!(bold(), "{run}")
-                                        ));
-
-                                    if let Err(err) = result {
-                                        warn_error(&err);
-                                    }
-                                }
-                            }
-                        }
+    // Only the binaries found this run are kept, so uninstalled ones are forgotten.
+    if ran > 0 || described.len() != cached.len() {
+        // Sorted by path, so the file only changes where the descriptions did.
+        let mut table = described.iter().collect::<Vec<(&ImStr, &(i64, ImStr))>>();
+        table.sort_unstable_by_key(|&(path, _)| path);
+        let table = table
+            .into_iter()
+            .map(|(path, (modified, description))| {
+                let cached = toml::map::Map::from_iter([
+                    (String::from("modified"), toml::Value::Integer(*modified)),
+                    (
+                        String::from("description"),
+                        toml::Value::from(description.as_str()),
+                    ),
+                ]);
+                (path.to_string(), toml::Value::Table(cached))
+            })
+            .collect();
+        let result = toml::to_string(&toml::Value::Table(table))
+            .context("failed to serialize descriptions??")
+            .and_then(|descriptions| cache.write(DESCRIPTIONS_FILE, &descriptions));
+        if let Err(err) = result {
+            warn_error(&err);
+        }
+    }
+
+    described
+        .into_iter()
+        .filter(|(_, (_, description))| !description.is_empty())
+        .map(|(path, (_, description))| (path, description))
+        .collect()
+}
+
+/// Run `describe` for the binary `name`, replacing `{name}` in its arguments,
+/// or passing the name last if none of them has it.
+fn spawn_describe(describe: &[ImStr], name: &str) -> anyhow::Result<Child> {
+    let (program, args) = describe.split_first().expect("unreachable");
+    let mut command = Command::new(program.as_str());
+    if args.iter().any(|arg| arg.contains("{name}")) {
+        command.args(args.iter().map(|arg| arg.replace("{name}", name)));
+    } else {
+        command.args(args.iter().map(ImStr::as_str)).arg(name);
+    }
+
+    // In its own process group, so anything it starts is killed with it if it takes too long.
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .context(format!(
+            "failed to run command `{}`",
+            style_stderr!(bold(), "{program}")
+        ))
+}
+
+/// Read the description printed by the finished describe command `child` for the binary `name`.
+///
+/// This is the first line it printed, without the `name (1) - ` that `whatis` starts it with.
+fn read_description(child: &mut Child, name: &str) -> anyhow::Result<ImStr> {
+    let status = child.wait().context("failed to wait for command??")?;
+    if !status.success() {
+        return Err(anyhow!("the command failed with {status}"));
+    }
+
+    let mut output = Vec::new();
+    if let Some(mut stdout) = child.stdout.take() {
+        io::Read::read_to_end(&mut stdout, &mut output)
+            .context("failed to read the command's output??")?;
+    }
+    let output = String::from_utf8_lossy(&output);
+    let line = output
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .context("the command printed nothing")?;
+    let description = match line.split_once(" - ") {
+        Some((before, after)) if before.starts_with(name) => after.trim(),
+        _ => line,
+    };
+
+    Ok(ImStr::from(description))
+}
+
+fn spawn_label(label: &Run, shell: &Shell) -> anyhow::Result<Child> {
+    let (mut command, input) =
+        build_command(label, shell)?.context("the label command is empty")?;
+    if input.is_none() {
+        command.stdin(Stdio::null());
+    }
+
+    // In its own process group, so anything it starts is killed with it if it takes too long.
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .context(format!(
+            "failed to run command `{}`",
+            style_stderr!(bold(), "{label}")
+        ))?;
+
+    if let Err(err) = write_input(&mut child, input.as_deref()) {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(err);
+    }
+
+    Ok(child)
+}
+
+/// Read the first line printed by the finished label command `child`.
+fn read_label(child: &mut Child) -> anyhow::Result<ImStr> {
+    let status = child.wait().context("failed to wait for command??")?;
+    if !status.success() {
+        return Err(anyhow!("its label command failed with {status}"));
+    }
+
+    let mut output = String::new();
+    if let Some(mut stdout) = child.stdout.take() {
+        io::Read::read_to_string(&mut stdout, &mut output)
+            .context("its label command printed invalid utf-8")?;
+    }
+    let label = output.lines().next().unwrap_or_default().trim();
+    if label.is_empty() {
+        return Err(anyhow!("its label command printed nothing"));
+    }
+
+    Ok(ImStr::from(label))
+}
+
+fn walk_dir(dir: ReadDir) -> anyhow::Result<Vec<(OsString, ImStr)>> {
+    let mut files = Vec::new();
+
+    for entry in dir {
+        let entry = entry.context("error trying to walk PATH directory")?;
+        let filetype = entry.file_type().context("error reading file metadata")?;
+        let follow_symlink_is_dir = || {
+            fs::metadata(entry.path())
+                .context("error reading file metadata")
+                .map(|entry| entry.is_dir())
+                .map_err(|err| {
+                    err.context(format!("symlink `{}` is broken", entry.path().display()))
+                })
+                .unwrap_or_else(|err| {
+                    warn_error(&err);
+                    false
+                })
+        };
+
+        // Only symlinks need another stat to find out what they point to.
+        if filetype.is_dir() || (filetype.is_symlink() && follow_symlink_is_dir()) {
+            continue;
+        } else if entry.path().is_executable() {
+            files.push((
+                entry.path().into_os_string(),
+                entry.file_name().to_string_lossy().into(),
+            ));
+        }
+    }
+
+    Ok(files)
+}
+
+/// Find all executables in `root` and its subdirectories, walking them in parallel.
+///
+/// Symlinks are followed, but a symlink that loops back to one of its parents is skipped.
+/// Hidden files and ignore files like `.gitignore` get no special treatment.
+fn walk_dir_recursive(root: &Path) -> Vec<(OsString, ImStr)> {
+    let files = Mutex::new(Vec::new());
+
+    WalkBuilder::new(root)
+        .standard_filters(false)
+        .follow_links(true)
+        .build_parallel()
+        .run(|| {
+            Box::new(|entry| {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(err) => {
+                        verbose!("skipping directory: {err}");
+                        return WalkState::Continue;
                     }
+                };
+
+                let is_dir = entry.file_type().is_none_or(|filetype| filetype.is_dir());
+                if !is_dir && entry.path().is_executable() {
+                    files
+                        .lock()
+                        .expect("no thread panicked")
+                        .push(entry.into_path());
                 }
+
+                WalkState::Continue
+            })
+        });
+
+    // Parallel traversal finds files in an arbitrary order, so sort them to keep menus stable.
+    let mut files = files.into_inner().expect("no thread panicked");
+    files.sort_unstable();
+    files
+        .into_iter()
+        .map(|path| {
+            let name = path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into();
+            (path.into_os_string(), name)
+        })
+        .collect()
+}
+
+/// Render `entries` as the menu's input, tagging each with its index among all entries.
+fn display_entries(config: &Config, entries: &[(usize, &RunEntry)], encoding: Encoding) -> String {
+    let mut display = String::new();
+    let mut last_group = None;
+
+    // One line per entry: the name, its tag and separator, and a newline.
+    // No tag is longer than the one for the largest index.
+    let mut tag = String::new();
+    let last = entries.iter().map(|&(i, _)| i).max().unwrap_or_default();
+    encoding.push_tag(last, &mut tag);
+    let (separator, tag) = (config.numbered.separator().len(), tag.len());
+    display.reserve(
+        entries
+            .iter()
+            .map(|(_, entry)| entry.display_name().len() + separator + tag + 1)
+            .sum(),
+    );
+
+    for &(i, entry) in entries {
+        if last_group != Some(entry.group) {
+            if let Some(header) = group_header(config, entry.group) {
+                display.push_str(&header);
+                display.push('\n');
             }
+            last_group = Some(entry.group);
         }
+
+        let display_name = entry.display_name();
+        let name = sanitize_name(&display_name);
+        let shown = if uses_markup(config) {
+            Cow::from(config.group_colors.markup(entry.group, &name))
+        } else {
+            Cow::from(&*name)
+        };
+        if config.numbered.is_enabled() {
+            encoding.push_tag(i, &mut display);
+            display.push_str(config.numbered.separator());
+            display.push_str(&shown);
+        } else {
+            display.push_str(&shown);
+            encoding.push_tag(i, &mut display);
+        }
+        // After the tag, so it's never mistaken for part of the tag.
+        if let Some(folded) = folded_region(config, &name) {
+            display.push_str(&folded);
+        }
+        display.push('\n');
+    }
+
+    display
+}
+
+/// The ascii-folded copy of `name` added to the end of its line if `config.fold-matching`
+/// is enabled, or `None` if it's disabled or `name` has no accented letters.
+fn folded_region(config: &Config, name: &str) -> Option<String> {
+    if !config.fold_matching.0 {
+        return None;
     }
+    let folded = fold_ascii(name)?;
+
+    // Rofi matches against a line's `meta` option without displaying it.
+    #[cfg(feature = "rofi")]
+    if config.backend == config::Backend::Rofi {
+        return Some(format!("\0meta\x1f{folded}"));
+    }
+
+    Some(format!(" ({folded})"))
+}
+
+/// Replace control characters like newlines in `name` with spaces,
+/// so each entry takes up exactly one line of the menu.
+fn sanitize_name(name: &str) -> Cow<'_, str> {
+    if name.contains(char::is_control) {
+        Cow::from(name.replace(char::is_control, " "))
+    } else {
+        Cow::from(name)
+    }
+}
+
+/// Output the exact dmenu invocation and the start of its input to stderr.
+fn print_menu_debug(dmenu_argv: &[Cow<'_, str>], menu_display: &str) {
+    const MAX_LINES: usize = 10;
+
+    let argv = dmenu_argv
+        .iter()
+        .map(|arg| shell_quote(arg))
+        .collect::<Vec<Cow<'_, str>>>()
+        .join(" ");
+    let line_count = menu_display.lines().count();
+
+    eprintln!("{} {argv}", style_stderr!(bold(), "dmenu command:"));
+    eprintln!(
+        "{} (first {} of {line_count} lines)",
+        style_stderr!(bold(), "dmenu input:"),
+        line_count.min(MAX_LINES)
+    );
+    for line in menu_display.lines().take(MAX_LINES) {
+        eprintln!("{line}");
+    }
+    eprintln!();
+}
+
+fn group_header(config: &Config, group: i64) -> Option<String> {
+    let header = config
+        .group_headers
+        .header(group, config.group_name(group).map(ImStr::as_str))?;
+    if uses_markup(config) {
+        Some(escape_markup(&header).into_owned())
+    } else {
+        Some(header)
+    }
+}
+
+/// Whether the menu is rendered as pango markup, to color the entries of `config.group-colors`.
+fn uses_markup(config: &Config) -> bool {
+    config.group_colors.is_enabled() && config.backend.supports_markup()
+}
+
+/// Ask for confirmation to run `entry` in a second menu, if it requires it.
+///
+/// The menu only offers `no`, so the accepting word must be typed out.
+fn confirm(config: &Config, entry: &RunEntry) -> anyhow::Result<bool> {
+    let Some(confirm) = &entry.options.confirm else {
+        return Ok(true);
+    };
+
+    let mut dmenu = config.dmenu.clone();
+    dmenu.prompt = Some(ImStr::from(confirm.prompt(&entry.name)));
+    // The query was for the main menu, and would type out an answer here.
+    dmenu.query = None;
+    let args = dmenu.args(config.backend);
+    let (choice, _) = run_dmenu(
+        String::from("no\n"),
+        &dmenu.argv(config.backend, &args),
+        config.menu_timeout.0,
+    )
+    .context("problem running dmenu to confirm")?;
+
+    let confirmed = confirm.accepts(&choice);
+    if !confirmed {
+        verbose!("`{}` wasn't confirmed, so it won't be run", entry.name);
+    }
+    Ok(confirmed)
+}
+
+/// Show the command `entry` resolved to on `tty`, and ask whether to run it.
+///
+/// Without a terminal, the command is output instead, and it isn't run.
+fn ask_to_run(
+    config: &Config,
+    entry: &RunEntry,
+    tty: Option<&mut fs::File>,
+) -> anyhow::Result<bool> {
+    let Some(command) = entry_command(config, entry)? else {
+        verbose!("`{}` has nothing to run", entry.name);
+        return Ok(false);
+    };
+    let rendered = render_command(command);
+    let Some(tty) = tty else {
+        println!("{rendered}");
+        return Ok(false);
+    };
+
+    write!(tty, "{}: {rendered}\nRun it? [y/N] ", entry.name)
+        .context("failed to write to the terminal??")?;
+    let mut answer = String::new();
+    io::BufReader::new(tty)
+        .read_line(&mut answer)
+        .context("failed to read from the terminal??")?;
+
+    let answer = answer.trim().to_lowercase();
+    Ok(answer == "y" || answer == "yes")
+}
+
+/// Run dmenu, reopening it up to `config.retry` times if it's dismissed without a selection.
+///
+/// Retrying stops early if dmenu exits too quickly for a person to have dismissed it,
+/// since it will most likely keep doing so.
+fn run_dmenu_with_retries(
+    config: &Config,
+    menu_display: String,
+    dmenu_argv: &[Cow<'_, str>],
+) -> anyhow::Result<(String, ExitStatus)> {
+    const MIN_DISMISS_TIME: Duration = Duration::from_millis(200);
+
+    let mut retries = config.retry.0;
+    // Reopening the menu doesn't restart `config.menu-timeout`, which covers the whole interaction.
+    let deadline = config
+        .menu_timeout
+        .0
+        .map(|timeout| Instant::now() + timeout);
+    loop {
+        let started = Instant::now();
+        let timeout = deadline.map(|deadline| deadline.saturating_duration_since(started));
+        let (choices, status) = run_dmenu(menu_display.clone(), dmenu_argv, timeout)
+            .context("problem running dmenu")?;
+
+        if !choices.trim().is_empty() || retries == 0 || accelerator(config, status).is_some() {
+            return Ok((choices, status));
+        }
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            verbose!("`config.menu-timeout` ran out, so dmenu won't be reopened");
+            return Ok((choices, status));
+        }
+
+        if started.elapsed() < MIN_DISMISS_TIME {
+            verbose!("dmenu exited immediately without a selection, so it won't be reopened");
+            return Ok((choices, status));
+        }
+
+        retries -= 1;
+        verbose!("dmenu was dismissed; reopening it ({retries} retries left)");
+    }
+}
+
+/// The key binding and entry name of the accelerator dmenu exited with, if any.
+///
+/// Rofi exits with status 10 for its first custom key binding, 11 for the second, and so on.
+fn accelerator(config: &Config, status: ExitStatus) -> Option<&(ImStr, ImStr)> {
+    const FIRST_CUSTOM_STATUS: i32 = 10;
+
+    if !config.backend.supports_accelerators() {
+        return None;
+    }
+    let i = status.code()?.checked_sub(FIRST_CUSTOM_STATUS)?;
+    config.accelerators.0.get(usize::try_from(i).ok()?)
+}
+
+/// Run dmenu with `menu_display` as its input, returning its output and exit status.
+///
+/// If nothing is selected within `timeout`, dmenu is closed as if it was dismissed.
+fn run_dmenu(
+    menu_display: String,
+    dmenu_argv: &[Cow<'_, str>],
+    timeout: Option<Duration>,
+) -> anyhow::Result<(String, ExitStatus)> {
+    menu::run_launcher(dmenu_argv, menu_display, timeout)
+}
+
+fn run_commands(commands: &[RunEntry], config: &Config) -> anyhow::Result<()> {
+    let systemd_scope = config.systemd_scope.0 && {
+        let found = find_in_path("systemd-run").is_some();
+        if !found {
+            let err = anyhow!("`systemd-run` wasn't found in `PATH`, so commands run without one")
+                .context("can't run commands in a systemd scope");
+            warn_error(&err);
+        }
+        found
+    };
+
+    let running = commands
+        .iter()
+        .filter_map(|entry| {
+            spawn_command(entry, config, systemd_scope)
+                .map_err(|err| warn_error(&err))
+                .ok()
+                .flatten()
+        })
+        .collect::<Vec<Running>>();
+
+    // Every command is started before any is waited on, so they all run at once.
+    thread::scope(|scope| {
+        for running in running {
+            scope.spawn(move || {
+                if let Err(err) = running.wait() {
+                    warn_error(&err);
+                }
+            });
+        }
+    });
 
     Ok(())
 }
 
+/// A command that was started and still has to be waited on.
+struct Running {
+    child: Child,
+    /// The command as it's shown in errors.
+    run: String,
+    /// How long to wait before killing it, or `None` if it isn't waited on.
+    wait: Option<Option<Duration>>,
+    /// The thread copying the command's pseudo-terminal to its log, if it has one.
+    output: Option<thread::JoinHandle<io::Result<u64>>>,
+}
+
+impl Running {
+    fn wait(mut self) -> anyhow::Result<()> {
+        match self.wait {
+            Some(Some(timeout)) => wait_with_timeout(&mut self.child, timeout).context(format!(
+                "command `{}` was killed",
+                style_stderr!(bold(), "{}", self.run)
+            ))?,
+            Some(None) => {
+                self.child.wait().context("failed to wait for command??")?;
+            }
+            None => {}
+        }
+        // Nothing else reads the terminal, so wait until the command is done with it.
+        if let Some(output) = self.output {
+            let _ = output.join();
+        }
+
+        Ok(())
+    }
+}
+
+/// Start the command of `entry`, returning it if it has to be waited on.
+///
+/// It's waited on if `wait` is enabled for it, or if it has a pseudo-terminal to read.
+fn spawn_command(
+    entry: &RunEntry,
+    config: &Config,
+    systemd_scope: bool,
+) -> anyhow::Result<Option<Running>> {
+    let Some((mut command, input)) = entry_command(config, entry)? else {
+        return Ok(None);
+    };
+    let wait = entry
+        .options
+        .wait
+        .unwrap_or(entry.options.timeout.is_some() || config.wait.0)
+        .then(|| entry.options.timeout.or(config.timeout.0));
+
+    match &entry.run {
+        Run::Bare(_) => verbose!("running bare command `{}`", entry.run),
+        Run::Shell(run) => verbose!(
+            "running shell command `{run}` with shell `{}`",
+            command.get_program().to_string_lossy()
+        ),
+    }
+
+    if systemd_scope {
+        verbose!("running it in a transient systemd scope");
+        // `systemd-run --scope` runs the command itself, so it keeps dmm's stdio and process group.
+        command = wrap_command(
+            &command,
+            &["systemd-run", "--user", "--scope", "--quiet", "--"],
+        );
+    }
+    if config.clean_env.0 {
+        command.env_clear();
+        for var in &config.env_passthrough.0 {
+            if let Some(value) = env::var_os(var.as_str()) {
+                command.env(var.as_str(), value);
+            }
+        }
+    }
+    command.envs(
+        config
+            .env
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str())),
+    );
+    if input.is_some() {
+        command.stdin(Stdio::piped());
+        // Nothing reads a piped shell's output, so it's discarded unless it's forwarded.
+        if entry.options.stdin.is_none()
+            && !matches!(
+                *entry_shell(config, entry),
+                Shell::Enabled {
+                    forward_output: true,
+                    ..
+                }
+            )
+        {
+            command.stdout(Stdio::null()).stderr(Stdio::null());
+        }
+    }
+    let log = entry.options.log.as_ref().and_then(|log| {
+        open_log(log, config)
+            .context(format!(
+                "can't log the output of entry `{}`, so it runs without a log",
+                style_stderr!(bold(), "{}", entry.name)
+            ))
+            .map_err(|err| warn_error(&err))
+            .ok()
+    });
+    let pty = entry
+        .options
+        .pty
+        .unwrap_or(config.pty.0)
+        .then(|| {
+            open_pty()
+                .context(format!(
+                    "can't open a pseudo-terminal for entry `{}`, so it runs without one",
+                    style_stderr!(bold(), "{}", entry.name)
+                ))
+                .map_err(|err| warn_error(&err))
+                .ok()
+        })
+        .flatten();
+    let duplicate = |file: &fs::File| file.try_clone().context("failed to duplicate file??");
+    if let Some((_, terminal)) = &pty {
+        verbose!("running it with a pseudo-terminal");
+        if input.is_none() {
+            command.stdin(duplicate(terminal)?);
+        }
+        command
+            .stdout(duplicate(terminal)?)
+            .stderr(duplicate(terminal)?);
+    } else if let Some(log) = &log {
+        command.stdout(duplicate(log)?).stderr(duplicate(log)?);
+    }
+    if config.setsid.0 {
+        // SAFETY: `setsid` is async-signal-safe, and nothing else is done between fork and exec.
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+    if let Some(nice) = entry.options.nice {
+        const NICE_RANGE: RangeInclusive<i64> = -20..=19;
+        let clamped = nice.clamp(*NICE_RANGE.start(), *NICE_RANGE.end());
+        if clamped != nice {
+            warn_error(&anyhow!(
+                "`{}` must be from {} to {}, so {clamped} is used instead of {nice}",
+                style_stderr!(bold(), "menu.{}.nice", entry.name),
+                NICE_RANGE.start(),
+                NICE_RANGE.end(),
+            ));
+        }
+        let nice = libc::c_int::try_from(clamped).expect("unreachable");
+
+        // SAFETY: `setpriority` is async-signal-safe, and nothing else is done between fork and exec.
+        // Lowering the niceness below the current one fails without privileges,
+        // which fails to spawn the command rather than running it with the wrong priority.
+        unsafe {
+            command.pre_exec(move || {
+                if libc::setpriority(libc::PRIO_PROCESS, 0, nice) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+    }
+    if matches!(wait, Some(Some(_))) && !config.setsid.0 {
+        // Run the command in its own process group, so any processes it starts can be killed.
+        // A new session is also a new process group, and a group leader can't start a session.
+        command.process_group(0);
+    }
+
+    let mut child = command.spawn().context(match &entry.run {
+        Run::Bare(_) => format!(
+            "couldn't run bare command `{}`",
+            style_stderr!(bold(), "{}", entry.run)
+        ),
+        Run::Shell(run) => format!(
+            "problem running shell command `{}`",
+            style_stderr!(bold(), "{run}")
+        ),
+    })?;
+
+    write_input(&mut child, input.as_deref())?;
+
+    // The terminal is only closed once every copy of it is, including the ones `command` holds.
+    drop(command);
+    let output = pty.map(|(mut output, _)| {
+        let mut log = log.map_or_else(
+            || Box::new(io::sink()) as Box<dyn Write + Send>,
+            |log| Box::new(log),
+        );
+        // Reading fails once the command and anything it started have closed the terminal.
+        thread::spawn(move || io::copy(&mut output, &mut log))
+    });
+
+    if wait.is_none() && output.is_none() {
+        return Ok(None);
+    }
+
+    Ok(Some(Running {
+        child,
+        run: entry.run.to_string(),
+        wait,
+        output,
+    }))
+}
+
+/// Open a pseudo-terminal, returning its controlling end and the terminal itself.
+///
+/// The terminal is 80 columns by 24 rows, and neither end is inherited by commands.
+fn open_pty() -> anyhow::Result<(fs::File, fs::File)> {
+    let (mut controller, mut terminal) = (-1, -1);
+    let size = libc::winsize {
+        ws_row: 24,
+        ws_col: 80,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+
+    // SAFETY: The pointers are valid for the duration of the call, and `openpty` accepts null
+    // for the name and terminal attributes.
+    let result = unsafe {
+        libc::openpty(
+            &mut controller,
+            &mut terminal,
+            ptr::null_mut(),
+            ptr::null(),
+            &size,
+        )
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error()).context("failed to open a pseudo-terminal");
+    }
+    // SAFETY: `openpty` succeeded, so both are open file descriptors that nothing else owns.
+    let ends = unsafe {
+        (
+            fs::File::from_raw_fd(controller),
+            fs::File::from_raw_fd(terminal),
+        )
+    };
+
+    for end in [&ends.0, &ends.1] {
+        // SAFETY: `fcntl` has no memory safety requirements.
+        if unsafe { libc::fcntl(end.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+            return Err(io::Error::last_os_error())
+                .context("failed to configure a pseudo-terminal??");
+        }
+    }
+
+    Ok(ends)
+}
+
+/// Build the command that will execute `entry`, in its shell, with its globs expanded
+/// if `config.glob-args` is enabled; see [`build_command`].
+///
+/// The entry's `stdin` is returned as the input to write to the command,
+/// which isn't possible for a piped shell, since the command itself is its input.
+fn entry_command(
+    config: &Config,
+    entry: &RunEntry,
+) -> anyhow::Result<Option<(Command, Option<String>)>> {
+    let built = glob_command(config, entry)?;
+    let stdin = entry.options.expand_stdin(&entry.name).context(format!(
+        "unable to expand `{}`",
+        style_stderr!(bold(), "menu.{}.stdin", entry.name)
+    ))?;
+
+    match (built, stdin) {
+        (Some((_, Some(_))), Some(_)) => Err(anyhow!(
+            "the shell reads the command from its stdin, so `{}` can't be written to it",
+            style_stderr!(bold(), "menu.{}.stdin", entry.name)
+        )),
+        (Some((command, input)), stdin) => Ok(Some((command, input.or(stdin)))),
+        (None, _) => Ok(None),
+    }
+}
+
+/// Build the command that will execute `entry`, in its shell, with its globs expanded
+/// if `config.glob-args` is enabled.
+fn glob_command(
+    config: &Config,
+    entry: &RunEntry,
+) -> anyhow::Result<Option<(Command, Option<String>)>> {
+    let built = build_command(&entry.run, &entry_shell(config, entry))?;
+    let (GlobArgs::Enabled { nullglob }, Run::Bare(_), Some((command, None))) =
+        (config.glob_args, &entry.run, &built)
+    else {
+        return Ok(built);
+    };
+
+    let home = config
+        .base_dirs
+        .as_ref()
+        .map(|base_dirs| base_dirs.home_dir());
+    let mut expanded = Command::new(command.get_program());
+    for arg in command.get_args() {
+        let matches = glob::expand(arg, home, nullglob);
+        if glob::is_glob(arg) {
+            let shown = matches
+                .iter()
+                .map(|path| path.to_string_lossy())
+                .collect::<Vec<Cow<'_, str>>>();
+            verbose!(
+                "expanded `{}` to `{}`",
+                arg.to_string_lossy(),
+                shown.join(" ")
+            );
+        }
+        expanded.args(matches);
+    }
+
+    Ok(Some((expanded, None)))
+}
+
+/// The shell to run `entry` with: `config.shell`, unless the entry allows a shell of its own.
+fn entry_shell<'a>(config: &'a Config, entry: &RunEntry) -> Cow<'a, Shell> {
+    match &entry.options.allow_shell {
+        Some(allow_shell) => {
+            if !config.shell.is_enabled() {
+                verbose!(
+                    "`config.shell` is disabled, but `{}` allows a shell",
+                    entry.name
+                );
+            }
+            allow_shell.shell(&config.shell)
+        }
+        None => Cow::Borrowed(&config.shell),
+    }
+}
+
+/// Open the log file `log` for appending, creating it and its parent directories if needed.
+fn open_log(log: &str, config: &Config) -> anyhow::Result<fs::File> {
+    let path = config::expand_path(log, config.base_dirs.as_ref())?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context(format!(
+            "unable to create directory `{}`",
+            style_stderr!(bold(), "{}", parent.display())
+        ))?;
+    }
+
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .context(format!(
+            "unable to open log file `{}`",
+            style_stderr!(bold(), "{}", path.display())
+        ))
+}
+
+/// Build the command that will execute `run`.
+///
+/// If the shell is piped, the shell command is also returned so it can be written to stdin,
+/// which must be set to a pipe by the caller.
+/// Returns `None` if there is nothing to run.
+fn build_command(run: &Run, shell: &Shell) -> anyhow::Result<Option<(Command, Option<String>)>> {
+    match run {
+        Run::Bare(run) => Ok(run.split_first().map(|(bin, args)| {
+            let mut command = Command::new(bin.as_str());
+            command.args(args.iter().map(ImStr::as_str));
+            (command, None)
+        })),
+        Run::Shell(run) => {
+            if run.is_empty() {
+                return Ok(None);
+            }
+
+            match shell {
+                Shell::Disabled => Err(anyhow!(
+                    "shell execution is disabled; to enable, set `config.shell = true`"
+                )
+                .context(format!(
+                    "can't execute shell command `{}`",
+                    style_stderr!(bold(), "{run}")
+                ))),
+                Shell::Enabled { .. } => {
+                    let Some(argv) = shell.argv(run) else {
+                        return Ok(None);
+                    };
+                    let mut command = Command::new(argv[0]);
+                    command.args(&argv[1..]);
+
+                    Ok(Some((command, shell.input(run))))
+                }
+            }
+        }
+    }
+}
+
+/// Render a command from [`build_command`] as it could be typed into a shell.
+///
+/// A piped shell command is shown being written to the shell's stdin.
+fn render_command((command, input): (Command, Option<String>)) -> String {
+    let mut rendered = shell_quote(&command.get_program().to_string_lossy()).into_owned();
+    for arg in command.get_args() {
+        rendered.push(' ');
+        rendered.push_str(&shell_quote(&arg.to_string_lossy()));
+    }
+    if let Some(input) = input {
+        rendered = format!("printf '%s' {} | {rendered}", shell_quote(&input));
+    }
+
+    rendered
+}
+
+/// Run `command` through `wrapper`, passing the program and its arguments after the wrapper's own.
+///
+/// Only the program and arguments are kept; anything else set on `command` must be set again.
+fn wrap_command(command: &Command, wrapper: &[&str]) -> Command {
+    let (program, args) = wrapper.split_first().expect("unreachable");
+    let mut wrapped = Command::new(program);
+    wrapped
+        .args(args)
+        .arg(command.get_program())
+        .args(command.get_args());
+
+    wrapped
+}
+
+/// Find the executable `name` in `PATH`.
+fn find_in_path(name: &str) -> Option<PathBuf> {
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_executable())
+}
+
+/// Write the input of a piped shell, or an entry's `stdin`, to the child's stdin, then close it.
+///
+/// A command that exits without reading all of it isn't an error.
+fn write_input(child: &mut Child, input: Option<&str>) -> anyhow::Result<()> {
+    if let Some(input) = input {
+        let mut stdin = child
+            .stdin
+            .take()
+            .context("failed to establish pipe to command??")?;
+
+        match stdin.write_all(input.as_bytes()) {
+            Err(err) if err.kind() == ErrorKind::BrokenPipe => {
+                verbose!("the command exited before reading all of its input");
+            }
+            result => result.context("failed to write to command stdin??")?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Wait for `child` to exit, killing its process group if it takes longer than `timeout`.
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> anyhow::Result<()> {
+    let deadline = Instant::now() + timeout;
+
+    while child
+        .try_wait()
+        .context("failed to wait for command??")?
+        .is_none()
+    {
+        if Instant::now() >= deadline {
+            kill_process_group(child);
+
+            return Err(anyhow!(
+                "it was still running when its timeout of {}s ran out",
+                timeout.as_secs_f64()
+            ));
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    Ok(())
+}
+
+/// Kill the process group led by `child`, which must have been spawned with `process_group(0)`,
+/// and reap `child`.
+fn kill_process_group(child: &mut Child) {
+    let group = libc::pid_t::try_from(child.id()).expect("unreachable");
+    // SAFETY: `kill` has no memory safety requirements.
+    // The child hasn't been waited on, so its process group can't have been reused.
+    unsafe { libc::kill(-group, libc::SIGKILL) };
+    let _ = child.wait();
+}
+
 fn display_error(err: &anyhow::Error) {
     report_error(
         err,
-        "error:",
+        "error",
         ColorSpec::new().set_fg(Some(Color::Red)).set_bold(true),
     );
 }
@@ -421,17 +2354,24 @@ fn display_error(err: &anyhow::Error) {
 fn warn_error(err: &anyhow::Error) {
     report_error(
         err,
-        "warning:",
+        "warning",
         ColorSpec::new().set_fg(Some(Color::Yellow)).set_bold(true),
     );
 }
 
-fn report_error(err: &anyhow::Error, name: &str, style: &ColorSpec) {
+fn report_error(err: &anyhow::Error, level: &str, style: &ColorSpec) {
+    ring_bell();
+
+    if dmm::log::is_json() {
+        eprintln!("{}", dmm::log::error_json(level, err));
+        return;
+    }
+
     let mut stderr = StandardStream::stderr(stderr_color_choice());
     let mut chain = err.chain();
     let err = chain.next().unwrap();
 
-    write_style!(stderr, style, "{name} ");
+    write_style!(stderr, style, "{level}: ");
     eprintln!("{err}");
     for cause in chain {
         write_style!(stderr, style, "  - ");
@@ -439,3 +2379,659 @@ fn report_error(err: &anyhow::Error, name: &str, style: &ColorSpec) {
     }
     eprintln!();
 }
+
+/// Prepare `config.bell` to ring the first time an error or warning is reported.
+fn set_bell(config: &Config) {
+    let ring = match &config.bell {
+        Bell::Disabled => return,
+        Bell::Terminal => Ring::Terminal,
+        Bell::Command(run) => match build_command(run, &config.shell) {
+            Ok(Some((mut command, input))) => {
+                command
+                    .stdin(if input.is_some() {
+                        Stdio::piped()
+                    } else {
+                        Stdio::null()
+                    })
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null());
+                Ring::Command(Box::new(command), input)
+            }
+            Ok(None) => return,
+            Err(err) => {
+                warn_error(&err.context("`config.bell` can't be rung"));
+                return;
+            }
+        },
+    };
+    *BELL.lock().expect("unreachable") = Some(ring);
+}
+
+/// Ring `config.bell`, if it's enabled and hasn't rung yet.
+///
+/// Without a controlling terminal, the terminal bell does nothing.
+fn ring_bell() {
+    let Some(ring) = BELL.lock().expect("unreachable").take() else {
+        return;
+    };
+
+    match ring {
+        Ring::Terminal => {
+            if let Ok(mut tty) = fs::OpenOptions::new().write(true).open("/dev/tty") {
+                let _ = tty.write_all(b"\x07");
+            }
+        }
+        // Failures aren't reported as errors, since that would only try to ring the bell again.
+        Ring::Command(mut command, input) => {
+            let rung = command
+                .spawn()
+                .and_then(|mut child| match (input, child.stdin.take()) {
+                    (Some(input), Some(mut stdin)) => stdin.write_all(input.as_bytes()),
+                    _ => Ok(()),
+                });
+            if let Err(err) = rung {
+                verbose!("unable to ring the bell: {err}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    /// Load a config from `toml`, as if dmm was run with `args`.
+    fn config(toml: &str, args: &[&str]) -> Config {
+        let args = config::cli(None)
+            .try_get_matches_from(["dmm"].iter().chain(args))
+            .unwrap();
+        Config::try_new(&toml.parse().unwrap(), None, args, None, None).unwrap()
+    }
+
+    /// Run `entry` like dmm does, waiting for it if it's waited on.
+    fn run(entry: &RunEntry, config: &Config) -> anyhow::Result<()> {
+        spawn_command(entry, config, false)?.map_or(Ok(()), Running::wait)
+    }
+
+    fn entry(name: &str, run: &str) -> RunEntry {
+        RunEntry {
+            name: ImStr::from(name),
+            label: None,
+            description: None,
+            run: Run::Shell(ImStr::from(run)),
+            group: 0,
+            options: RunOptions::default(),
+            source: Source::Pattern,
+        }
+    }
+
+    #[test]
+    fn name_only_entries_follow_config_shell() {
+        let mut config = config("menu.ls = true", &[]);
+        let entries = build_entries(&mut config, &Usage::default()).unwrap();
+        assert_eq!(entries[0].run, Run::Shell(ImStr::from("ls")));
+
+        let mut config = self::config("menu.ls = true\nconfig.shell = false", &[]);
+        let entries = build_entries(&mut config, &Usage::default()).unwrap();
+        assert_eq!(entries[0].run, Run::binary(ImStr::from("ls")));
+    }
+
+    #[test]
+    fn find_by_name_without_tags() {
+        let config = config("config.tag = \"auto\"", &[]);
+        let entries = [entry("hello", "echo hello"), entry("world", "echo world")];
+
+        let found = find_by_name(&config, &entries, "world").unwrap();
+        assert_eq!(found.name.as_str(), "world");
+        assert!(find_by_name(&config, &entries, "missing").is_none());
+
+        let config = self::config("config.tag = \"strict\"", &[]);
+        assert!(find_by_name(&config, &entries, "world").is_none());
+    }
+
+    #[test]
+    fn find_by_name_numbered() {
+        let config = config("[config]\ntag = \"auto\"\nnumbered = true", &[]);
+        let entries = [entry("hello", "echo hello"), entry("42", "echo 42")];
+        let separator = config.numbered.separator();
+
+        let found = find_by_name(&config, &entries, &format!("0{separator}hello")).unwrap();
+        assert_eq!(found.name.as_str(), "hello");
+        let found = find_by_name(&config, &entries, &format!("1{separator}42")).unwrap();
+        assert_eq!(found.name.as_str(), "42");
+    }
+
+    #[test]
+    fn similar_names_near_misses() {
+        let names = ["firefox", "files", "thunderbird"];
+
+        assert_eq!(similar_names("firfox", names.into_iter()), ["firefox"]);
+        assert_eq!(
+            similar_names("Thunderbrid", names.into_iter()),
+            ["thunderbird"]
+        );
+        assert_eq!(
+            similar_names("fire", names.into_iter()),
+            ["files", "firefox"]
+        );
+        assert_eq!(similar_names("thunder", names.into_iter()), ["thunderbird"]);
+        assert!(similar_names("f", names.into_iter()).is_empty());
+        assert!(similar_names("thun", names.into_iter()).is_empty());
+    }
+
+    #[test]
+    fn timed_out_commands_are_killed() {
+        let config = config("", &[]);
+        let mut entry = entry("sleep", "sleep 5");
+        entry.options.timeout = Some(Duration::from_secs(1));
+
+        let start = Instant::now();
+        let err = run(&entry, &config).unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(3));
+        assert!(format!("{err:#}").contains("timeout of 1s ran out"));
+
+        // `config.timeout` only applies to commands that are waited on.
+        let config = self::config("config.timeout = 1", &[]);
+        let sleep = self::entry("sleep", "sleep 5");
+        assert!(spawn_command(&sleep, &config, false).unwrap().is_none());
+
+        let config = self::config("config.timeout = 1\nconfig.wait = true", &[]);
+        let start = Instant::now();
+        assert!(run(&sleep, &config).is_err());
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn waited_commands_run_at_once() {
+        let config = config("config.wait = true", &[]);
+        let commands = [
+            entry("a", "sleep 1"),
+            entry("b", "sleep 1"),
+            entry("c", "sleep 1"),
+        ];
+
+        let start = Instant::now();
+        run_commands(&commands, &config).unwrap();
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_secs(1));
+        assert!(elapsed < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn duplicate_selections_are_dropped() {
+        let entries = [entry("hello", "echo hello"), entry("world", "echo world")];
+        let shown = entries
+            .iter()
+            .enumerate()
+            .collect::<Vec<(usize, &RunEntry)>>();
+        let menu = display_entries(&config("", &[]), &shown, Encoding::Binary);
+        let lines = menu.lines().collect::<Vec<&str>>();
+        let choices = [lines[0], lines[1], lines[0], lines[1], lines[0]].join("\n");
+        let names = |config: &Config| {
+            parse_choices(
+                config,
+                &entries,
+                &HashSet::default(),
+                &choices,
+                Encoding::Binary,
+            )
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect::<Vec<ImStr>>()
+        };
+
+        let config = self::config("config.dedup-selections = true", &[]);
+        assert_eq!(names(&config), ["hello", "world"].map(ImStr::from));
+        let config = self::config("config.dedup-selections = false", &[]);
+        assert_eq!(names(&config).len(), 5);
+    }
+
+    #[test]
+    fn tags_past_the_last_entry_are_skipped() {
+        let config = config("", &[]);
+        let entries = [entry("hello", "echo hello"), entry("world", "echo world")];
+        let mut choices = String::from("hello");
+        Encoding::Binary.push_tag(1, &mut choices);
+        choices.push_str("\nwrong");
+        Encoding::Binary.push_tag(entries.len(), &mut choices);
+
+        let commands = parse_choices(
+            &config,
+            &entries,
+            &HashSet::default(),
+            &choices,
+            Encoding::Binary,
+        );
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].name.as_str(), "world");
+    }
+
+    #[test]
+    fn sanitize_name_keeps_names_on_one_line() {
+        assert_eq!(sanitize_name("two\nlines"), "two lines");
+        assert_eq!(sanitize_name("tab\tand\r\nend"), "tab and  end");
+        assert!(matches!(
+            sanitize_name("plain name"),
+            Cow::Borrowed("plain name")
+        ));
+    }
+
+    #[test]
+    fn render_command_quotes_what_needs_it() {
+        let mut command = Command::new("notify-send");
+        command.args(["hello world", "it's", "--urgency=low", ""]);
+        assert_eq!(
+            render_command((command, None)),
+            r"notify-send 'hello world' 'it'\''s' --urgency=low ''"
+        );
+
+        let command = Command::new("sh");
+        assert_eq!(
+            render_command((command, Some(String::from("echo $HOME\n")))),
+            "printf '%s' 'echo $HOME\n' | sh"
+        );
+    }
+
+    #[test]
+    fn sort_reverse_keeps_groups_in_order() {
+        let toml = "[menu]\n\
+                    b = { run = \"echo b\", group = 1 }\n\
+                    a = { run = \"echo a\", group = 1 }\n\
+                    d = { run = \"echo d\", group = 2 }\n\
+                    c = { run = \"echo c\", group = 2 }";
+        let names = |extra: &str| {
+            let mut config = config(&format!("{extra}\n{toml}"), &[]);
+            build_entries(&mut config, &Usage::default())
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect::<Vec<ImStr>>()
+        };
+
+        // Higher groups come first.
+        assert_eq!(names(""), ["c", "d", "a", "b"].map(ImStr::from));
+        assert_eq!(
+            names("config.sort-reverse = true"),
+            ["d", "c", "b", "a"].map(ImStr::from)
+        );
+    }
+
+    #[test]
+    fn sort_case_sensitive() {
+        let toml = "[menu]\nbeta = \"echo\"\nAlpha = \"echo\"\nalpha = \"echo\"\nGamma = \"echo\"";
+        let names = |extra: &str| {
+            let mut config = config(&format!("{extra}\n{toml}"), &[]);
+            build_entries(&mut config, &Usage::default())
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect::<Vec<ImStr>>()
+        };
+
+        // Names that only differ in case are ordered by their bytes.
+        assert_eq!(
+            names(""),
+            ["Alpha", "alpha", "beta", "Gamma"].map(ImStr::from)
+        );
+        assert_eq!(
+            names("config.sort-case-sensitive = true"),
+            ["Alpha", "Gamma", "alpha", "beta"].map(ImStr::from)
+        );
+    }
+
+    #[test]
+    fn bell_rings_once() {
+        let rung = env::temp_dir().join(format!("dmm-bell-test-{}", process::id()));
+        let config = config(
+            &format!(
+                "config.bell = [\"sh\", \"-c\", \"echo rung >> {}\"]",
+                rung.display()
+            ),
+            &[],
+        );
+        set_bell(&config);
+        ring_bell();
+        ring_bell();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !rung.exists() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        // Give a second ring the chance to show up, if there was one.
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(fs::read_to_string(&rung).unwrap(), "rung\n");
+        fs::remove_file(rung).unwrap();
+    }
+
+    #[test]
+    fn place_default_first_or_preselected() {
+        fn names<'a>(shown: &[(usize, &'a RunEntry)]) -> Vec<&'a str> {
+            shown.iter().map(|(_, entry)| entry.name.as_str()).collect()
+        }
+        let mut entries = [
+            entry("a", "echo a"),
+            entry("b", "echo b"),
+            entry("c", "echo c"),
+        ];
+        entries[0].group = 1;
+
+        let config = config("[config]\ndefault = \"c\"\nbackend = \"dmenu\"", &[]);
+        let mut shown = entries
+            .iter()
+            .enumerate()
+            .collect::<Vec<(usize, &RunEntry)>>();
+        assert_eq!(
+            place_default(&config, &Usage::default(), &mut shown),
+            Some(0)
+        );
+        assert_eq!(names(&shown), ["c", "a", "b"]);
+        assert_eq!(shown[0].0, 2);
+
+        #[cfg(feature = "rofi")]
+        {
+            let config = self::config(
+                "[config]\ndefault = \"c\"\nbackend = \"rofi\"\ngroup-headers = true",
+                &[],
+            );
+            let mut shown = entries
+                .iter()
+                .enumerate()
+                .collect::<Vec<(usize, &RunEntry)>>();
+            // Below both group headers.
+            assert_eq!(
+                place_default(&config, &Usage::default(), &mut shown),
+                Some(4)
+            );
+            assert_eq!(names(&shown), ["a", "b", "c"]);
+        }
+
+        let mut usage = Usage::default();
+        usage.add_run(ImStr::from("b"), 1);
+        let config = self::config("[config]\ndefault = \"last\"\nbackend = \"dmenu\"", &[]);
+        let mut shown = entries
+            .iter()
+            .enumerate()
+            .collect::<Vec<(usize, &RunEntry)>>();
+        assert_eq!(place_default(&config, &usage, &mut shown), Some(0));
+        assert_eq!(names(&shown), ["b", "a", "c"]);
+    }
+
+    #[test]
+    fn untracked_entries_are_not_recorded() {
+        let mut untracked = entry("secret", "echo secret");
+        untracked.options.track = Some(false);
+        let mut custom = entry("echo custom", "echo custom");
+        custom.source = Source::Custom;
+
+        let mut usage = Usage::default();
+        assert!(!record_usage(&mut usage, &[untracked.clone()], 10, 1));
+        assert_eq!(usage.last_run(), None);
+
+        let commands = [entry("tracked", "echo tracked"), untracked, custom];
+        assert!(record_usage(&mut usage, &commands, 10, 2));
+        assert_eq!(usage.record("tracked").count, 1);
+        assert_eq!(usage.record("secret").count, 0);
+        assert_eq!(usage.history, [ImStr::from("echo custom")]);
+    }
+
+    #[test]
+    fn hide_duplicates_with_hidden_entries() {
+        let dir = env::temp_dir().join(format!("dmm-hide-duplicates-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["shown", "hidden", "other"] {
+            fs::write(dir.join(name), "#!/bin/sh\n").unwrap();
+            fs::set_permissions(dir.join(name), fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let entries = |options: &str| {
+            let toml = format!(
+                "config.path = {{ path = [\"{}\"]{options} }}\n\
+                 [menu]\n\
+                 shown = \"echo shown\"\n\
+                 hidden = {{ run = \"echo hidden\", when = {{ env = \"DMM_TEST_UNSET\" }} }}",
+                dir.display()
+            );
+            let mut config = config(&toml, &[]);
+            let mut entries = build_entries(&mut config, &Usage::default())
+                .unwrap()
+                .into_iter()
+                .map(|entry| (entry.name.to_string(), entry.source))
+                .collect::<Vec<(String, Source)>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            entries
+        };
+        let entry = |name: &str, source| (String::from(name), source);
+
+        assert_eq!(
+            entries(""),
+            [
+                entry("hidden", Source::Path),
+                entry("other", Source::Path),
+                entry("shown", Source::Pattern),
+            ]
+        );
+        assert_eq!(
+            entries(", hide-duplicates = true"),
+            [
+                entry("other", Source::Path),
+                entry("shown", Source::Pattern)
+            ]
+        );
+        assert_eq!(
+            entries(", replace = true"),
+            [
+                entry("hidden", Source::Path),
+                entry("other", Source::Path),
+                entry("shown", Source::Path),
+            ]
+        );
+        assert_eq!(
+            entries(", replace = true, hide-duplicates = true"),
+            [entry("other", Source::Path), entry("shown", Source::Path)]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn path_programs_through_the_shell() {
+        let dir = env::temp_dir().join(format!("dmm path shell test {}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("script");
+        // No `#!` line, so only a shell can run it.
+        fs::write(&script, "echo hello\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let script = ImStr::from(script.to_str().unwrap());
+        let build = |path_shell: bool, shell: bool| {
+            let toml = format!(
+                "config.path = {{ path = [\"{}\"], shell = {path_shell} }}\nconfig.shell = {shell}",
+                dir.display()
+            );
+            let mut config = config(&toml, &[]);
+            let mut entries = build_entries(&mut config, &Usage::default()).unwrap();
+            assert_eq!(entries.len(), 1);
+            (entries.remove(0), config)
+        };
+
+        let (bare, config) = build(false, true);
+        assert_eq!(bare.run, Run::binary(script.clone()));
+        assert!(run(&bare, &config).is_err());
+        let (shell, config) = build(true, true);
+        assert_eq!(shell.run, Run::Shell(ImStr::from(shell_quote(&script))));
+        run(&shell, &config).unwrap();
+        // Without a shell to run them in, they stay bare.
+        assert_eq!(build(true, false).0.run, Run::binary(script.clone()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn commands_run_when_the_cache_cant_be_written() {
+        // Nothing can be created inside a file, even with the permissions to do so.
+        let file = env::temp_dir().join(format!("dmm-unwritable-cache-test-{}", process::id()));
+        fs::write(&file, "").unwrap();
+        let marker = env::temp_dir().join(format!("dmm-unwritable-cache-ran-{}", process::id()));
+        let config = config(
+            &format!(
+                "config.cache-dir = \"{}/dmm\"\nconfig.wait = true",
+                file.display()
+            ),
+            &[],
+        );
+        let mut usage = Usage::default();
+        let commands = [entry("touch", &format!("touch {}", marker.display()))];
+
+        record_and_run(&config, &mut usage, &commands).unwrap();
+        // Saving already failed once, so later saves are skipped.
+        assert!(usage.save(&config.cache).is_ok());
+        assert!(marker.exists());
+        fs::remove_file(file).unwrap();
+        fs::remove_file(marker).unwrap();
+    }
+
+    #[test]
+    fn output_is_appended_to_the_log() {
+        let dir = env::temp_dir().join(format!("dmm-log-test-{}", process::id()));
+        let log = dir.join("missing/parents/entry.log");
+        let config = config("", &[]);
+        let mut entry = entry("hello", "echo hello; echo oops >&2");
+        entry.options.log = Some(ImStr::from(log.to_str().unwrap()));
+        entry.options.wait = Some(true);
+
+        run(&entry, &config).unwrap();
+        assert_eq!(fs::read_to_string(&log).unwrap(), "hello\noops\n");
+        run(&entry, &config).unwrap();
+        assert_eq!(
+            fs::read_to_string(&log).unwrap(),
+            "hello\noops\nhello\noops\n"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn labels_come_from_their_command() {
+        let dir = env::temp_dir().join(format!("dmm-label-test-{}", process::id()));
+        let labels = |label: &str| {
+            let config = config(
+                &format!(
+                    "config.cache-dir = \"{}\"\n\
+                     menu.clock = {{ run = \"true\", label = \"{label}\" }}",
+                    dir.display()
+                ),
+                &[],
+            );
+            label_entries(&config.entries, &config.shell, &config.cache)
+        };
+
+        let fixed = labels("echo fixed text; echo second line");
+        assert_eq!(fixed.get("clock").map(ImStr::as_str), Some("fixed text"));
+        // A failing command keeps the label it printed last time.
+        let failed = labels("exit 1");
+        assert_eq!(failed.get("clock").map(ImStr::as_str), Some("fixed text"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn home_paths_are_skipped_without_a_home_directory() {
+        let dir = env::temp_dir().join(format!("dmm-no-home-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("program"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(dir.join("program"), fs::Permissions::from_mode(0o755)).unwrap();
+
+        // `config` loads without a home directory, as if `HOME` were unset.
+        let toml = format!("config.path = [\"~/bin\", \"{}\"]", dir.display());
+        let mut config = config(&toml, &[]);
+        assert!(config.base_dirs.is_none());
+        let entries = build_entries(&mut config, &Usage::default()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name.as_str(), "program");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Runs `entry` waited on and returns the session id and niceness it ran with.
+    fn session_and_niceness(mut entry: RunEntry, config: &Config) -> (i32, i32) {
+        let dir = env::temp_dir().join(format!("dmm-ps-test-{}-{}", process::id(), entry.name));
+        let log = dir.join("ps.log");
+        entry.run = Run::Shell(ImStr::from("ps -o sid=,ni= -p $$"));
+        entry.options.log = Some(ImStr::from(log.to_str().unwrap()));
+        entry.options.wait = Some(true);
+        run(&entry, config).unwrap();
+        let output = fs::read_to_string(&log).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+        let mut fields = output
+            .split_whitespace()
+            .map(|field| field.parse().unwrap());
+        (fields.next().unwrap(), fields.next().unwrap())
+    }
+
+    #[test]
+    fn setsid_starts_a_new_session() {
+        // SAFETY: `getsid` has no preconditions.
+        let own = unsafe { libc::getsid(0) };
+        let (session, _) = session_and_niceness(entry("same", ""), &config("", &[]));
+        assert_eq!(session, own);
+        let (session, _) =
+            session_and_niceness(entry("new", ""), &config("config.setsid = true", &[]));
+        assert_ne!(session, own);
+    }
+
+    #[test]
+    fn commands_run_with_their_niceness() {
+        // SAFETY: `getpriority` has no preconditions.
+        let own = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+        let config = config("", &[]);
+        let niceness = |name: &str, nice: Option<i64>| {
+            let mut entry = entry(name, "");
+            entry.options.nice = nice;
+            session_and_niceness(entry, &config).1
+        };
+        assert_eq!(niceness("default", None), own);
+        assert_eq!(niceness("nicer", Some(10)), 10);
+        assert_eq!(niceness("clamped", Some(100)), 19);
+
+        // Only privileged processes can lower their niceness.
+        // SAFETY: `geteuid` has no preconditions.
+        if unsafe { libc::geteuid() } == 0 {
+            assert_eq!(niceness("meaner", Some(-5)), -5);
+        } else {
+            let mut entry = entry("meaner", "true");
+            entry.options.nice = Some(i64::from(own) - 1);
+            assert!(spawn_command(&entry, &config, false).is_err());
+        }
+    }
+
+    #[test]
+    fn timed_out_probes_are_killed_with_their_children() {
+        let marker = env::temp_dir().join(format!("dmm-probe-test-{}", process::id()));
+        let config = config(
+            &format!(
+                "menu.slow = {{ run = \"true\", if = \"(sleep 2; touch '{}') & wait\" }}\n\
+                 menu.fast = {{ run = \"true\", if = \"true\" }}",
+                marker.display()
+            ),
+            &[],
+        );
+        let entries = probe_entries(config.entries.iter().cloned(), &config.shell);
+        let names = entries.iter().map(Entry::name).collect::<Vec<ImStr>>();
+        assert_eq!(names, [ImStr::from("fast")]);
+
+        thread::sleep(Duration::from_millis(1500));
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn header_lines_are_skipped() {
+        let entries = [entry("hello", "echo hello")];
+        let headers = HashSet::from_iter([String::from("Apps")]);
+        let mut choices = String::from("Apps\nhello");
+        Encoding::Binary.push_tag(0, &mut choices);
+        choices.push_str("\ntyped");
+
+        let config = config("config.custom = true", &[]);
+        let commands = parse_choices(&config, &entries, &headers, &choices, Encoding::Binary);
+        let names = commands
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect::<Vec<ImStr>>();
+        assert_eq!(names, ["hello", "typed"].map(ImStr::from));
+    }
+}
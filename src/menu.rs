@@ -0,0 +1,219 @@
+use std::borrow::Cow;
+use std::io::{self, ErrorKind, Read, Write};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use std::{mem, panic};
+
+use anyhow::{anyhow, Context};
+
+use crate::config::{Backend, Dmenu};
+use crate::log::verbose;
+use crate::style::{bold, style_stderr};
+
+/// Show `items` in the launcher that `dmenu` describes for `backend`, returning the lines it
+/// output, which are usually the selected items. Dismissing the launcher selects nothing.
+///
+/// Only the options of `dmenu` are used, without any of dmm's tags or entries,
+/// so this can be used to show any menu.
+/// Each call runs its own launcher, so it can be called from several threads at once,
+/// though [`Dmenu`] can't be shared between them, and launchers may not like it.
+///
+/// # Errors
+///
+/// Errors if an item contains a newline, since each line is an item,
+/// if the launcher can't be run, or if what it outputs isn't utf-8.
+/// The launcher failing isn't an error, since dmenu exits unsuccessfully when it's dismissed;
+/// what it wrote to stderr is logged with `--verbose`.
+///
+/// ```
+/// use dmm::config::{Backend, Dmenu};
+/// use dmm::imstr::ImStr;
+/// use dmm::menu::run_menu;
+///
+/// // A launcher that always selects the first item.
+/// let dmenu = Dmenu {
+///     template: Some(vec![ImStr::new("head"), ImStr::new("-n"), ImStr::new("1")]),
+///     ..Dmenu::default()
+/// };
+///
+/// assert_eq!(run_menu(&["first", "second"], &dmenu, Backend::Dmenu).unwrap(), ["first"]);
+/// assert!(run_menu(&[], &dmenu, Backend::Dmenu).unwrap().is_empty());
+/// assert!(run_menu(&["two\nlines"], &dmenu, Backend::Dmenu).is_err());
+/// ```
+pub fn run_menu(items: &[&str], dmenu: &Dmenu, backend: Backend) -> anyhow::Result<Vec<String>> {
+    if let Some(item) = items.iter().find(|item| item.contains('\n')) {
+        return Err(anyhow!(
+            "menu item `{}` contains a newline, so it would be shown as more than one item",
+            style_stderr!(bold(), "{}", item.escape_debug())
+        ));
+    }
+    let mut input = String::with_capacity(items.iter().map(|item| item.len() + 1).sum());
+    for item in items {
+        input.push_str(item);
+        input.push('\n');
+    }
+
+    let args = dmenu.args(backend);
+    let (output, _) = run_launcher(&dmenu.argv(backend, &args), input, None)?;
+
+    Ok(output.lines().map(String::from).collect())
+}
+
+/// Run the launcher `argv` with `input` as its stdin, returning its output and exit status.
+///
+/// The input is written while the output is read, so neither side can block on a full pipe
+/// waiting for the other, and a launcher that exits before reading all of it isn't an error.
+/// What the launcher writes to stderr is logged with `--verbose`.
+///
+/// If the launcher is still open after `timeout`, it's terminated, as if it was dismissed.
+/// Only the launcher itself is, so a wrapper script should `exec` it.
+///
+/// ```
+/// use std::borrow::Cow;
+/// use std::time::{Duration, Instant};
+///
+/// use dmm::menu::run_launcher;
+///
+/// let started = Instant::now();
+/// let argv = [Cow::from("sleep"), Cow::from("10")];
+/// let (output, status) =
+///     run_launcher(&argv, String::from("item\n"), Some(Duration::from_millis(100))).unwrap();
+///
+/// assert!(output.is_empty());
+/// assert!(!status.success());
+/// assert!(started.elapsed() < Duration::from_secs(5));
+/// ```
+///
+/// A launcher that writes a lot to stderr doesn't block on it,
+/// and one that closes its input without reading it still has its output returned.
+///
+/// ```
+/// use std::borrow::Cow;
+///
+/// use dmm::menu::run_launcher;
+///
+/// let flood = [
+///     Cow::from("sh"),
+///     Cow::from("-c"),
+///     Cow::from("head -c 1000000 /dev/zero >&2; head -n 1"),
+/// ];
+/// let (output, status) = run_launcher(&flood, String::from("first\nsecond\n"), None).unwrap();
+/// assert_eq!(output, "first\n");
+/// assert!(status.success());
+///
+/// let closed = [Cow::from("sh"), Cow::from("-c"), Cow::from("exec <&-; echo typed")];
+/// let (output, status) = run_launcher(&closed, "item\n".repeat(100_000), None).unwrap();
+/// assert_eq!(output, "typed\n");
+/// assert!(status.success());
+/// ```
+pub fn run_launcher(
+    argv: &[Cow<'_, str>],
+    input: String,
+    timeout: Option<Duration>,
+) -> anyhow::Result<(String, ExitStatus)> {
+    let (name, args) = argv.split_first().context("the dmenu command is empty??")?;
+    let mut launcher = Command::new(name.as_ref())
+        .args(args.iter().map(Cow::as_ref))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context(format!(
+            "failed to run command `{}` (is it installed?)",
+            style_stderr!(bold(), "{name}")
+        ))?;
+    let mut stdin = launcher
+        .stdin
+        .take()
+        .context("failed to establish pipe to dmenu??")?;
+
+    let thread = thread::spawn(move || match stdin.write_all(input.as_bytes()) {
+        // The launcher closed its input before reading the whole menu, likely as it was dismissed.
+        Err(err) if err.kind() == ErrorKind::BrokenPipe => {
+            verbose!("dmenu exited before reading the whole menu");
+            Ok(())
+        }
+        result => result.context("failed to write to dmenu stdin??"),
+    });
+
+    let pid = libc::pid_t::try_from(launcher.id()).expect("unreachable");
+    // The timer is stopped by dropping `stop` once the launcher exits.
+    let (stop, stopped) = mpsc::channel::<()>();
+    let timer = timeout.map(|timeout| {
+        thread::spawn(move || {
+            if stopped.recv_timeout(timeout) == Err(RecvTimeoutError::Timeout) {
+                verbose!("closing the menu, since nothing was selected in time");
+                // SAFETY: `kill` has no memory safety requirements.
+                // The launcher isn't reaped until the timer is stopped, so its pid can't be reused.
+                unsafe { libc::kill(pid, libc::SIGTERM) };
+            }
+        })
+    });
+
+    let mut stderr = launcher
+        .stderr
+        .take()
+        .context("failed to establish pipe to dmenu??")?;
+    let stderr = thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = stderr.read_to_end(&mut output);
+        output
+    });
+    let mut stdout = Vec::new();
+    launcher
+        .stdout
+        .take()
+        .context("failed to establish pipe to dmenu??")?
+        .read_to_end(&mut stdout)
+        .context("failed to read dmenu stdout??")?;
+
+    // Wait for the launcher to exit without reaping it, then stop the timer before reaping it.
+    // SAFETY: `siginfo_t` is a plain C struct, for which all zeroes is a valid value.
+    let mut info = unsafe { mem::zeroed::<libc::siginfo_t>() };
+    loop {
+        // SAFETY: `info` is valid for writes for the duration of the call.
+        let result = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                libc::id_t::try_from(pid).expect("unreachable"),
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT,
+            )
+        };
+        if result == 0 {
+            break;
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != ErrorKind::Interrupted {
+            return Err(err).context("failed to wait for dmenu??");
+        }
+    }
+    drop(stop);
+    if let Some(timer) = timer {
+        if let Err(err) = timer.join() {
+            panic::resume_unwind(err);
+        }
+    }
+    let status = launcher.wait().context("failed to wait for dmenu??")?;
+    let stderr = match stderr.join() {
+        Ok(stderr) => stderr,
+        Err(err) => panic::resume_unwind(err),
+    };
+
+    match thread.join() {
+        Ok(result) => result?,
+        Err(err) => panic::resume_unwind(err),
+    }
+
+    for line in String::from_utf8_lossy(&stderr).lines() {
+        verbose!("{name}: {line}");
+    }
+
+    let stdout = String::from_utf8(stdout).context(format!(
+        "`{}` output invalid utf-8",
+        style_stderr!(bold(), "{name}")
+    ))?;
+    Ok((stdout, status))
+}
@@ -1,28 +1,60 @@
-use std::io;
+use std::{env, io};
 
 use is_terminal::IsTerminal;
 use termcolor::{ColorChoice, ColorSpec, StandardStream, WriteColor};
 
+use crate::log;
+
 pub fn bold() -> ColorSpec {
     let mut style = ColorSpec::new();
     style.set_bold(true);
     style
 }
 
-pub fn stderr_color_choice() -> ColorChoice {
-    if io::stderr().is_terminal() {
-        ColorChoice::Auto
+/// Whether color is forced on or off by the environment, overriding terminal detection.
+///
+/// A non-empty `NO_COLOR` disables color, taking precedence over `FORCE_COLOR` or `CLICOLOR_FORCE`,
+/// either of which enables it unless set to `0` (or `false` for `FORCE_COLOR`).
+pub fn env_color_override() -> Option<bool> {
+    let is_set = |name| env::var_os(name).filter(|value| !value.is_empty());
+
+    if is_set("NO_COLOR").is_some() {
+        Some(false)
+    } else if is_set("FORCE_COLOR").is_some_and(|value| value != "0" && value != "false")
+        || is_set("CLICOLOR_FORCE").is_some_and(|value| value != "0")
+    {
+        Some(true)
     } else {
-        ColorChoice::Never
+        None
+    }
+}
+
+pub fn stderr_color_choice() -> ColorChoice {
+    // Json output is read by programs, which don't want color codes in its strings.
+    if log::is_json() {
+        return ColorChoice::Never;
+    }
+    match env_color_override() {
+        Some(true) => ColorChoice::AlwaysAnsi,
+        Some(false) => ColorChoice::Never,
+        None if io::stderr().is_terminal() => ColorChoice::Auto,
+        None => ColorChoice::Never,
     }
 }
 
 pub fn stderr_color_enabled() -> bool {
-    io::stderr().is_terminal() && StandardStream::stderr(ColorChoice::Auto).supports_color()
+    if log::is_json() {
+        return false;
+    }
+    env_color_override().unwrap_or_else(|| {
+        io::stderr().is_terminal() && StandardStream::stderr(ColorChoice::Auto).supports_color()
+    })
 }
 
 pub fn stdout_color_enabled() -> bool {
-    io::stdout().is_terminal() && StandardStream::stdout(ColorChoice::Auto).supports_color()
+    env_color_override().unwrap_or_else(|| {
+        io::stdout().is_terminal() && StandardStream::stdout(ColorChoice::Auto).supports_color()
+    })
 }
 
 #[macro_export]
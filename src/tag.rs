@@ -7,10 +7,15 @@ const ONE: char = '\u{200d}';
 /// `Zero width non joiner` character.
 const SEP: char = '\u{200c}';
 
+/// The longest tag any encoding writes to [`BUF`]: every bit of a [`usize`] in binary,
+/// between two separators.
+const BUF_CAPACITY: usize = usize::BITS as usize + 2 * SEP.len_utf8();
+
 thread_local! {
     /// Persistant [`String`] buffer to minimize allocations.
-    static BUF: RefCell<String> =
-        String::with_capacity(usize::BITS.try_into().expect("unreachable")).into();
+    ///
+    /// It starts large enough for any tag, so it never has to grow while tagging a menu.
+    static BUF: RefCell<String> = String::with_capacity(BUF_CAPACITY).into();
 }
 
 macro_rules! with_buf {
@@ -23,6 +28,27 @@ macro_rules! with_buf {
     }
 }
 
+/// Remove all characters used by zero-width tags from `string`.
+///
+/// ```
+/// use dmm::tag::{strip_tags, Binary, Tag};
+///
+/// let mut line = String::from("firefox");
+/// Binary::push_tag(5, &mut line);
+/// assert_eq!(strip_tags(&line), "firefox");
+/// ```
+pub fn strip_tags(string: &str) -> String {
+    string
+        .chars()
+        .filter(|c| ![ZERO, ONE, SEP].contains(c))
+        .collect()
+}
+
+/// Whether `string` contains any of the characters used by zero-width tags.
+pub fn contains_tag_chars(string: &str) -> bool {
+    string.contains([ZERO, ONE, SEP])
+}
+
 /// Convert a number to a string tag, and convert that tag back to its numeric value.
 pub trait Tag {
     /// Convert a number to a tag that is pushed onto the provided [`String`].
@@ -31,6 +57,20 @@ pub trait Tag {
     fn convert_tag(tag: &str) -> Option<usize>;
 
     /// Find the first tag, returning it and any part of the string after the tag.
+    ///
+    /// Whatever comes before or after the tag, like a numbered entry's separator, is ignored.
+    ///
+    /// ```
+    /// use dmm::tag::{Decimal, Tag};
+    ///
+    /// for separator in [": ", "\t", " -- ", "\t→ ", "1) "] {
+    ///     let mut line = String::new();
+    ///     Decimal::push_tag(42, &mut line);
+    ///     line.push_str(separator);
+    ///     line.push_str("firefox");
+    ///     assert_eq!(Decimal::pop_tag(&line), Some(42));
+    /// }
+    /// ```
     fn pop_tag(string: &str) -> Option<usize> {
         string.find(SEP).and_then(|first_sep| {
             let start = first_sep + SEP.len_utf8();
@@ -97,3 +137,36 @@ impl Tag for Decimal {
         tag.parse().ok()
     }
 }
+
+/// A tag encoding selected at runtime, dispatching to the matching [`Tag`] implementation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Binary,
+    Decimal,
+}
+
+impl Encoding {
+    /// See [`Tag::push_tag`].
+    pub fn push_tag(self, num: usize, out: &mut String) {
+        match self {
+            Self::Binary => Binary::push_tag(num, out),
+            Self::Decimal => Decimal::push_tag(num, out),
+        }
+    }
+
+    /// See [`Tag::convert_tag`].
+    pub fn convert_tag(self, tag: &str) -> Option<usize> {
+        match self {
+            Self::Binary => Binary::convert_tag(tag),
+            Self::Decimal => Decimal::convert_tag(tag),
+        }
+    }
+
+    /// See [`Tag::pop_tag`].
+    pub fn pop_tag(self, string: &str) -> Option<usize> {
+        match self {
+            Self::Binary => Binary::pop_tag(string),
+            Self::Decimal => Decimal::pop_tag(string),
+        }
+    }
+}
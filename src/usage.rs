@@ -0,0 +1,409 @@
+use std::cmp::Reverse;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ahash::HashMap;
+use anyhow::{anyhow, Context};
+use toml::{map::Map, Value};
+
+use crate::cache::Cache;
+use crate::imstr::ImStr;
+use crate::log::verbose;
+
+/// The name of the cache file usage is stored in.
+const FILE_NAME: &str = "usage.toml";
+/// The version of the json format used to export usage.
+const JSON_VERSION: u64 = 1;
+
+/// How often and how recently an entry was run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Record {
+    pub count: u64,
+    /// When the entry was last run, in seconds since the unix epoch.
+    pub last_used: u64,
+}
+
+impl Record {
+    /// Combine how often and how recently the entry was run into a single score.
+    ///
+    /// The score is the run count decayed exponentially by the time since the entry was last run,
+    /// `count * 0.5^(age / half_life)`, so it halves every `half_life`.
+    /// An entry run 10 times, last one half life ago, scores the same as one run 5 times just now.
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use dmm::usage::Record;
+    ///
+    /// let day = 24 * 60 * 60;
+    /// let week = Duration::from_secs(7 * day);
+    /// let now = 100 * day;
+    ///
+    /// let record = Record { count: 10, last_used: now };
+    /// assert_eq!(record.frecency(now, week), 10.0);
+    ///
+    /// let record = Record { count: 10, last_used: now - 7 * day };
+    /// assert_eq!(record.frecency(now, week), 5.0);
+    ///
+    /// let record = Record { count: 8, last_used: now - 21 * day };
+    /// assert_eq!(record.frecency(now, week), 1.0);
+    ///
+    /// assert_eq!(Record::default().frecency(now, week), 0.0);
+    /// ```
+    pub fn frecency(&self, now: u64, half_life: Duration) -> f64 {
+        let age = now.saturating_sub(self.last_used) as f64;
+        let half_lives = age / half_life.as_secs_f64().max(1.0);
+
+        self.count as f64 * 0.5_f64.powf(half_lives)
+    }
+}
+
+/// The usage of each entry, and the history of custom commands, kept in the cache between runs.
+#[derive(Debug, Default, Clone)]
+pub struct Usage {
+    pub records: HashMap<ImStr, Record>,
+    /// Custom commands that were run, newest first, without duplicates.
+    pub history: Vec<ImStr>,
+}
+
+impl Usage {
+    /// Load usage from `cache`, starting fresh if it's missing or invalid.
+    pub fn load(cache: &Cache) -> Self {
+        let Some(usage) = cache.read(FILE_NAME) else {
+            return Self::default();
+        };
+
+        usage
+            .parse::<Value>()
+            .map_err(anyhow::Error::from)
+            .and_then(|usage| Self::try_from(&usage))
+            .unwrap_or_else(|err| {
+                verbose!("ignoring invalid usage cache: {err:#}");
+                Self::default()
+            })
+    }
+
+    pub fn save(&self, cache: &Cache) -> anyhow::Result<()> {
+        let usage = toml::to_string(&self.to_value()).context("failed to serialize usage??")?;
+        cache.write(FILE_NAME, &usage)
+    }
+
+    /// Remove the run counts, the history, or both from `cache`, returning whether anything was.
+    ///
+    /// What isn't removed is kept; the cache file is deleted once nothing is left in it.
+    ///
+    /// ```
+    /// use dmm::cache::Cache;
+    /// use dmm::imstr::ImStr;
+    /// use dmm::usage::Usage;
+    ///
+    /// let dir = std::env::temp_dir().join(format!("dmm-usage-clear-doc-{}", std::process::id()));
+    /// let cache = Cache::new(Some(dir.clone()));
+    /// let mut usage = Usage::default();
+    /// usage.add_run(ImStr::from("firefox"), 100);
+    /// usage.add_history(ImStr::from("echo hi"), 10);
+    /// usage.save(&cache).unwrap();
+    ///
+    /// assert!(Usage::clear(&cache, true, false).unwrap());
+    /// let usage = Usage::load(&cache);
+    /// assert!(usage.records.is_empty());
+    /// assert_eq!(usage.history, [ImStr::new("echo hi")]);
+    ///
+    /// assert!(!Usage::clear(&cache, true, false).unwrap());
+    /// assert!(Usage::clear(&cache, false, true).unwrap());
+    /// assert!(!dir.join("usage.toml").exists());
+    /// std::fs::remove_dir_all(dir).unwrap();
+    /// ```
+    pub fn clear(cache: &Cache, records: bool, history: bool) -> anyhow::Result<bool> {
+        let mut usage = Self::load(cache);
+        let cleared =
+            (records && !usage.records.is_empty()) || (history && !usage.history.is_empty());
+        if records {
+            usage.records.clear();
+        }
+        if history {
+            usage.history.clear();
+        }
+
+        if usage.records.is_empty() && usage.history.is_empty() {
+            cache.remove(FILE_NAME)
+        } else {
+            if cleared {
+                usage.save(cache)?;
+            }
+            Ok(cleared)
+        }
+    }
+
+    pub fn record(&self, name: &str) -> Record {
+        self.records.get(name).copied().unwrap_or_default()
+    }
+
+    /// The name of the entry that was run most recently, if any were.
+    ///
+    /// Entries run in the same second are told apart by name, so the result is always the same.
+    ///
+    /// ```
+    /// use dmm::usage::Usage;
+    ///
+    /// let mut usage = Usage::default();
+    /// assert_eq!(usage.last_run(), None);
+    ///
+    /// usage.add_run("firefox".into(), 100);
+    /// usage.add_run("htop".into(), 200);
+    /// usage.add_run("firefox".into(), 150);
+    /// assert_eq!(usage.last_run().map(|name| name.as_str()), Some("htop"));
+    /// ```
+    pub fn last_run(&self) -> Option<&ImStr> {
+        self.records
+            .iter()
+            .max_by_key(|(name, record)| (record.last_used, Reverse(*name)))
+            .map(|(name, _)| name)
+    }
+
+    /// Count a run of the entry `name` at `now`, in seconds since the unix epoch.
+    pub fn add_run(&mut self, name: ImStr, now: u64) {
+        let record = self.records.entry(name).or_default();
+        record.count += 1;
+        record.last_used = record.last_used.max(now);
+    }
+
+    /// Add `command` to the top of the history, removing any older copy of it,
+    /// then drop the oldest commands so at most `max` remain.
+    ///
+    /// ```
+    /// use dmm::imstr::ImStr;
+    /// use dmm::usage::Usage;
+    ///
+    /// let mut usage = Usage::default();
+    /// for command in ["a", "b", "c", "a"] {
+    ///     usage.add_history(ImStr::from(command), 3);
+    /// }
+    /// assert_eq!(usage.history, ["a", "c", "b"].map(ImStr::new));
+    ///
+    /// usage.add_history(ImStr::from("d"), 3);
+    /// assert_eq!(usage.history, ["d", "a", "c"].map(ImStr::new));
+    ///
+    /// usage.add_history(ImStr::from("e"), 0);
+    /// assert!(usage.history.is_empty());
+    /// ```
+    pub fn add_history(&mut self, command: ImStr, max: usize) {
+        self.history.retain(|old| *old != command);
+        self.history.insert(0, command);
+        self.history.truncate(max);
+    }
+
+    /// Merge `other` into this usage, as when importing it from another machine.
+    ///
+    /// Run counts are summed and the latest last run is kept.
+    /// Commands only in the history of `other` are added after this history.
+    pub fn merge(&mut self, other: Self, history_max: usize) {
+        for (name, other) in other.records {
+            let record = self.records.entry(name).or_default();
+            record.count = record.count.saturating_add(other.count);
+            record.last_used = record.last_used.max(other.last_used);
+        }
+
+        for command in other.history {
+            if !self.history.contains(&command) {
+                self.history.push(command);
+            }
+        }
+        self.history.truncate(history_max);
+    }
+
+    /// Convert to json for exporting, in the format read by [`Self::from_json`].
+    ///
+    /// ```json
+    /// {
+    ///   "version": 1,
+    ///   "entries": { "firefox": { "count": 12, "last_used": 1700000000 } },
+    ///   "history": ["echo newest", "echo oldest"]
+    /// }
+    /// ```
+    ///
+    /// `last_used` is in seconds since the unix epoch.
+    pub fn to_json(&self) -> serde_json::Value {
+        let entries = self
+            .records
+            .iter()
+            .map(|(name, record)| {
+                let record = serde_json::json!({
+                    "count": record.count,
+                    "last_used": record.last_used,
+                });
+                (name.to_string(), record)
+            })
+            .collect::<serde_json::Map<String, serde_json::Value>>();
+
+        serde_json::json!({
+            "version": JSON_VERSION,
+            "entries": entries,
+            "history": self.history.iter().map(ImStr::as_str).collect::<Vec<&str>>(),
+        })
+    }
+
+    /// Read usage exported by [`Self::to_json`].
+    pub fn from_json(json: &serde_json::Value) -> anyhow::Result<Self> {
+        let version = json
+            .get("version")
+            .and_then(serde_json::Value::as_u64)
+            .context("`version` must be a positive integer")?;
+        if version != JSON_VERSION {
+            return Err(anyhow!(
+                "`version` is {version}, but only version {JSON_VERSION} is supported"
+            ));
+        }
+
+        let records = json
+            .get("entries")
+            .and_then(serde_json::Value::as_object)
+            .context("`entries` must be an object")?
+            .iter()
+            .map(|(name, record)| {
+                let field = |field: &str| {
+                    record
+                        .get(field)
+                        .and_then(serde_json::Value::as_u64)
+                        .context(format!(
+                            "`entries.{name}.{field}` must be a positive integer"
+                        ))
+                };
+                let record = Record {
+                    count: field("count")?,
+                    last_used: field("last_used")?,
+                };
+                Ok((ImStr::from(name.as_str()), record))
+            })
+            .collect::<anyhow::Result<HashMap<ImStr, Record>>>()?;
+
+        let history = json
+            .get("history")
+            .and_then(serde_json::Value::as_array)
+            .context("`history` must be an array")?
+            .iter()
+            .map(|command| {
+                command
+                    .as_str()
+                    .map(ImStr::from)
+                    .context("`history` must only contain strings")
+            })
+            .collect::<anyhow::Result<Vec<ImStr>>>()?;
+
+        Ok(Self { records, history })
+    }
+
+    fn to_value(&self) -> Value {
+        // Sorted by name, so the file only changes where the usage did.
+        let mut records = self.records.iter().collect::<Vec<(&ImStr, &Record)>>();
+        records.sort_unstable_by_key(|&(name, _)| name);
+        let entries = records
+            .into_iter()
+            .map(|(name, record)| {
+                let record = Map::from_iter([
+                    (
+                        String::from("count"),
+                        Value::Integer(record.count.try_into().unwrap_or(i64::MAX)),
+                    ),
+                    (
+                        String::from("last-used"),
+                        Value::Integer(record.last_used.try_into().unwrap_or(i64::MAX)),
+                    ),
+                ]);
+                (name.to_string(), Value::Table(record))
+            })
+            .collect::<Map<String, Value>>();
+
+        let history = self
+            .history
+            .iter()
+            .map(|command| Value::String(command.to_string()))
+            .collect();
+
+        Value::Table(Map::from_iter([
+            (String::from("entries"), Value::Table(entries)),
+            (String::from("history"), Value::Array(history)),
+        ]))
+    }
+}
+
+impl TryFrom<&Value> for Usage {
+    type Error = anyhow::Error;
+    fn try_from(usage: &Value) -> anyhow::Result<Self> {
+        let empty = Map::new();
+        let entries = match usage.get("entries") {
+            Some(Value::Table(entries)) => entries,
+            Some(_) => return Err(anyhow!("`entries` must be a table")),
+            None => &empty,
+        };
+
+        let records = entries
+            .iter()
+            .map(|(name, record)| {
+                let field = |field: &str| {
+                    record
+                        .get(field)
+                        .and_then(Value::as_integer)
+                        .and_then(|value| u64::try_from(value).ok())
+                        .context(format!(
+                            "`entries.{name}.{field}` must be a positive integer"
+                        ))
+                };
+                let record = Record {
+                    count: field("count")?,
+                    last_used: field("last-used")?,
+                };
+                Ok((ImStr::from(name), record))
+            })
+            .collect::<anyhow::Result<HashMap<ImStr, Record>>>()?;
+
+        let history = match usage.get("history") {
+            Some(Value::Array(history)) => history
+                .iter()
+                .map(|command| {
+                    command
+                        .as_str()
+                        .map(ImStr::from)
+                        .context("`history` must only contain strings")
+                })
+                .collect::<anyhow::Result<Vec<ImStr>>>()?,
+            Some(_) => return Err(anyhow!("`history` must be an array")),
+            None => Vec::new(),
+        };
+
+        Ok(Self { records, history })
+    }
+}
+
+/// The current time in seconds since the unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_records_are_sorted() {
+        let mut usage = Usage::default();
+        for name in [
+            "zsh", "apt", "vim", "htop", "firefox", "tar", "bash", "nano",
+        ] {
+            usage.add_run(ImStr::from(name), 100);
+        }
+
+        let Value::Table(value) = usage.to_value() else {
+            panic!("usage is saved as a table");
+        };
+        let names = value["entries"]
+            .as_table()
+            .unwrap()
+            .keys()
+            .collect::<Vec<&String>>();
+        let mut sorted = names.clone();
+        sorted.sort_unstable();
+        assert_eq!(names, sorted);
+    }
+}